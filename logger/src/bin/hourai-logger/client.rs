@@ -1,8 +1,11 @@
-use hourai::{prelude::*, init, db, cache::{InMemoryCache, ResourceType}};
+use hourai::{
+    prelude::*, init, cache::{InMemoryCache, ResourceType},
+    db, proto::guild_configs::LoggingConfig
+};
 use twilight_model::{
     channel::Message,
     gateway::payload::*,
-    guild::Permissions,
+    guild::{GuildStatus, Permissions},
     guild::member::Member,
     user::User,
 };
@@ -43,6 +46,16 @@ const CACHED_RESOURCES: ResourceType =
         ResourceType::PRESENCE.bits() |
         ResourceType::USER_CURRENT.bits());
 
+/// The maximum number of characters of message content stored in Redis. Only the start of a
+/// message is needed to log its deletion or edit.
+const MAX_CACHED_CONTENT_LENGTH: Option<usize> = Some(2000);
+
+/// How long guild configs read from Redis are memoized for.
+const CONFIG_MEMO_TTL: Duration = Duration::from_secs(300);
+
+/// The maximum number of guild configs fetched from Redis at once when a shard becomes ready.
+const CONFIG_WARM_CONCURRENCY: usize = 16;
+
 pub async fn run(initializer: init::Initializer) {
     Client::new(initializer).await.run().await;
 }
@@ -54,6 +67,8 @@ struct Client {
     pub cache: InMemoryCache,
     pub sql: sqlx::PgPool,
     pub redis: db::RedisPool,
+    pub redis_client: redis::Client,
+    pub guild_configs: db::GuildConfigMemo,
 }
 
 impl Client {
@@ -75,6 +90,8 @@ impl Client {
                 .build(),
             sql: initializer.sql().await,
             redis: initializer.redis().await,
+            redis_client: initializer.redis_client(),
+            guild_configs: db::GuildConfigMemo::new(CONFIG_MEMO_TTL),
         }
     }
 
@@ -89,6 +106,7 @@ impl Client {
 
         tokio::spawn(self.clone().log_bans());
         tokio::spawn(self.clone().flush_online());
+        tokio::spawn(self.clone().listen_for_config_invalidations());
 
         let mut events = self.gateway.some_events(BOT_EVENTS);
         while let Some((shard_id, evt)) = events.next().await {
@@ -133,6 +151,16 @@ impl Client {
         }
     }
 
+    async fn listen_for_config_invalidations(self) {
+        loop {
+            let result = self.guild_configs.listen_for_invalidations(&self.redis_client).await;
+            if let Err(err) = result {
+                error!("Error while listening for config invalidations: {:?}", err);
+            }
+            tokio::time::sleep(Duration::from_secs(5u64)).await;
+        }
+    }
+
     #[inline(always)]
     pub fn total_shards(&self) -> u64 {
         let shards = self.gateway.config().shard_config().shard()[1];
@@ -178,7 +206,7 @@ impl Client {
     async fn consume_event(self, shard_id: u64, event: Event) -> () {
         let kind = event.kind();
         let result = match event {
-            Event::Ready(_) => self.on_shard_ready(shard_id).await,
+            Event::Ready(evt) => self.on_shard_ready(shard_id, *evt).await,
             Event::BanAdd(evt) => self.on_ban_add(evt).await,
             Event::BanRemove(evt) => self.on_ban_remove(evt).await,
             Event::GuildCreate(evt) => self.on_guild_create(*evt).await,
@@ -212,7 +240,16 @@ impl Client {
         }
     }
 
-    async fn on_shard_ready(self, shard_id: u64) -> Result<()> {
+    async fn on_shard_ready(self, shard_id: u64, evt: Ready) -> Result<()> {
+        let guild_ids = evt.guilds.iter().map(|status| match status {
+            GuildStatus::Online(guild) => guild.id,
+            GuildStatus::Offline(guild) => guild.id,
+        });
+        let memoized = self.guild_configs
+            .warm::<LoggingConfig, _, _>(&self.redis, guild_ids, CONFIG_WARM_CONCURRENCY)
+            .await;
+        debug!("Loaded logging configs for {} guilds on shard {}", memoized, shard_id);
+
         db::Ban::clear_shard(shard_id, self.total_shards())
             .execute(&self.sql)
             .await?;
@@ -259,7 +296,14 @@ impl Client {
     }
 
     async fn on_message_create(mut self, evt: Message) -> Result<()> {
-        db::CachedMessage::new(evt).flush()
+        if let Some(guild_id) = evt.guild_id {
+            let memo = &self.guild_configs;
+            if !db::should_cache_guild_messages(memo, &mut self.redis, guild_id).await {
+                return Ok(());
+            }
+        }
+
+        db::CachedMessage::new(evt, MAX_CACHED_CONTENT_LENGTH).flush()
                           .query_async(&mut self.redis).await?;
         Ok(())
     }
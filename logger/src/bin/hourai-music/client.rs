@@ -1,14 +1,24 @@
 use anyhow::bail;
-use crate::{config, prelude::*, player::{Player, PlayerManager}, track::Track};
-use hourai::{init, commands, cache::{InMemoryCache, ResourceType}};
-use twilight_model::{channel::Message, id::ChannelId};
+use crate::{
+    config, prelude::*, player::{default_volume, Player, PlayerManager, MAX_VOLUME},
+    track::{self, Track},
+};
+use hourai::{
+    init, commands, cache::{InMemoryCache, ResourceType},
+    db::{self, Cacheable, CompressionLevel, RedisPool}, proto::guild_configs::MusicConfig
+};
+use twilight_model::{
+    channel::{Message, embed::{Embed, EmbedThumbnail}},
+    id::ChannelId,
+};
+use twilight_lavalink::model::{SlimVoiceServerUpdate, VoiceUpdate};
 use twilight_lavalink::{Lavalink, http::LoadType};
 use twilight_command_parser::{Parser, CommandParserConfig, Command};
 use twilight_gateway::{
     Intents, Event, EventTypeFlags,
     cluster::*,
 };
-use std::{convert::TryFrom, str::FromStr};
+use std::{str::FromStr, time::Instant};
 use hyper::{
     Body, Request,
     service::Service,
@@ -26,6 +36,8 @@ const BOT_INTENTS: Intents = Intents::from_bits_truncate(
 const BOT_EVENTS : EventTypeFlags =
     EventTypeFlags::from_bits_truncate(
         EventTypeFlags::READY.bits() |
+        EventTypeFlags::MESSAGE_CREATE.bits() |
+        EventTypeFlags::VOICE_SERVER_UPDATE.bits() |
         EventTypeFlags::VOICE_STATE_UPDATE.bits() |
         EventTypeFlags::GUILD_DELETE.bits());
 
@@ -34,23 +46,16 @@ const CACHED_RESOURCES: ResourceType =
         ResourceType::VOICE_STATE.bits() |
         ResourceType::USER_CURRENT.bits());
 
+/// How long a player may sit idle before it leaves the voice channel.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How many upcoming tracks are listed by the queue command.
+const QUEUE_DISPLAY_LIMIT: usize = 10;
+
 pub async fn run(initializer: init::Initializer) {
     Client::new(initializer).await.run().await;
 }
 
-fn format_duration(duration: Duration) -> String {
-    let mut secs = duration.as_secs();
-    let hours = secs / 3600;
-    secs -= hours * 3600;
-    let minutes = secs / 60;
-    secs -= secs * 60;
-    if hours > 0 {
-        format!("{:02}:{:02}:{:02}", hours, minutes, secs)
-    } else {
-        format!("{:02}:{:02}", minutes, secs)
-    }
-}
-
 #[derive(Clone)]
 pub struct Client<'a> {
     pub http_client: twilight_http::Client,
@@ -59,6 +64,7 @@ pub struct Client<'a> {
     pub cache: InMemoryCache,
     pub lavalink: twilight_lavalink::Lavalink,
     pub players: Arc<PlayerManager>,
+    pub redis: RedisPool,
     resolver: GaiResolver,
     parser: Parser<'a>
 }
@@ -73,13 +79,16 @@ impl Client<'static> {
             parser.add_prefix(config.command_prefix.clone());
             parser.add_command("play", false);
             parser.add_command("pause", false);
+            parser.add_command("replay", false);
             parser.add_command("stop", false);
             parser.add_command("shuffle", false);
             parser.add_command("skip", false);
             parser.add_command("forceskip", false);
+            parser.add_command("skipto", false);
             parser.add_command("remove", false);
             parser.add_command("volume", false);
             parser.add_command("removeall", false);
+            parser.add_command("dedupe", false);
             parser.add_command("nowplaying", false);
             parser.add_command("np", false);
             parser.add_command("queue", false);
@@ -105,6 +114,7 @@ impl Client<'static> {
                 .resource_types(CACHED_RESOURCES)
                 .build(),
             players: Arc::new(PlayerManager::new()),
+            redis: initializer.redis().await,
             resolver: GaiResolver::new(),
             parser: parser
         };
@@ -122,6 +132,8 @@ impl Client<'static> {
         self.gateway.up().await;
         info!("Client started.");
 
+        tokio::spawn(self.clone().disconnect_idle_players());
+
         let mut events = self.gateway.some_events(BOT_EVENTS);
         while let Some((_, evt)) = events.next().await {
             self.cache.update(&evt);
@@ -147,13 +159,13 @@ impl Client<'static> {
         &mut self,
         uri: impl AsRef<str>,
         password: impl Into<String>
-    ) -> Result<LavalinkEventStream> {
+    ) -> Result<(Node, LavalinkEventStream)> {
         let name = Name::from_str(uri.as_ref()).unwrap();
         let pass = password.into();
         for address in self.resolver.call(name).await? {
             debug!("Trying to connect to a Lavalink node at: {} ", address);
             match self.lavalink.add(address, pass.as_str()).await  {
-                Ok((_, rx)) => return Ok(rx),
+                Ok(connection) => return Ok(connection),
                 Err(err) => debug!("Failed to connect to {}: {:?}", address, err)
             }
         }
@@ -163,10 +175,11 @@ impl Client<'static> {
     async fn run_node(mut self, config: config::MusicNode) {
         let name = format!("https://{}:{}", config.host, config.port);
         info!("Starting listener for node {}.", name.as_str());
+        let mut reconnecting = false;
         loop {
             let connect = self.connect_node(name.as_str(), config.password.as_str());
-            let mut rx: LavalinkEventStream = match connect.await {
-                Ok(rx) => rx,
+            let (node, mut rx) = match connect.await {
+                Ok(connection) => connection,
                 Err(err) => {
                     error!("Error connecting to node {}: {:?}", name.as_str(), err);
                     debug!("Retrying connection to {} in 5 seconds.", name.as_str());
@@ -176,6 +189,10 @@ impl Client<'static> {
             };
 
             info!("Connected to node to {}.", name.as_str());
+            if reconnecting {
+                self.failover_players(&node);
+            }
+            reconnecting = true;
             while let Some(event) = rx.next().await {
                 tokio::spawn(self.clone().handle_lavalink_event(event));
             }
@@ -183,6 +200,66 @@ impl Client<'static> {
         }
     }
 
+    /// Moves the players left behind by a Lavalink node that went down onto the node once it has
+    /// reconnected, resuming their tracks where they left off.
+    fn failover_players(&self, node: &Node) {
+        let address = node.config().address;
+        for player in self.players.players() {
+            let guild_id = player.guild_id();
+            let on_node = self.lavalink
+                .players()
+                .get(&guild_id)
+                .map(|kv| kv.value().node().config().address == address)
+                .unwrap_or(false);
+            if !on_node {
+                continue;
+            }
+            let voice_update = match self.voice_update(guild_id) {
+                Some(voice_update) => voice_update,
+                None => {
+                    debug!("No voice connection to fail over in guild {}", guild_id);
+                    continue;
+                },
+            };
+            match player.failover(node.clone(), voice_update) {
+                Ok(Some(track)) => {
+                    info!("Resumed `{}` in guild {} after failover", track, guild_id);
+                },
+                Ok(None) => {},
+                Err(err) => error!("Error while failing over player in guild {}: {:?}",
+                                   guild_id, err),
+            }
+        }
+    }
+
+    /// Builds the payload a Lavalink node needs to join the bot's voice connection in a guild.
+    fn voice_update(&self, guild_id: GuildId) -> Option<VoiceUpdate> {
+        let session_id = self.cache.voice_session_id(guild_id, self.user_id())?;
+        let (endpoint, token) = self.cache.voice_server(guild_id)?;
+        Some(VoiceUpdate::from((guild_id, session_id, SlimVoiceServerUpdate {
+            endpoint: Some(endpoint),
+            guild_id: Some(guild_id),
+            token,
+        })))
+    }
+
+    async fn disconnect_idle_players(self) {
+        loop {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            let now = Instant::now();
+            for player in self.players.players() {
+                player.refresh_idle(&self.cache, now);
+                if !player.should_disconnect(now, IDLE_TIMEOUT) {
+                    continue;
+                }
+                info!("Disconnecting idle player in guild {}", player.guild_id());
+                if let Err(err) = player.disconnect().await {
+                    error!("Error while disconnecting idle player: {:?}", err);
+                }
+            }
+        }
+    }
+
     async fn consume_event(self, event: Event) -> () {
         let kind = event.kind();
         let result = match event {
@@ -193,6 +270,7 @@ impl Client<'static> {
                 }
                 Ok(())
             },
+            Event::VoiceServerUpdate(_) => Ok(()),
             Event::VoiceStateUpdate(_) => Ok(()),
             _ => {
                 error!("Unexpected event type: {:?}", event);
@@ -260,10 +338,10 @@ impl Client<'static> {
         let bot = self.cache.voice_state(guild_id, self.user_id());
         if user.is_none() {
             bail!(CommandError::FailedPrecondition(
-                  "You must be in a voice channel to play music."));
+                  "You must be in a voice channel to play music.".into()));
         } else if bot.is_some() && user != bot {
             bail!(CommandError::FailedPrecondition(
-                  "You must be in the same voice channel to play music."));
+                  "You must be in the same voice channel to play music.".into()));
         }
 
         Ok(user)
@@ -275,7 +353,7 @@ impl Client<'static> {
         self.players
             .get_player(guild_id)
             .ok_or_else(||
-                CommandError::FailedPrecondition("No music is currently playing.").into())
+                CommandError::FailedPrecondition("No music is currently playing.".into()).into())
     }
 
     async fn require_dj<'a>(&self, ctx: &commands::Context<'a>) -> Result<()> {
@@ -290,6 +368,7 @@ impl Client<'static> {
                 message: &evt,
                 http: self.http_client.clone(),
                 cache: self.cache.clone(),
+                redis: self.redis.clone(),
             };
 
             let result = match command {
@@ -297,14 +376,18 @@ impl Client<'static> {
                     self.play(ctx, arguments.into_remainder()).await,
                 Command { name: "pause", .. } => self.pause(ctx, true).await,
                 Command { name: "stop", .. } => self.stop(ctx).await,
+                Command { name: "replay", .. } => self.replay(ctx).await,
                 Command { name: "shuffle",  .. } => self.shuffle(ctx).await,
                 Command { name: "skip", .. } => self.skip(ctx).await,
                 Command { name: "forceskip", .. } => self.forceskip(ctx).await,
+                Command { name: "skipto", mut arguments, .. } =>
+                    self.skip_to(ctx, arguments.next()).await,
                 Command { name: "remove", arguments, .. } => Ok(()),
                 Command { name: "removeall", .. } => self.remove_all(ctx).await,
-                Command { name: "nowplaying", .. } => Ok(()),
-                Command { name: "np", .. } => Ok(()),
-                Command { name: "queue", .. } => Ok(()),
+                Command { name: "dedupe", .. } => self.dedupe(ctx).await,
+                Command { name: "nowplaying", .. } => self.now_playing(ctx).await,
+                Command { name: "np", .. } => self.now_playing(ctx).await,
+                Command { name: "queue", .. } => self.queue(ctx).await,
                 Command { name: "volume", arguments, .. } =>
                     // TODO(james7132): Do proper argument parsing.
                     self.volume(ctx, 100).await,
@@ -365,10 +448,16 @@ impl Client<'static> {
             _ => vec![],
         };
 
-        let queue: Vec<Track> = tracks.into_iter()
-                                      .filter_map(|t| Track::try_from(t).ok())
-                                      .collect();
-        let duration = format_duration(queue.iter().map(|t| t.info.length).sum());
+        // Failures are logged as they are decoded, so they can be dropped here.
+        let (queue, _) = Track::try_from_many(tracks);
+        let duration = match queue.as_slice() {
+            [track] => track.info.formatted_length(),
+            tracks => tracks.iter()
+                .map(|track| track.info.finite_length())
+                .sum::<Option<Duration>>()
+                .map(track::format_duration)
+                .unwrap_or_else(|| String::from("LIVE")),
+        };
 
         let response = if queue.len() > 1 {
             format!(":notes: Added **{}** tracks ({}) to the music queue.",
@@ -383,9 +472,12 @@ impl Client<'static> {
         if let Some(player) = self.players.get_player(guild_id) {
             player.enqueue(ctx.message.author.id, queue);
         } else {
+            let config = MusicConfig::get(&mut self.redis.clone(), guild_id).await?
+                .unwrap_or_else(MusicConfig::new);
             let player = Player::new(&self, guild_id).await?;
             player.enqueue(ctx.message.author.id, queue);
             player.connect(user_channel_id.unwrap()).await?;
+            player.set_volume(default_volume(&config))?;
             player.play_next().await?;
         }
 
@@ -399,6 +491,14 @@ impl Client<'static> {
         Ok(())
     }
 
+    async fn replay<'a>(&self, ctx: commands::Context<'a>) -> Result<()> {
+        self.require_dj(&ctx).await?;
+        if let Some(track) = self.require_playing(&ctx)?.replay()? {
+            ctx.respond().content(format!("Restarted `{}`.", track))?.await?;
+        }
+        Ok(())
+    }
+
     async fn stop<'a>(&self, ctx: commands::Context<'a>) -> Result<()> {
         self.require_dj(&ctx).await?;
         self.require_playing(&ctx)?
@@ -449,6 +549,17 @@ impl Client<'static> {
         Ok(())
     }
 
+    async fn dedupe<'a>(&self, ctx: commands::Context<'a>) -> Result<()> {
+        self.require_dj(&ctx).await?;
+        let player = self.require_playing(&ctx)?;
+        let response = match player.dedupe() {
+            0 => "There are no duplicate tracks in the queue.".to_owned(),
+            count => format!("Removed **{}** duplicate tracks from the queue.", count),
+        };
+        ctx.respond().content(response)?.await?;
+        Ok(())
+    }
+
     async fn shuffle<'a>(&self, ctx: commands::Context<'a>) -> Result<()> {
         self.require_in_voice_channel(&ctx)?;
         let player = self.require_playing(&ctx)?;
@@ -473,24 +584,106 @@ impl Client<'static> {
         Ok(())
     }
 
+    async fn skip_to<'a>(&self, ctx: commands::Context<'a>, position: Option<&str>)
+        -> Result<()> {
+        self.require_dj(&ctx).await?;
+        let player = self.require_playing(&ctx)?;
+        // Queue positions are shown to users starting from 1.
+        let index = position
+            .and_then(|pos| pos.parse::<usize>().ok())
+            .and_then(|pos| pos.checked_sub(1))
+            .ok_or_else(|| CommandError::InvalidArgument(
+                "Expected a position in the queue to skip to.".into()))?;
+        let response = if let Some(track) = player.skip_to(index)? {
+            format!("Skipped to `{}`.", track)
+        } else {
+            format!("There is no track at position {} in the queue.", index + 1)
+        };
+        ctx.respond().content(response)?.await?;
+        Ok(())
+    }
+
     async fn volume<'a>(&self, ctx: commands::Context<'a>, volume: i64) -> Result<()> {
         self.require_dj(&ctx).await?;
-        if volume < 0 || volume > 150 {
+        if volume < 0 || volume > MAX_VOLUME {
             bail!(CommandError::InvalidArgument(
-                    "Volume must be between 0 and 150.".into()));
+                    format!("Volume must be between 0 and {}.", MAX_VOLUME)));
         }
-        self.require_playing(&ctx)?.set_volume(volume)?;
+        let player = self.require_playing(&ctx)?;
+
+        // Persist the volume as the default for the next time the player joins. This is done
+        // first, so the live volume is left as is if the command fails.
+        let guild_id = commands::precondition::require_in_guild(&ctx)?;
+        let mut redis = self.redis.clone();
+        db::update_config(&mut redis, guild_id, CompressionLevel::DEFAULT,
+                          |config: &mut MusicConfig| config.set_volume(volume as u32)).await?;
+        player.set_volume(volume)?;
+
         ctx.respond().content(format!("Set volume to `{}`.", volume))?.await?;
         Ok(())
     }
 
-    async fn queue<'a>(&self, _: commands::Context<'a>) -> Result<()> {
-        // TODO(james7132): Implement.
+    async fn queue<'a>(&self, ctx: commands::Context<'a>) -> Result<()> {
+        let summary = self.require_playing(&ctx)?.queue_summary(QUEUE_DISPLAY_LIMIT);
+        if summary.len == 0 {
+            ctx.respond().content("There is nothing in the queue right now.")?.await?;
+            return Ok(());
+        }
+
+        let duration = summary.duration
+            .map(track::format_duration)
+            .unwrap_or_else(|| String::from("LIVE"));
+        let mut response = format!(":notes: **{}** tracks in the queue ({}):",
+                                   summary.len, duration);
+        for (idx, track) in summary.upcoming.iter().enumerate() {
+            response.push_str(
+                &format!("\n`{}.` `{}` ({})", idx + 1, track, track.formatted_length()));
+        }
+        if summary.len > summary.upcoming.len() {
+            response.push_str(
+                &format!("\n...and **{}** more.", summary.len - summary.upcoming.len()));
+        }
+
+        ctx.respond().content(response)?.await?;
         Ok(())
     }
 
-    async fn now_playing<'a>(&self, _: commands::Context<'a>) -> Result<()> {
-        // TODO(james7132): Implement.
+    async fn now_playing<'a>(&self, ctx: commands::Context<'a>) -> Result<()> {
+        let (user_id, track, position) = match self.require_playing(&ctx)?.now_playing() {
+            Some(now_playing) => now_playing,
+            None => {
+                ctx.respond().content("Nothing is playing right now.")?.await?;
+                return Ok(());
+            },
+        };
+
+        let progress = if track.is_stream {
+            track.formatted_length()
+        } else {
+            format!("{}/{}", track::format_duration(position), track.formatted_length())
+        };
+        let embed = Embed {
+            author: None,
+            color: None,
+            description: Some(format!("Requested by <@{}> `[{}]`", user_id, progress)),
+            fields: Vec::new(),
+            footer: None,
+            image: None,
+            kind: String::from("rich"),
+            provider: None,
+            thumbnail: track.thumbnail.clone().map(|url| EmbedThumbnail {
+                height: None,
+                proxy_url: None,
+                url: Some(url),
+                width: None,
+            }),
+            timestamp: None,
+            title: Some(track.to_string()),
+            url: Some(track.uri.clone()),
+            video: None,
+        };
+
+        ctx.respond().embed(embed)?.await?;
         Ok(())
     }
 }
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+/// Tracks how long a player has been idle, either because it has run out of tracks to play or
+/// because everyone else has left its voice channel.
+///
+/// Each timer only starts on the transition into an idle state, so repeatedly reporting the same
+/// state does not reset it.
+#[derive(Default)]
+pub struct IdleTracker {
+    queue_empty_since: Option<Instant>,
+    channel_empty_since: Option<Instant>,
+}
+
+impl IdleTracker {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records whether the player currently has nothing to play.
+    pub fn set_queue_empty(&mut self, empty: bool, now: Instant) {
+        Self::transition(&mut self.queue_empty_since, empty, now);
+    }
+
+    /// Records whether the player's voice channel currently has no listeners.
+    pub fn set_channel_empty(&mut self, empty: bool, now: Instant) {
+        Self::transition(&mut self.channel_empty_since, empty, now);
+    }
+
+    /// Whether either the queue or the channel has been empty for at least `timeout`.
+    pub fn should_disconnect(&self, now: Instant, timeout: Duration) -> bool {
+        [self.queue_empty_since, self.channel_empty_since]
+            .iter()
+            .flatten()
+            .any(|since| now.saturating_duration_since(*since) >= timeout)
+    }
+
+    fn transition(since: &mut Option<Instant>, empty: bool, now: Instant) {
+        if !empty {
+            *since = None;
+        } else if since.is_none() {
+            *since = Some(now);
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_queue_empty_timeout() {
+        let start = Instant::now();
+        let mut idle = IdleTracker::new();
+        idle.set_queue_empty(true, start);
+        // Reporting the same state again does not restart the timer.
+        idle.set_queue_empty(true, start + Duration::from_secs(30));
+
+        assert!(!idle.should_disconnect(start + Duration::from_secs(59), TIMEOUT));
+        assert!(idle.should_disconnect(start + TIMEOUT, TIMEOUT));
+
+        idle.set_queue_empty(false, start + TIMEOUT);
+        assert!(!idle.should_disconnect(start + Duration::from_secs(120), TIMEOUT));
+    }
+
+    #[test]
+    fn test_channel_empty_timeout() {
+        let start = Instant::now();
+        let mut idle = IdleTracker::new();
+        idle.set_queue_empty(false, start);
+        idle.set_channel_empty(true, start);
+
+        assert!(!idle.should_disconnect(start + Duration::from_secs(10), TIMEOUT));
+        assert!(idle.should_disconnect(start + Duration::from_secs(61), TIMEOUT));
+
+        // Someone rejoining resets the timer.
+        idle.set_channel_empty(false, start + Duration::from_secs(61));
+        idle.set_channel_empty(true, start + Duration::from_secs(90));
+        assert!(!idle.should_disconnect(start + Duration::from_secs(120), TIMEOUT));
+    }
+}
@@ -1,4 +1,5 @@
 mod client;
+mod idle;
 mod prelude;
 mod queue;
 mod player;
@@ -1,6 +1,7 @@
-use hourai::prelude::*;
-use crate::{client::Client, queue::MusicQueue, track::*};
+use hourai::{prelude::*, cache::InMemoryCache, proto::guild_configs::MusicConfig};
+use crate::{client::Client, idle::IdleTracker, queue::MusicQueue, track::*};
 use std::collections::HashSet;
+use std::time::Instant;
 use dashmap::DashMap;
 use std::sync::{Weak, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use twilight_gateway::Cluster;
@@ -17,6 +18,21 @@ macro_rules! get_lavalink_player {
     };
 }
 
+/// The maximum volume the volume command accepts, matching the Python music bot.
+pub const MAX_VOLUME: i64 = 150;
+
+/// The maximum volume Lavalink supports.
+pub const MAX_DEFAULT_VOLUME: i64 = 1000;
+
+/// Gets the volume a guild's player should start at, clamped to [`MAX_DEFAULT_VOLUME`]. Unset
+/// configs default to 100.
+///
+/// This reads `MusicConfig.volume`, where both music bots persist the volume set by their volume
+/// commands, rather than a separate default volume field that would need to be kept in sync.
+pub fn default_volume(config: &MusicConfig) -> i64 {
+    (config.get_volume() as i64).min(MAX_DEFAULT_VOLUME)
+}
+
 pub struct PlayerManager(DashMap<GuildId, Player>);
 
 impl PlayerManager {
@@ -34,6 +50,10 @@ impl PlayerManager {
         self.0.insert(player.guild_id(), player.clone());
     }
 
+    pub fn players(&self) -> Vec<Player> {
+        self.0.iter().map(|kv| kv.value().clone()).collect()
+    }
+
     pub fn destroy_player(&self, guild_id: GuildId) {
         debug!("Destroyed player for guild {}", guild_id);
         self.0.remove(&guild_id);
@@ -41,11 +61,22 @@ impl PlayerManager {
 
 }
 
+/// A snapshot of the upcoming tracks in a player's queue.
+pub struct QueueSummary {
+    /// The next tracks to be played, in play order.
+    pub upcoming: Vec<TrackInfo>,
+    /// The total number of tracks in the queue.
+    pub len: usize,
+    /// The total play time of the queue, or None if it contains a stream.
+    pub duration: Option<Duration>,
+}
+
 struct PlayerState {
     channel_id: Option<ChannelId>,
-    currently_playing: Option<(UserId, TrackInfo)>,
+    currently_playing: Option<(UserId, Track)>,
     skip_votes: HashSet<UserId>,
     queue: MusicQueue<UserId, Track>,
+    idle: IdleTracker,
 }
 
 struct PlayerRef {
@@ -82,7 +113,8 @@ impl Player {
                 channel_id: None,
                 currently_playing: None,
                 skip_votes: HashSet::new(),
-                queue: MusicQueue::new()
+                queue: MusicQueue::new(),
+                idle: IdleTracker::new(),
             })
         }));
 
@@ -122,6 +154,36 @@ impl Player {
         self.state_mut().queue.shuffle(user_id)
     }
 
+    /// Removes queued tracks that share a URI with an earlier track in the queue.
+    ///
+    /// Returns the number of tracks removed.
+    pub fn dedupe(&self) -> usize {
+        self.state_mut().queue.dedupe_by_key(|track| track.info.uri.clone())
+    }
+
+    /// Summarizes the queue, listing up to `limit` of the upcoming tracks.
+    pub fn queue_summary(&self, limit: usize) -> QueueSummary {
+        let state = self.state();
+        QueueSummary {
+            upcoming: state.queue.iter_in_order().take(limit).map(|t| t.info.clone()).collect(),
+            len: state.queue.len(),
+            duration: state.queue.total_duration(),
+        }
+    }
+
+    /// Gets the currently playing track, the user who queued it, and how far into the track
+    /// playback is. Returns None if nothing is playing.
+    pub fn now_playing(&self) -> Option<(UserId, TrackInfo, Duration)> {
+        let state = self.state();
+        let (user_id, track) = state.currently_playing.as_ref()?;
+        Some((*user_id, track.info.clone(), self.position()))
+    }
+
+    /// The last playback position reported by Lavalink.
+    fn position(&self) -> Duration {
+        Duration::from_millis(get_lavalink_player!(self).position().max(0) as u64)
+    }
+
     /// The number of votes to skip the current song
     pub fn vote_count(&self) -> usize {
         self.state().skip_votes.len()
@@ -131,26 +193,99 @@ impl Player {
         self.state_mut().skip_votes.insert(user_id);
     }
 
+    /// Refreshes the player's idle timers from the current queue and the population of its
+    /// voice channel.
+    pub fn refresh_idle(&self, cache: &InMemoryCache, now: Instant) {
+        let mut state = self.state_mut();
+        let queue_empty = state.currently_playing.is_none() && state.queue.is_empty();
+        // The bot itself is counted among the users in the channel.
+        let channel_empty = state.channel_id
+            .map(|id| cache.voice_channel_user_count(id) <= 1)
+            .unwrap_or(true);
+        state.idle.set_queue_empty(queue_empty, now);
+        state.idle.set_channel_empty(channel_empty, now);
+    }
+
+    /// Whether the player has been idle long enough that it should leave voice.
+    pub fn should_disconnect(&self, now: Instant, timeout: Duration) -> bool {
+        self.state().idle.should_disconnect(now, timeout)
+    }
+
     pub async fn play_next(&self) -> Result<Option<TrackInfo>> {
-        let (previous, playing) = {
+        let (previous, stopped) = {
             let mut state = self.state_mut();
             state.skip_votes.clear();
-            let previous = state.currently_playing.as_ref().map(|kv| kv.1.clone());
+            let previous = state.currently_playing.as_ref().map(|kv| kv.1.info.clone());
             state.currently_playing = match state.queue.pop() {
                 Some(kv) => {
                     get_lavalink_player!(self).send(kv.value.play(self.0.guild_id))?;
-                    Some((kv.key, kv.value.info))
+                    Some((kv.key, kv.value))
                 },
                 None => None
             };
-            (previous, state.currently_playing.clone())
+            (previous, state.currently_playing.is_none())
         };
-        if playing.is_none() {
+        if stopped {
             self.disconnect().await?;
         }
         Ok(previous)
     }
 
+    /// Skips ahead to the track at `index` in the queue, discarding every track queued before
+    /// it, and starts playing it. Returns the newly playing track, or None and leaves the queue
+    /// untouched if `index` is out of range.
+    pub fn skip_to(&self, index: usize) -> Result<Option<TrackInfo>> {
+        let mut state = self.state_mut();
+        let next = match state.queue.skip_to(index) {
+            Some(kv) => kv,
+            None => return Ok(None),
+        };
+        get_lavalink_player!(self).send(next.value.play(self.0.guild_id))?;
+        state.skip_votes.clear();
+        let info = next.value.info.clone();
+        state.currently_playing = Some((next.key, next.value));
+        Ok(Some(info))
+    }
+
+    /// Restarts the currently playing track from the beginning. Returns the restarted track, or
+    /// None if nothing is playing.
+    pub fn replay(&self) -> Result<Option<TrackInfo>> {
+        let mut state = self.state_mut();
+        state.skip_votes.clear();
+        match &state.currently_playing {
+            Some((_, track)) => {
+                get_lavalink_player!(self).send(track.replay(self.0.guild_id))?;
+                Ok(Some(track.info.clone()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Resumes the currently playing track from `position`. Returns the resumed track, or None
+    /// if nothing is playing.
+    pub fn resume(&self, position: Duration) -> Result<Option<TrackInfo>> {
+        let state = self.state();
+        match &state.currently_playing {
+            Some((_, track)) => {
+                get_lavalink_player!(self).send(track.resume(self.0.guild_id, position))?;
+                Ok(Some(track.info.clone()))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Moves the player onto `node` after its previous node went down, and resumes the current
+    /// track from the last position reported by the previous node. Playback would otherwise
+    /// restart from the beginning. `voice_update` is forwarded to the new node so that it can
+    /// join the player's voice channel.
+    pub fn failover(&self, node: Node, voice_update: VoiceUpdate) -> Result<Option<TrackInfo>> {
+        let position = self.position();
+        // The previous node is unreachable, so it can't be told to destroy the player.
+        let _ = self.0.lavalink_manager.destroy(self.0.guild_id);
+        self.0.lavalink_manager.get_or_insert(self.0.guild_id, node).send(voice_update)?;
+        self.resume(position)
+    }
+
     pub async fn connect(&self, channel_id: ChannelId) -> Result<()> {
         let gateway = &self.0.gateway;
         let shard_id = gateway.shard_id(self.0.guild_id);
@@ -227,3 +362,24 @@ impl Player {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_volume() {
+        let mut config = MusicConfig::new();
+        assert_eq!(default_volume(&config), 100);
+
+        config.set_volume(50);
+        assert_eq!(default_volume(&config), 50);
+
+        // Volumes above what the volume command accepts are still honored.
+        config.set_volume(500);
+        assert_eq!(default_volume(&config), 500);
+
+        config.set_volume(5000);
+        assert_eq!(default_volume(&config), MAX_DEFAULT_VOLUME);
+    }
+}
@@ -1,4 +1,7 @@
-use std::collections::VecDeque;
+use crate::track::Track;
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+use std::time::Duration;
 use rand::seq::SliceRandom;
 
 pub struct QueueItem<K, V> {
@@ -65,6 +68,29 @@ impl<K, V> MusicQueue<K, V> where K: Copy + Eq {
         })
     }
 
+    /// Skips ahead to the item at `index` in play order, discarding every item before it, and
+    /// pops it from the queue. Index 0 is equivalent to [`pop`].
+    ///
+    /// Returns None and leaves the queue untouched if `index` is out of range. If there are n
+    /// keys in the queue, this is a O(n + index) operation.
+    ///
+    /// [`pop`]: Self::pop
+    pub fn skip_to(&mut self, index: usize) -> Option<QueueItem<K, V>> {
+        if index >= self.len() {
+            return None;
+        }
+        for _ in 0..index {
+            self.pop();
+        }
+        self.pop()
+    }
+
+    /// Gets the total number of items in the queue across all keys. If there are n keys in the
+    /// queue, this is a O(n) operation.
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|kv| kv.1.len()).sum()
+    }
+
     /// Gets the total number of items in the queue.  If there are n keys and k values in the
     /// queue for a given key, this is a O(n) operation.
     pub fn count(&self, key: K) -> Option<usize> {
@@ -102,8 +128,180 @@ impl<K, V> MusicQueue<K, V> where K: Copy + Eq {
             })
     }
 
+    /// Removes every item whose dedupe key has already been seen earlier in play order, keeping
+    /// only the first occurrence. The head of the queue is never removed. If there are k values
+    /// in the queue, this is a O(k) operation.
+    ///
+    /// Returns the number of items removed from the queue.
+    pub fn dedupe_by_key<D, F>(&mut self, mut f: F) -> usize
+        where D: Hash + Eq, F: FnMut(&V) -> D {
+        // Items are played round-robin, so the j-th item of every key is played before the
+        // (j + 1)-th item of any key.
+        let depth = self.0.iter().map(|kv| kv.1.len()).max().unwrap_or(0);
+        let mut keep: Vec<Vec<bool>> = self.0.iter().map(|kv| vec![true; kv.1.len()]).collect();
+        let mut seen = HashSet::new();
+        let mut removed = 0;
+        for j in 0..depth {
+            for (i, kv) in self.0.iter().enumerate() {
+                if let Some(value) = kv.1.get(j) {
+                    if !seen.insert(f(value)) {
+                        keep[i][j] = false;
+                        removed += 1;
+                    }
+                }
+            }
+        }
+
+        for (kv, keep) in self.0.iter_mut().zip(keep) {
+            let mut keep = keep.into_iter();
+            kv.1.retain(|_| keep.next().unwrap_or(true));
+        }
+        self.0.retain(|kv| !kv.1.is_empty());
+        removed
+    }
+
+    /// Whether there are no items left in the queue. This is a O(1) operation.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     pub fn contains_key(&self, key: K) -> bool {
         self.0.iter().find(|kv| kv.0 == key).is_some()
     }
 
+    /// Iterates over every item in the queue in play order. If there are n keys and k values in
+    /// the queue, this is a O(n * k) operation.
+    pub fn iter_in_order(&self) -> impl Iterator<Item=&V> {
+        // Items are played round-robin, so the j-th item of every key is played before the
+        // (j + 1)-th item of any key.
+        let depth = self.0.iter().map(|kv| kv.1.len()).max().unwrap_or(0);
+        (0..depth).flat_map(move |j| self.0.iter().filter_map(move |kv| kv.1.get(j)))
+    }
+
+    /// Iterates over every item in the queue. Items are grouped by key, not in play order. If
+    /// there are n keys in the queue, creating the iterator is a O(n) operation.
+    pub fn iter(&self) -> impl Iterator<Item=&V> {
+        self.0.iter().flat_map(|kv| kv.1.iter())
+    }
+
+}
+
+impl<K> MusicQueue<K, Track> where K: Copy + Eq {
+
+    /// Gets the total play time of every track in the queue. Streams have no fixed length, so
+    /// this returns None if any track in the queue is a stream. If there are k values in the
+    /// queue, this is a O(k) operation.
+    pub fn total_duration(&self) -> Option<Duration> {
+        self.iter().map(|track| track.info.finite_length()).sum()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue() -> MusicQueue<char, u32> {
+        let mut queue = MusicQueue::new();
+        queue.extend('a', vec![1, 2, 3]);
+        queue.extend('b', vec![1, 2]);
+        queue.extend('c', vec![1]);
+        queue
+    }
+
+    fn drain(queue: &mut MusicQueue<char, u32>) -> Vec<(char, u32)> {
+        std::iter::from_fn(|| queue.pop().map(|item| (item.key, item.value))).collect()
+    }
+
+    #[test]
+    fn test_skip_to() {
+        let mut queue = queue();
+        let item = queue.skip_to(2).unwrap();
+        assert_eq!((item.key, item.value), ('c', 1));
+        assert_eq!(drain(&mut queue), vec![('a', 2), ('b', 2), ('a', 3)]);
+    }
+
+    #[test]
+    fn test_skip_to_zero() {
+        let mut queue = queue();
+        let item = queue.skip_to(0).unwrap();
+        assert_eq!((item.key, item.value), ('a', 1));
+        assert_eq!(queue.len(), 5);
+    }
+
+    #[test]
+    fn test_skip_to_out_of_range() {
+        let mut queue = queue();
+        assert!(queue.skip_to(6).is_none());
+        assert_eq!(queue.len(), 6);
+        assert_eq!(drain(&mut queue)[0], ('a', 1));
+
+        assert!(MusicQueue::<char, u32>::new().skip_to(0).is_none());
+    }
+
+    #[test]
+    fn test_iter_in_order() {
+        let mut queue = queue();
+        let expected = drain(&mut queue());
+        assert_eq!(queue.iter_in_order().copied().collect::<Vec<_>>(),
+                   expected.iter().map(|kv| kv.1).collect::<Vec<_>>());
+
+        queue.pop();
+        assert_eq!(queue.iter_in_order().copied().collect::<Vec<_>>(), vec![1, 1, 2, 2, 3]);
+    }
+
+    #[test]
+    fn test_dedupe_by_key() {
+        let mut queue = MusicQueue::new();
+        queue.extend('a', vec![1, 2, 1, 3]);
+        queue.extend('b', vec![2, 1, 4]);
+        queue.extend('c', vec![1]);
+
+        // Play order: (a, 1), (b, 2), (c, 1), (a, 2), (b, 1), (a, 1), (b, 4), (a, 3)
+        assert_eq!(queue.dedupe_by_key(|v| *v), 4);
+        assert_eq!(drain(&mut queue), vec![('a', 1), ('b', 2), ('a', 3), ('b', 4)]);
+    }
+
+    fn track(secs: u64, is_stream: bool) -> Track {
+        Track {
+            info: crate::track::TrackInfo {
+                title: None,
+                author: None,
+                uri: String::from("https://a"),
+                length: Duration::from_secs(secs),
+                is_stream,
+                thumbnail: None,
+            },
+            track: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_total_duration() {
+        let mut queue = MusicQueue::new();
+        assert_eq!(queue.total_duration(), Some(Duration::from_secs(0)));
+
+        queue.extend('a', vec![track(60, false), track(30, false)]);
+        queue.extend('b', vec![track(15, false)]);
+        assert_eq!(queue.total_duration(), Some(Duration::from_secs(105)));
+    }
+
+    #[test]
+    fn test_total_duration_with_stream() {
+        let mut queue = MusicQueue::new();
+        queue.extend('a', vec![track(60, false)]);
+        queue.extend('b', vec![track(0, true), track(15, false)]);
+        assert_eq!(queue.total_duration(), None);
+    }
+
+    #[test]
+    fn test_dedupe_by_key_keeps_head() {
+        let mut queue = MusicQueue::new();
+        queue.extend('a', vec![1]);
+        queue.extend('b', vec![1, 1]);
+
+        assert_eq!(queue.dedupe_by_key(|v| *v), 2);
+        assert_eq!(drain(&mut queue), vec![('a', 1)]);
+        assert_eq!(MusicQueue::<char, u32>::new().dedupe_by_key(|v| *v), 0);
+    }
 }
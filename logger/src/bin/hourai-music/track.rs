@@ -2,8 +2,25 @@ use twilight_model::id::GuildId;
 use twilight_lavalink::model::Play;
 use std::convert::TryFrom;
 use std::{fmt, time::Duration};
+use thiserror::Error;
 use tracing::error;
 
+#[derive(Error, Debug)]
+pub enum TrackError {
+    #[error("Failed to decode track {}: {:?}", .track, .source)]
+    Decode {
+        /// The title or URI of the track that failed to decode.
+        track: String,
+        source: base64::DecodeError,
+    },
+}
+
+impl From<base64::DecodeError> for TrackError {
+    fn from(source: base64::DecodeError) -> Self {
+        Self::Decode { track: String::from("unknown track"), source }
+    }
+}
+
 #[derive(Clone)]
 pub struct TrackInfo {
     pub title: Option<String>,
@@ -11,6 +28,40 @@ pub struct TrackInfo {
     pub uri: String,
     pub length: Duration,
     pub is_stream: bool,
+    /// The URL of the track's artwork, if one can be derived from its URI.
+    pub thumbnail: Option<String>,
+}
+
+/// Formats a duration as `h:mm:ss`, omitting the hours if zero.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+impl TrackInfo {
+
+    /// The play time of the track. Streams have no fixed length, so this is None for streams.
+    pub fn finite_length(&self) -> Option<Duration> {
+        if self.is_stream {
+            None
+        } else {
+            Some(self.length)
+        }
+    }
+
+    /// Formats the length of the track as `h:mm:ss`, omitting the hours if zero. Streams have no
+    /// fixed length and are rendered as "LIVE".
+    pub fn formatted_length(&self) -> String {
+        self.finite_length()
+            .map(format_duration)
+            .unwrap_or_else(|| String::from("LIVE"))
+    }
+
 }
 
 impl fmt::Display for TrackInfo {
@@ -36,13 +87,95 @@ impl Track {
         Play::new(guild_id, base64::encode(&self.track), None, None, false)
     }
 
+    /// Plays the track from the very beginning, replacing whatever is currently playing.
+    pub fn replay(&self, guild_id: GuildId) -> Play {
+        Play::new(guild_id, base64::encode(&self.track), Some(0), None, false)
+    }
+
+    /// Plays the track starting from `position`, i.e. to resume playback on another Lavalink node
+    /// after the original one failed. The position is clamped to the length of the track.
+    /// Streams cannot be seeked, so they are played from the live position instead.
+    pub fn resume(&self, guild_id: GuildId, position: Duration) -> Play {
+        let start_time = if self.info.is_stream {
+            None
+        } else {
+            Some(position.min(self.info.length).as_millis() as u64)
+        };
+        Play::new(guild_id, base64::encode(&self.track), start_time, None, false)
+    }
+
+    /// Decodes a batch of tracks loaded from Lavalink, such as a playlist. Tracks that fail to
+    /// decode are returned separately so that one bad entry does not discard the whole batch.
+    /// Successfully decoded tracks keep their original order.
+    pub fn try_from_many(
+        tracks: impl IntoIterator<Item=twilight_lavalink::http::Track>
+    ) -> (Vec<Track>, Vec<TrackError>) {
+        let mut decoded = Vec::new();
+        let mut errors = Vec::new();
+        for track in tracks {
+            match Self::try_from(track) {
+                Ok(track) => decoded.push(track),
+                Err(err) => errors.push(err),
+            }
+        }
+        (decoded, errors)
+    }
+
+}
+
+/// Derives the URL of a track's artwork from its URI. Only YouTube videos are supported, as other
+/// sources, like SoundCloud, do not encode their artwork in the track's URI.
+///
+/// TODO: Lavalink does not report artwork for SoundCloud tracks either. Supporting them requires
+/// resolving the artwork through SoundCloud's oEmbed endpoint
+/// (`https://soundcloud.com/oembed?format=json&url=<uri>`) when the track is loaded, which needs
+/// an HTTPS capable client in the music bot.
+fn thumbnail_url(uri: &str) -> Option<String> {
+    youtube_video_id(uri).map(|id| format!("https://i.ytimg.com/vi/{}/hqdefault.jpg", id))
+}
+
+/// Extracts the video ID from YouTube watch, shorts, embed, and youtu.be URLs.
+fn youtube_video_id(uri: &str) -> Option<&str> {
+    let rest = uri.strip_prefix("https://").or_else(|| uri.strip_prefix("http://"))?;
+    let (host, path) = match rest.find('/') {
+        Some(idx) => rest.split_at(idx),
+        None => return None,
+    };
+    let host = host
+        .trim_start_matches("www.")
+        .trim_start_matches("m.")
+        .trim_start_matches("music.");
+
+    let id = match host {
+        "youtu.be" => path[1..].split(|c| c == '?' || c == '/').next(),
+        "youtube.com" => {
+            if let Some(query) = path.strip_prefix("/watch?") {
+                query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("v="))
+            } else {
+                path.strip_prefix("/shorts/")
+                    .or_else(|| path.strip_prefix("/embed/"))
+                    .and_then(|id| id.split(|c| c == '?' || c == '/').next())
+            }
+        },
+        _ => None,
+    }?;
+
+    let valid = id.len() == 11 &&
+        id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Some(id)
+    } else {
+        None
+    }
 }
 
-fn decode_track(track: String) -> std::result::Result<Vec<u8>, base64::DecodeError> {
+fn decode_track(info: &TrackInfo, track: String) -> std::result::Result<Vec<u8>, TrackError> {
     base64::decode(&track)
            .map_err(|err| {
                error!("Failed to decode track {}: {:?}", track, err);
-               err
+               TrackError::Decode { track: info.to_string(), source: err }
            })
 }
 
@@ -52,6 +185,7 @@ impl From<twilight_lavalink::http::TrackInfo> for TrackInfo {
         Self {
             title: value.title,
             author: value.author,
+            thumbnail: thumbnail_url(&value.uri),
             uri: value.uri,
             length: Duration::from_millis(value.length),
             is_stream: value.is_stream,
@@ -62,14 +196,148 @@ impl From<twilight_lavalink::http::TrackInfo> for TrackInfo {
 
 impl TryFrom<twilight_lavalink::http::Track> for Track {
 
-    type Error = base64::DecodeError;
+    type Error = TrackError;
 
     fn try_from(value: twilight_lavalink::http::Track) -> Result<Self, Self::Error> {
-        Ok(Self {
-            info: TrackInfo::from(value.info),
-            track: decode_track(value.track)?
-        })
+        let info = TrackInfo::from(value.info);
+        let track = decode_track(&info, value.track)?;
+        Ok(Self { info, track })
     }
 
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lavalink_track(track: &str, uri: &str) -> twilight_lavalink::http::Track {
+        twilight_lavalink::http::Track {
+            track: track.to_owned(),
+            info: twilight_lavalink::http::TrackInfo {
+                identifier: uri.to_owned(),
+                is_seekable: true,
+                author: None,
+                length: 1000,
+                is_stream: false,
+                position: 0,
+                title: None,
+                uri: uri.to_owned(),
+            },
+        }
+    }
+
+    fn track_info(length: Duration, is_stream: bool) -> TrackInfo {
+        TrackInfo {
+            title: None,
+            author: None,
+            uri: String::from("https://a"),
+            length,
+            is_stream,
+            thumbnail: None,
+        }
+    }
+
+    #[test]
+    fn test_formatted_length_sub_minute() {
+        assert_eq!(track_info(Duration::from_secs(7), false).formatted_length(), "0:07");
+        assert_eq!(track_info(Duration::from_secs(59), false).formatted_length(), "0:59");
+    }
+
+    #[test]
+    fn test_formatted_length_multi_hour() {
+        let length = Duration::from_secs(2 * 3600 + 5 * 60 + 9);
+        assert_eq!(track_info(length, false).formatted_length(), "2:05:09");
+        assert_eq!(track_info(Duration::from_secs(754), false).formatted_length(), "12:34");
+    }
+
+    #[test]
+    fn test_formatted_length_stream() {
+        assert_eq!(track_info(Duration::from_secs(0), true).formatted_length(), "LIVE");
+    }
+
+    #[test]
+    fn test_replay_starts_from_beginning() {
+        let track = Track::try_from(lavalink_track("AAEC", "https://a")).unwrap();
+        let play = track.replay(GuildId(1));
+        assert_eq!(play.start_time, Some(0));
+        assert_eq!(play.end_time, None);
+        assert!(!play.no_replace);
+        assert_eq!(play.track, "AAEC");
+        assert_eq!(play.guild_id, GuildId(1));
+    }
+
+    #[test]
+    fn test_resume_starts_from_position() {
+        let track = Track::try_from(lavalink_track("AAEC", "https://a")).unwrap();
+        let play = track.resume(GuildId(1), Duration::from_millis(400));
+        assert_eq!(play.start_time, Some(400));
+        assert_eq!(play.end_time, None);
+        assert_eq!(play.track, "AAEC");
+
+        // Positions past the end of the track are clamped to its length.
+        let play = track.resume(GuildId(1), Duration::from_secs(5));
+        assert_eq!(play.start_time, Some(1000));
+    }
+
+    #[test]
+    fn test_resume_stream_plays_live() {
+        let mut track = Track::try_from(lavalink_track("AAEC", "https://a")).unwrap();
+        track.info.is_stream = true;
+        let play = track.resume(GuildId(1), Duration::from_millis(400));
+        assert_eq!(play.start_time, None);
+    }
+
+    #[test]
+    fn test_thumbnail_url_youtube() {
+        const THUMBNAIL: &str = "https://i.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg";
+        for uri in &[
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42",
+            "http://m.youtube.com/watch?v=dQw4w9WgXcQ",
+            "https://music.youtube.com/watch?v=dQw4w9WgXcQ&list=abc",
+            "https://youtu.be/dQw4w9WgXcQ?t=42",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(thumbnail_url(uri).as_deref(), Some(THUMBNAIL), "{}", uri);
+        }
+
+        let track = Track::try_from(
+            lavalink_track("AAEC", "https://www.youtube.com/watch?v=dQw4w9WgXcQ")).unwrap();
+        assert_eq!(track.info.thumbnail.as_deref(), Some(THUMBNAIL));
+    }
+
+    #[test]
+    fn test_thumbnail_url_unrecognized() {
+        for uri in &[
+            "https://soundcloud.com/artist/track",
+            "https://example.com/watch?v=dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=short",
+            "https://www.youtube.com/watch",
+            "https://youtu.be/",
+            "youtube.com/watch?v=dQw4w9WgXcQ",
+            "not a uri",
+            "",
+        ] {
+            assert_eq!(thumbnail_url(uri), None, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_try_from_many_splits_failures() {
+        let (tracks, errors) = Track::try_from_many(vec![
+            lavalink_track("AAEC", "https://a"),
+            lavalink_track("not base64!", "https://b"),
+            lavalink_track("AwQF", "https://c"),
+        ]);
+
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(tracks[0].info.uri, "https://a");
+        assert_eq!(tracks[0].track, vec![0, 1, 2]);
+        assert_eq!(tracks[1].info.uri, "https://c");
+        assert_eq!(tracks[1].track, vec![3, 4, 5]);
+        match &errors[0] {
+            TrackError::Decode { track, .. } => assert_eq!(track, "https://b"),
+        }
+    }
+}
@@ -2,6 +2,7 @@ use super::{
     config::{Config, ResourceType},
     InMemoryCache,
 };
+use std::time::Duration;
 
 /// Builder to configure and construct an [`InMemoryCache`].
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
@@ -20,7 +21,8 @@ impl InMemoryCacheBuilder {
 
     /// Sets the list of resource types for the cache to handle.
     ///
-    /// Defaults to all types.
+    /// Defaults to all types except [`ResourceType::INTEGRATION`], [`ResourceType::TYPING`], and
+    /// [`ResourceType::REACTOR`].
     pub fn resource_types(mut self, resource_types: ResourceType) -> Self {
         self.0.resource_types = resource_types;
 
@@ -35,6 +37,42 @@ impl InMemoryCacheBuilder {
 
         self
     }
+
+    /// Sets how long deleted messages are retained as tombstones before being
+    /// discarded.
+    ///
+    /// Defaults to `None`, where deleted messages are discarded immediately.
+    pub fn deleted_message_retention(mut self, retention: Duration) -> Self {
+        self.0.deleted_message_retention = Some(retention);
+
+        self
+    }
+
+    /// Sets the maximum number of users to cache. Once exceeded, the least recently used users
+    /// are evicted.
+    ///
+    /// Evicting a user only removes it from the user cache. Cached members hold their user as an
+    /// `Arc<User>`, so an evicted user survives through every member referencing it, and remains
+    /// available via [`InMemoryCache::member`] until the member itself is removed or updated.
+    ///
+    /// [`InMemoryCache::member`]: super::InMemoryCache::member
+    ///
+    /// Defaults to `None`, where the number of users is unlimited.
+    pub fn max_users(mut self, max_users: usize) -> Self {
+        self.0.max_users = Some(max_users);
+
+        self
+    }
+
+    /// Sets whether to count cache hits and misses on the main getters. See
+    /// [`InMemoryCache::hit_miss_stats`].
+    ///
+    /// Defaults to `false`.
+    pub fn hit_miss_stats(mut self, enabled: bool) -> Self {
+        self.0.hit_miss_stats = enabled;
+
+        self
+    }
 }
 
 #[cfg(test)]
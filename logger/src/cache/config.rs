@@ -1,4 +1,5 @@
 use bitflags::bitflags;
+use std::time::Duration;
 
 bitflags! {
     /// A set of bitflags which can be used to specify what resource to process
@@ -19,6 +20,14 @@ bitflags! {
         const USER_CURRENT = 1 << 8;
         const USER = 1 << 9;
         const VOICE_STATE = 1 << 10;
+        const BAN = 1 << 11;
+        /// Not enabled by default.
+        const INTEGRATION = 1 << 12;
+        /// Not enabled by default.
+        const TYPING = 1 << 13;
+        /// Tracks which users added each reaction, independently of the message cache. Not
+        /// enabled by default.
+        const REACTOR = 1 << 14;
     }
 }
 
@@ -29,6 +38,9 @@ bitflags! {
 pub struct Config {
     pub(super) resource_types: ResourceType,
     pub(super) message_cache_size: usize,
+    pub(super) deleted_message_retention: Option<Duration>,
+    pub(super) max_users: Option<usize>,
+    pub(super) hit_miss_stats: bool,
 }
 
 impl Config {
@@ -41,6 +53,33 @@ impl Config {
     pub fn message_cache_size_mut(&mut self) -> &mut usize {
         &mut self.message_cache_size
     }
+
+    /// Returns how long deleted messages are retained as tombstones, if at all.
+    pub fn deleted_message_retention(&self) -> Option<Duration> {
+        self.deleted_message_retention
+    }
+
+    /// Returns a mutable reference to the deleted message retention window.
+    pub fn deleted_message_retention_mut(&mut self) -> &mut Option<Duration> {
+        &mut self.deleted_message_retention
+    }
+
+    /// Returns the maximum number of users cached, if limited. Evicted users may still be held
+    /// as an `Arc<User>` by cached members.
+    pub fn max_users(&self) -> Option<usize> {
+        self.max_users
+    }
+
+    /// Returns a mutable reference to the maximum number of users cached.
+    pub fn max_users_mut(&mut self) -> &mut Option<usize> {
+        &mut self.max_users
+    }
+
+    /// Returns whether cache hits and misses are counted.
+    pub fn hit_miss_stats(&self) -> bool {
+        self.hit_miss_stats
+    }
+
     /// Returns an immutable reference to the resource types enabled.
     pub fn resource_types(&self) -> ResourceType {
         self.resource_types
@@ -55,8 +94,14 @@ impl Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            resource_types: ResourceType::all(),
+            resource_types: ResourceType::all() -
+                ResourceType::INTEGRATION -
+                ResourceType::TYPING -
+                ResourceType::REACTOR,
             message_cache_size: 100,
+            deleted_message_retention: None,
+            max_users: None,
+            hit_miss_stats: false,
         }
     }
 }
@@ -79,21 +124,40 @@ mod tests {
         assert_eq!(1 << 8, ResourceType::USER_CURRENT.bits());
         assert_eq!(1 << 9, ResourceType::USER.bits());
         assert_eq!(1 << 10, ResourceType::VOICE_STATE.bits());
+        assert_eq!(1 << 11, ResourceType::BAN.bits());
+        assert_eq!(1 << 12, ResourceType::INTEGRATION.bits());
+        assert_eq!(1 << 13, ResourceType::TYPING.bits());
+        assert_eq!(1 << 14, ResourceType::REACTOR.bits());
     }
 
     #[test]
     fn test_defaults() {
         let conf = Config {
-            resource_types: ResourceType::all(),
+            resource_types: ResourceType::all() -
+                ResourceType::INTEGRATION -
+                ResourceType::TYPING -
+                ResourceType::REACTOR,
             message_cache_size: 100,
+            deleted_message_retention: None,
+            max_users: None,
+            hit_miss_stats: false,
         };
         let default = Config::default();
         assert_eq!(conf.resource_types, default.resource_types);
         assert_eq!(conf.message_cache_size, default.message_cache_size);
+        assert_eq!(conf.deleted_message_retention, default.deleted_message_retention);
+        assert_eq!(conf.max_users, default.max_users);
+        assert_eq!(conf.hit_miss_stats, default.hit_miss_stats);
     }
 
     #[test]
     fn test_config_fields() {
-        static_assertions::assert_fields!(Config: resource_types, message_cache_size);
+        static_assertions::assert_fields!(
+            Config: resource_types,
+            message_cache_size,
+            deleted_message_retention,
+            max_users,
+            hit_miss_stats
+        );
     }
 }
@@ -0,0 +1,116 @@
+use dashmap::DashMap;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+};
+use twilight_model::id::UserId;
+
+/// Tracks when each cached user was last accessed, so that the least recently used users can be
+/// evicted once the number of cached users is capped.
+///
+/// Accessing a user only bumps its last access time, which needs no write lock. Users are kept
+/// ordered by the time they were last ordered at instead, and are lazily moved to the back once
+/// they reach the front of the order having been accessed since.
+#[derive(Debug, Default)]
+pub(super) struct UserAccess {
+    clock: AtomicU64,
+    // The tick each user is ordered at, and the tick it was last accessed at.
+    users: DashMap<UserId, (u64, AtomicU64)>,
+    order: Mutex<BTreeMap<u64, UserId>>,
+}
+
+impl UserAccess {
+    /// Marks a user as accessed now.
+    pub fn touch(&self, user_id: UserId) {
+        let now = self.clock.fetch_add(1, Ordering::Relaxed);
+        if let Some(entry) = self.users.get(&user_id) {
+            entry.1.store(now, Ordering::Relaxed);
+            return;
+        }
+        self.lock_order().insert(now, user_id);
+        // If another thread raced to insert the user, its entry in the order is left behind and
+        // discarded once it reaches the front.
+        self.users.insert(user_id, (now, AtomicU64::new(now)));
+    }
+
+    /// Stops tracking a user, i.e. once it is removed from the cache.
+    pub fn remove(&self, user_id: UserId) {
+        if let Some((_, (ordered_at, _))) = self.users.remove(&user_id) {
+            self.lock_order().remove(&ordered_at);
+        }
+    }
+
+    /// Stops tracking the least recently used user, returning it. This is an O(log n)
+    /// operation, amortized over the accesses since the user was last ordered.
+    pub fn pop_least_recent(&self) -> Option<UserId> {
+        loop {
+            let (ordered_at, user_id) = {
+                let mut order = self.lock_order();
+                let ordered_at = *order.keys().next()?;
+                (ordered_at, order.remove(&ordered_at)?)
+            };
+            let accessed_at = match self.users.get(&user_id) {
+                Some(entry) if entry.0 == ordered_at => entry.1.load(Ordering::Relaxed),
+                // Left behind by a racing insert.
+                _ => continue,
+            };
+            if accessed_at == ordered_at {
+                self.users.remove(&user_id);
+                return Some(user_id);
+            }
+            if let Some(mut entry) = self.users.get_mut(&user_id) {
+                entry.0 = accessed_at;
+            }
+            self.lock_order().insert(accessed_at, user_id);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.users.clear();
+        self.lock_order().clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    fn lock_order(&self) -> MutexGuard<'_, BTreeMap<u64, UserId>> {
+        self.order.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserAccess;
+    use twilight_model::id::UserId;
+
+    #[test]
+    fn test_pop_least_recent() {
+        let access = UserAccess::default();
+        assert_eq!(access.pop_least_recent(), None);
+
+        for id in 1..=3 {
+            access.touch(UserId(id));
+        }
+        access.touch(UserId(1));
+        assert_eq!(access.pop_least_recent(), Some(UserId(2)));
+        assert_eq!(access.pop_least_recent(), Some(UserId(3)));
+        assert_eq!(access.pop_least_recent(), Some(UserId(1)));
+        assert!(access.is_empty());
+    }
+
+    #[test]
+    fn test_remove() {
+        let access = UserAccess::default();
+        access.touch(UserId(1));
+        access.touch(UserId(2));
+        access.remove(UserId(1));
+        access.remove(UserId(3));
+        assert_eq!(access.order.lock().unwrap().len(), 1);
+        assert_eq!(access.pop_least_recent(), Some(UserId(2)));
+        assert_eq!(access.pop_least_recent(), None);
+    }
+}
@@ -2,27 +2,44 @@ pub mod model;
 
 mod builder;
 mod config;
+mod lru;
+mod observer;
+mod stats;
 mod updates;
 
 pub use self::{
     builder::InMemoryCacheBuilder,
     config::{Config, ResourceType},
-    updates::UpdateCache,
+    observer::CacheObserver,
+    updates::{CacheUpdate, UpdateCache},
 };
 
 use self::model::*;
+use self::lru::UserAccess;
+use self::stats::{HitMissStats, StatResource};
 use dashmap::{mapref::entry::Entry, DashMap, DashSet};
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, BTreeSet, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     hash::Hash,
-    sync::{Arc, Mutex},
+    mem::size_of,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError, RwLock, TryLockError,
+    },
+    time::{Duration, Instant},
 };
 use twilight_model::{
-    channel::{Group, GuildChannel, PrivateChannel},
-    gateway::presence::{Presence, Status, UserOrId},
-    guild::{Emoji, Guild, Member, PartialMember, Role, Permissions},
-    id::{ChannelId, EmojiId, GuildId, MessageId, RoleId, UserId},
+    channel::{
+        permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
+        message::MessageReaction,
+        ChannelType, Group, GuildChannel, Message, PrivateChannel, ReactionType,
+    },
+    gateway::presence::{Activity, ActivityType, Presence, Status, UserOrId},
+    guild::{
+        Emoji, Guild, Member, PartialMember, Permissions, PremiumTier, Role, SystemChannelFlags,
+    },
+    id::{ChannelId, EmojiId, GuildId, IntegrationId, MessageId, RoleId, UserId},
     user::{CurrentUser, User},
     voice::VoiceState,
 };
@@ -78,29 +95,113 @@ fn upsert_item<K: Eq + Hash, V: PartialEq>(map: &DashMap<K, Arc<V>>, k: K, v: V)
     }
 }
 
+// Approximate heap sizes of a single entry in each of the cache's maps, used by
+// `InMemoryCache::estimated_memory_bytes`. Each is the size of the key plus the stored value,
+// including the pointee of any Arc. Heap allocations owned by the values (strings, vectors, etc.)
+// and the maps' own overhead are not counted.
+const GUILD_ENTRY_BYTES: usize =
+    size_of::<GuildId>() + size_of::<Arc<CachedGuild>>() + size_of::<CachedGuild>();
+const CHANNEL_ENTRY_BYTES: usize =
+    size_of::<ChannelId>() + size_of::<GuildItem<GuildChannel>>() + size_of::<GuildChannel>();
+const PRIVATE_CHANNEL_ENTRY_BYTES: usize =
+    size_of::<ChannelId>() + size_of::<Arc<PrivateChannel>>() + size_of::<PrivateChannel>();
+const GROUP_ENTRY_BYTES: usize =
+    size_of::<ChannelId>() + size_of::<Arc<Group>>() + size_of::<Group>();
+const EMOJI_ENTRY_BYTES: usize =
+    size_of::<EmojiId>() + size_of::<GuildItem<CachedEmoji>>() + size_of::<CachedEmoji>();
+const ROLE_ENTRY_BYTES: usize =
+    size_of::<RoleId>() + size_of::<GuildItem<Role>>() + size_of::<Role>();
+const MEMBER_ENTRY_BYTES: usize =
+    size_of::<(GuildId, UserId)>() + size_of::<Arc<CachedMember>>() + size_of::<CachedMember>();
+const USER_ENTRY_BYTES: usize = size_of::<UserId>() +
+    size_of::<(Arc<User>, BTreeSet<GuildId>)>() + size_of::<User>();
+const MESSAGE_ENTRY_BYTES: usize =
+    size_of::<MessageId>() + size_of::<Arc<CachedMessage>>() + size_of::<CachedMessage>();
+const VOICE_STATE_ENTRY_BYTES: usize =
+    size_of::<(GuildId, UserId)>() + size_of::<CachedVoiceState>();
+const VOICE_SERVER_ENTRY_BYTES: usize = size_of::<GuildId>() + size_of::<(String, String)>();
+const CUSTOM_STATUS_ENTRY_BYTES: usize = size_of::<(GuildId, UserId)>() + size_of::<String>();
+const TYPING_ENTRY_BYTES: usize = size_of::<UserId>() + size_of::<Instant>();
+// Every ID in the per-guild and per-channel index sets.
+const INDEX_ENTRY_BYTES: usize = size_of::<u64>();
+
 // When adding a field here, be sure to add it to `InMemoryCache::clear` if
 // necessary.
+// When adding a map here, be sure to account for it in
+// `InMemoryCache::estimated_memory_bytes` as well.
 #[derive(Debug, Default)]
 struct InMemoryCacheRef {
+    // The resource types in here are only the initial ones, see `resource_types`.
     config: Arc<Config>,
+    // Changed at runtime without locking, so events never wait on a config change.
+    resource_types: AtomicResourceType,
+    // Configured rather than cached, so these are not removed by clear() or guild deletes.
+    extra_owners: DashMap<GuildId, HashSet<UserId>>,
+    bans: DashMap<GuildId, HashSet<UserId>>,
     channels_guild: DashMap<ChannelId, GuildItem<GuildChannel>>,
     channels_private: DashMap<ChannelId, Arc<PrivateChannel>>,
+    // None marks a channel whose webhooks changed since they were last counted.
+    channel_webhooks: DashMap<ChannelId, Option<usize>>,
     // So long as the lock isn't held across await or panic points this is fine.
     current_user: Mutex<Option<Arc<CurrentUser>>>,
+    custom_statuses: DashMap<(GuildId, UserId), String>,
     emojis: DashMap<EmojiId, GuildItem<CachedEmoji>>,
     groups: DashMap<ChannelId, Arc<Group>>,
     guilds: DashMap<GuildId, Arc<CachedGuild>>,
     guild_channels: DashMap<GuildId, HashSet<ChannelId>>,
     guild_emojis: DashMap<GuildId, HashSet<EmojiId>>,
+    guild_integrations: DashMap<GuildId, HashSet<IntegrationId>>,
     guild_members: DashMap<GuildId, HashSet<UserId>>,
+    // The expected chunk count and the indexes received so far for each guild being chunked.
+    guild_member_chunks: DashMap<GuildId, (u32, HashSet<u32>)>,
     guild_presences: DashMap<GuildId, HashSet<UserId>>,
+    guild_streaming: DashMap<GuildId, HashSet<UserId>>,
     guild_roles: DashMap<GuildId, HashSet<RoleId>>,
     members: DashMap<(GuildId, UserId), Arc<CachedMember>>,
     messages: DashMap<ChannelId, BTreeMap<MessageId, Arc<CachedMessage>>>,
+    // The users who added each reaction to a message, for messages with any reactions.
+    message_reactors: DashMap<MessageId, Vec<(ReactionType, HashSet<UserId>)>>,
+    message_tombstones: DashMap<MessageId, (Instant, Arc<CachedMessage>)>,
     roles: DashMap<RoleId, GuildItem<Role>>,
     unavailable_guilds: DashSet<GuildId>,
     users: DashMap<UserId, (Arc<User>, BTreeSet<GuildId>)>,
-    voice_states: DashMap<(GuildId, UserId), ChannelId>,
+    // Only tracked when the number of users is capped.
+    user_access: UserAccess,
+    // Not reset by clear().
+    observer: RwLock<Option<Arc<dyn CacheObserver>>>,
+    // Not reset by clear().
+    stats: HitMissStats,
+    // When each user last started typing in each channel. Pruned lazily when read.
+    typing: DashMap<ChannelId, HashMap<UserId, Instant>>,
+    voice_states: DashMap<(GuildId, UserId), CachedVoiceState>,
+    voice_state_channels: DashMap<ChannelId, HashSet<UserId>>,
+    // The endpoint and token of the voice server each guild's voice connection is assigned to.
+    voice_servers: DashMap<GuildId, (String, String)>,
+}
+
+/// The set of resource types the cache processes, stored as its bits so that it can be swapped
+/// atomically.
+#[derive(Debug)]
+struct AtomicResourceType(AtomicU64);
+
+impl AtomicResourceType {
+    fn new(resource_types: ResourceType) -> Self {
+        Self(AtomicU64::new(resource_types.bits()))
+    }
+
+    fn load(&self) -> ResourceType {
+        ResourceType::from_bits_truncate(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, resource_types: ResourceType) {
+        self.0.store(resource_types.bits(), Ordering::Relaxed);
+    }
+}
+
+impl Default for AtomicResourceType {
+    fn default() -> Self {
+        Self::new(Config::default().resource_types())
+    }
 }
 
 /// A thread-safe, in-memory-process cache of Discord data. It can be cloned and
@@ -163,12 +264,25 @@ impl InMemoryCache {
     }
 
     fn new_with_config(config: Config) -> Self {
+        let stats = HitMissStats::default();
+        stats.set_enabled(config.hit_miss_stats());
         Self(Arc::new(InMemoryCacheRef {
+            resource_types: AtomicResourceType::new(config.resource_types()),
             config: Arc::new(config),
+            stats,
             ..Default::default()
         }))
     }
 
+    /// Gets the number of cache hits and misses, in that order, for each of the main getters:
+    /// "guild", "user", "member", "message", "role", and "channel" (guild channels).
+    ///
+    /// Counts are only recorded if enabled via [`InMemoryCacheBuilder::hit_miss_stats`], and are
+    /// otherwise always zero.
+    pub fn hit_miss_stats(&self) -> HashMap<&'static str, (u64, u64)> {
+        self.0.stats.snapshot()
+    }
+
     /// Create a new builder to configure and construct an in-memory cache.
     pub fn builder() -> InMemoryCacheBuilder {
         InMemoryCacheBuilder::new()
@@ -176,7 +290,38 @@ impl InMemoryCache {
 
     /// Returns a copy of the config cache.
     pub fn config(&self) -> Config {
-        (*self.0.config).clone()
+        let mut config = (*self.0.config).clone();
+        *config.resource_types_mut() = self.0.resource_types.load();
+        config
+    }
+
+    /// Replaces the set of resource types the cache processes. The swap is atomic and does not
+    /// lock.
+    ///
+    /// Each event checks the flags that are current at the time it is
+    /// processed, so events being processed concurrently with this call may
+    /// be handled under either the old or the new set.
+    pub fn set_resource_types(&self, resource_types: ResourceType) {
+        self.0.resource_types.store(resource_types);
+    }
+
+    /// Sets the observer notified of changes to the cache, replacing any previous observer.
+    pub fn set_observer(&self, observer: Arc<dyn CacheObserver>) {
+        *self.0.observer.write().unwrap_or_else(PoisonError::into_inner) = Some(observer);
+    }
+
+    fn observer(&self) -> Option<Arc<dyn CacheObserver>> {
+        self.0.observer.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Notifies the observer, if any, that each of the voice channels is now empty. Must not be
+    /// called with any of the cache's locks held.
+    fn notify_voice_channels_empty(&self, channel_ids: impl IntoIterator<Item = ChannelId>) {
+        if let Some(observer) = self.observer() {
+            for channel_id in channel_ids {
+                observer.on_voice_channel_empty(channel_id);
+            }
+        }
     }
 
     /// Update the cache with an event from the gateway.
@@ -184,13 +329,63 @@ impl InMemoryCache {
         value.update(self);
     }
 
+    /// Update the cache with an event from the gateway, reporting what was changed.
+    ///
+    /// See [`UpdateCache::update_returning`].
+    pub fn update_returning(&self, value: &impl UpdateCache) -> Option<CacheUpdate> {
+        value.update_returning(self)
+    }
+
     /// Finds which voice channel a user is in for a given Guild.
     /// This runs O(1) time.
     pub fn voice_state(&self, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
         self.0
             .voice_states
             .get(&(guild_id, user_id))
-            .map(|kv| *kv.value())
+            .map(|kv| kv.value().channel_id)
+    }
+
+    /// Gets the endpoint and token of the voice server the bot's voice connection in a guild is
+    /// assigned to, in that order. Along with the bot's voice session ID, this is needed to
+    /// connect to the voice server.
+    /// This runs O(1) time.
+    pub fn voice_server(&self, guild_id: GuildId) -> Option<(String, String)> {
+        self.0
+            .voice_servers
+            .get(&guild_id)
+            .map(|kv| kv.value().clone())
+    }
+
+    /// Gets the session ID of a user's voice connection in a guild. Along with the
+    /// [`voice_server`], this is needed to connect to voice. Only the current user's sessions
+    /// are kept, so this returns None for any other user.
+    /// This runs O(1) time.
+    ///
+    /// [`voice_server`]: Self::voice_server
+    pub fn voice_session_id(&self, guild_id: GuildId, user_id: UserId) -> Option<String> {
+        self.0
+            .voice_states
+            .get(&(guild_id, user_id))
+            .and_then(|kv| kv.value().session_id.clone())
+    }
+
+    /// Finds which voice channel a member is connected to, i.e. to check that a member is in
+    /// voice before moving them. This is an alias of [`voice_state`].
+    /// This runs O(1) time.
+    ///
+    /// [`voice_state`]: Self::voice_state
+    pub fn member_voice_channel(&self, guild_id: GuildId, user_id: UserId) -> Option<ChannelId> {
+        self.voice_state(guild_id, user_id)
+    }
+
+    /// Checks if a user in voice is able to speak. In stage channels, this distinguishes speakers
+    /// from the audience. Returns None if the user is not in a voice channel.
+    /// This runs O(1) time.
+    pub fn is_speaker(&self, guild_id: GuildId, user_id: UserId) -> Option<bool> {
+        self.0
+            .voice_states
+            .get(&(guild_id, user_id))
+            .map(|kv| !kv.value().suppress)
     }
 
     /// Finds all of the users in a given voice channel.
@@ -202,32 +397,135 @@ impl InMemoryCache {
         self.0
             .voice_states
             .iter()
-            .filter(|kv| *kv.value() == channel_id)
+            .filter(|kv| kv.value().channel_id == channel_id)
             .map(|kv| kv.key().1)
             .collect()
     }
 
+    /// Gets a page of the users in a given voice channel. Users are sorted by ID so that pages
+    /// are consistent between calls.
+    /// This runs O(m log m) time if m is the number of users in the channel.
+    pub fn voice_channel_users_paged(
+        &self,
+        channel_id: ChannelId,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<UserId> {
+        let mut users: Vec<UserId> = match self.0.voice_state_channels.get(&channel_id) {
+            Some(users) => users.iter().copied().collect(),
+            None => return Vec::new(),
+        };
+        users.sort_unstable();
+        users.into_iter().skip(offset).take(limit).collect()
+    }
+
+    /// Gets the number of users in a given voice channel.
+    /// This runs O(1) time.
+    pub fn voice_channel_user_count(&self, channel_id: ChannelId) -> usize {
+        self.0
+            .voice_state_channels
+            .get(&channel_id)
+            .map(|users| users.len())
+            .unwrap_or(0)
+    }
+
     /// Gets a channel by ID.
     ///
     /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
     /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
     pub fn guild_channel(&self, channel_id: ChannelId) -> Option<Arc<GuildChannel>> {
+        self.0.stats.record(StatResource::Channel, self.get_guild_channel(channel_id))
+    }
+
+    // Like the public getters, but not counted in the hit/miss stats, as internal lookups don't
+    // reflect demand from users of the cache.
+    fn get_guild_channel(&self, channel_id: ChannelId) -> Option<Arc<GuildChannel>> {
         self.0
             .channels_guild
             .get(&channel_id)
             .map(|x| Arc::clone(&x.data))
     }
 
+    fn get_member(&self, guild_id: GuildId, user_id: UserId) -> Option<Arc<CachedMember>> {
+        self.0
+            .members
+            .get(&(guild_id, user_id))
+            .map(|r| Arc::clone(r.value()))
+    }
+
+    fn get_role(&self, role_id: RoleId) -> Option<Arc<Role>> {
+        self.0.roles.get(&role_id).map(|role| Arc::clone(&role.data))
+    }
+
+    // Accessing a user still counts as a use when evicting users.
+    fn get_user(&self, user_id: UserId) -> Option<Arc<User>> {
+        let user = self.0.users.get(&user_id).map(|r| Arc::clone(&r.0));
+        if user.is_some() {
+            self.touch_user(user_id);
+        }
+        user
+    }
+
+    /// Gets the raw permission overwrites of a channel, in the order Discord sent them. Returns
+    /// None if the channel is not cached.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn channel_permission_overwrites(
+        &self,
+        channel_id: ChannelId
+    ) -> Option<Vec<PermissionOverwrite>> {
+        self.0
+            .channels_guild
+            .get(&channel_id)
+            .map(|channel| guild_channel_overwrites(&channel.data).to_vec())
+    }
+
+    /// Gets the ID of the guild a channel belongs to.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn channel_guild_id(&self, channel_id: ChannelId) -> Option<GuildId> {
+        self.0.channels_guild.get(&channel_id).map(|x| x.guild_id)
+    }
+
     /// Gets the current user.
     ///
     /// This is an O(1) operation.
     pub fn current_user(&self) -> Option<Arc<CurrentUser>> {
-        self.0
-            .current_user
-            .lock()
-            .expect("current user poisoned")
-            .clone()
+        self.lock_current_user().clone()
+    }
+
+    /// Gets the current user without blocking. Returns None if the current user is not cached
+    /// or is being updated by another thread.
+    ///
+    /// Like [`current_user`], this recovers from a panic on another thread while the current
+    /// user was held rather than propagating it.
+    ///
+    /// This is an O(1) operation.
+    ///
+    /// [`current_user`]: Self::current_user
+    pub fn try_current_user(&self) -> Option<Arc<CurrentUser>> {
+        match self.0.current_user.try_lock() {
+            Ok(user) => user.clone(),
+            Err(TryLockError::Poisoned(poisoned)) => {
+                tracing::warn!("Recovering poisoned current user in the cache.");
+                poisoned.into_inner().clone()
+            }
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Locks the current user, recovering the guard if a panic poisoned the lock. The cached
+    /// value is replaced wholesale, so it is never left partially updated.
+    fn lock_current_user(&self) -> MutexGuard<'_, Option<Arc<CurrentUser>>> {
+        self.0.current_user.lock().unwrap_or_else(|poisoned| {
+            tracing::warn!("Recovering poisoned current user in the cache.");
+            poisoned.into_inner()
+        })
     }
 
     /// Gets an emoji by ID.
@@ -239,6 +537,43 @@ impl InMemoryCache {
         self.0.emojis.get(&emoji_id).map(|x| Arc::clone(&x.data))
     }
 
+    /// Checks if a member of a guild can use one of the guild's custom emojis. Emojis restricted
+    /// to a set of roles can only be used by members with at least one of those roles. Returns
+    /// false if the emoji is unavailable, or if the emoji or member is not cached in the guild.
+    ///
+    /// This is an O(n) operation, where n is the number of roles the emoji is restricted to.
+    /// This requires the [`GUILD_EMOJIS`] and [`GUILD_MEMBERS`] intents.
+    ///
+    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn emoji_usable_by_member(
+        &self,
+        guild_id: GuildId,
+        emoji_id: EmojiId,
+        user_id: UserId,
+    ) -> bool {
+        let emoji = match self.0.emojis.get(&emoji_id) {
+            Some(emoji) if emoji.guild_id == guild_id => Arc::clone(&emoji.data),
+            _ => return false,
+        };
+        let member = match self.get_member(guild_id, user_id) {
+            Some(member) => member,
+            None => return false,
+        };
+
+        emoji.available &&
+            (emoji.roles.is_empty() || emoji.roles.iter().any(|id| member.roles.contains(id)))
+    }
+
+    /// Gets the ID of the guild an emoji belongs to.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_EMOJIS`] intent.
+    ///
+    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
+    pub fn emoji_guild_id(&self, emoji_id: EmojiId) -> Option<GuildId> {
+        self.0.emojis.get(&emoji_id).map(|x| x.guild_id)
+    }
+
     /// Gets a group by ID.
     ///
     /// This is an O(1) operation.
@@ -258,186 +593,1260 @@ impl InMemoryCache {
         self.0.guilds.iter().map(|r| *r.key()).collect()
     }
 
+    /// Gets the number of guilds in the cache, without collecting their IDs.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_count(&self) -> usize {
+        self.0.guilds.len()
+    }
+
     /// Gets a guild by ID.
     ///
     /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
     /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
     pub fn guild(&self, guild_id: GuildId) -> Option<Arc<CachedGuild>> {
-        self.0.guilds.get(&guild_id).map(|r| Arc::clone(r.value()))
+        let guild = self.0.guilds.get(&guild_id).map(|r| Arc::clone(r.value()));
+        self.0.stats.record(StatResource::Guild, guild)
     }
 
-    /// Gets the set of channels in a guild.
+    /// Gets a copy of the features enabled for a guild.
     ///
-    /// This is a O(m) operation, where m is the amount of channels in the
-    /// guild. This requires the [`GUILDS`] intent.
+    /// This is an O(n) operation, where n is the number of features. This requires the
+    /// [`GUILDS`] intent.
     ///
     /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
-    pub fn guild_channels(&self, guild_id: GuildId) -> Option<HashSet<ChannelId>> {
-        self.0
-            .guild_channels
-            .get(&guild_id)
-            .map(|r| r.value().clone())
+    pub fn guild_features(&self, guild_id: GuildId) -> Option<Vec<String>> {
+        self.0.guilds.get(&guild_id).map(|r| r.features.clone())
     }
 
-    /// Gets the set of emojis in a guild.
+    /// Gets the channel a guild moves inactive voice users to.
     ///
-    /// This is a O(m) operation, where m is the amount of emojis in the guild.
-    /// This requires both the [`GUILDS`] and [`GUILD_EMOJIS`] intents.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
     /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
-    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
-    pub fn guild_emojis(&self, guild_id: GuildId) -> Option<HashSet<EmojiId>> {
-        self.0
-            .guild_emojis
-            .get(&guild_id)
-            .map(|r| r.value().clone())
+    pub fn guild_afk_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.0.guilds.get(&guild_id)?.afk_channel_id
     }
 
-    /// Gets the set of members in a guild.
-    ///
-    /// This list may be incomplete if not all members have been cached.
+    /// Gets how long voice users must be inactive before being moved to the AFK channel.
     ///
-    /// This is a O(m) operation, where m is the amount of members in the guild.
-    /// This requires the [`GUILD_MEMBERS`] intent.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
-    pub fn guild_members(&self, guild_id: GuildId) -> Option<HashSet<UserId>> {
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_afk_timeout(&self, guild_id: GuildId) -> Option<Duration> {
         self.0
-            .guild_members
+            .guilds
             .get(&guild_id)
-            .map(|r| r.value().clone())
+            .map(|guild| Duration::from_secs(guild.afk_timeout))
     }
 
-    /// Gets the set of presences in a guild.
+    /// Gets the channel a guild's system messages, like welcome messages, are sent to.
     ///
-    /// This list may be incomplete if not all members have been cached.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// This is a O(m) operation, where m is the amount of members in the guild.
-    /// This requires the [`GUILD_PRESENCES`] intent.
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_system_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.0.guilds.get(&guild_id)?.system_channel_id
+    }
+
+    /// Gets the flags for which system messages are suppressed in a guild.
     ///
-    /// [`GUILD_PRESENCES`]: ::twilight_model::gateway::Intents::GUILD_PRESENCES
-    pub fn guild_online(&self, guild_id: GuildId) -> Option<HashSet<UserId>> {
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_system_channel_flags(&self, guild_id: GuildId) -> Option<SystemChannelFlags> {
         self.0
-            .guild_presences
+            .guilds
             .get(&guild_id)
-            .map(|r| r.value().clone())
+            .map(|guild| guild.system_channel_flags)
     }
 
-    /// Gets the set of roles in a guild.
+    /// Gets the channel a community guild displays its rules in.
     ///
-    /// This is a O(m) operation, where m is the amount of roles in the guild.
-    /// This requires the [`GUILDS`] intent.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
     /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
-    pub fn guild_roles(&self, guild_id: GuildId) -> Option<HashSet<RoleId>> {
-        self.0.guild_roles.get(&guild_id).map(|r| r.value().clone())
+    pub fn guild_rules_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.0.guilds.get(&guild_id)?.rules_channel_id
     }
 
-    /// Gets a member by guild ID and user ID.
+    /// Gets the channel a guild's widget generates invites to.
     ///
-    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
-    pub fn member(&self, guild_id: GuildId, user_id: UserId) -> Option<Arc<CachedMember>> {
-        self.0
-            .members
-            .get(&(guild_id, user_id))
-            .map(|r| Arc::clone(r.value()))
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_widget_channel(&self, guild_id: GuildId) -> Option<ChannelId> {
+        self.0.guilds.get(&guild_id)?.widget_channel_id
     }
 
-    /// Gets a message by channel ID and message ID.
+    /// Gets the CDN URL of a guild's icon. Returns None if the guild is not cached or has no
+    /// icon.
     ///
-    /// This is an O(log n) operation. This requires one or both of the
-    /// [`GUILD_MESSAGES`] or [`DIRECT_MESSAGES`] intents.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// [`GUILD_MESSAGES`]: ::twilight_model::gateway::Intents::GUILD_MESSAGES
-    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
-    pub fn message(
-        &self,
-        channel_id: ChannelId,
-        message_id: MessageId,
-    ) -> Option<Arc<CachedMessage>> {
-        let channel = self.0.messages.get(&channel_id)?;
-
-        channel.get(&message_id).cloned()
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_icon_url(&self, guild_id: GuildId) -> Option<String> {
+        let guild = self.0.guilds.get(&guild_id)?;
+        let hash = guild.icon.as_ref()?;
+        Some(cdn_image_url("icons", guild_id.0, hash))
     }
 
-    /// Gets a presence by, optionally, guild ID, and user ID.
+    /// Gets the CDN URL of a guild's banner. Returns None if the guild is not cached or has no
+    /// banner.
     ///
-    /// This is an O(1) operation. This requires the [`GUILD_PRESENCES`] intent.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// [`GUILD_PRESENCES`]: ::twilight_model::gateway::Intents::GUILD_PRESENCES
-    pub fn presence(&self, guild_id: GuildId, user_id: UserId) -> bool {
-        self.0
-            .guild_presences
-            .get(&guild_id)
-            .map(|p| p.contains(&user_id))
-            .unwrap_or(false)
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_banner_url(&self, guild_id: GuildId) -> Option<String> {
+        let guild = self.0.guilds.get(&guild_id)?;
+        let hash = guild.banner.as_ref()?;
+        Some(cdn_image_url("banners", guild_id.0, hash))
     }
 
-    /// Gets a private channel by ID.
+    /// Checks if a guild's icon is animated. Returns None if the guild is not cached or has no
+    /// icon.
     ///
-    /// This is an O(1) operation. This requires the [`DIRECT_MESSAGES`] intent.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
-    pub fn private_channel(&self, channel_id: ChannelId) -> Option<Arc<PrivateChannel>> {
-        self.0
-            .channels_private
-            .get(&channel_id)
-            .map(|r| Arc::clone(r.value()))
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_icon_is_animated(&self, guild_id: GuildId) -> Option<bool> {
+        let guild = self.0.guilds.get(&guild_id)?;
+        guild.icon.as_deref().map(is_animated_hash)
     }
 
-    /// Gets a role by ID.
+    /// Checks if a guild's banner is animated. Returns None if the guild is not cached or has no
+    /// banner.
     ///
     /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
     /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
-    pub fn role(&self, role_id: RoleId) -> Option<Arc<Role>> {
-        self.0
-            .roles
-            .get(&role_id)
-            .map(|role| Arc::clone(&role.data))
+    pub fn guild_banner_is_animated(&self, guild_id: GuildId) -> Option<bool> {
+        let guild = self.0.guilds.get(&guild_id)?;
+        guild.banner.as_deref().map(is_animated_hash)
     }
 
-    /// Gets a user by ID.
+    /// Gets the invite URL of a guild's vanity URL, i.e. `https://discord.gg/<code>`. Returns None
+    /// if the guild is not cached or has no vanity URL.
     ///
-    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
     ///
-    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
-    pub fn user(&self, user_id: UserId) -> Option<Arc<User>> {
-        self.0.users.get(&user_id).map(|r| Arc::clone(&r.0))
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_vanity_url(&self, guild_id: GuildId) -> Option<String> {
+        let guild = self.0.guilds.get(&guild_id)?;
+        guild.vanity_url_code.as_ref().map(|code| format!("https://discord.gg/{}", code))
     }
 
-    /// Clear the state of the Cache.
+    /// Checks if a guild has a feature enabled (i.e. "COMMUNITY" or "VANITY_URL"). Returns false
+    /// if the guild is not cached.
     ///
-    /// This is equal to creating a new empty cache.
-    pub fn clear(&self) {
-        self.0.channels_guild.clear();
-        self.0.channels_private.clear();
+    /// This is an O(n) operation, where n is the number of features. This requires the
+    /// [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_has_feature(&self, guild_id: GuildId, feature: &str) -> bool {
         self.0
-            .current_user
-            .lock()
-            .expect("current user poisoned")
-            .take();
-        self.0.emojis.clear();
-        self.0.groups.clear();
-        self.0.guilds.clear();
-        self.0.guild_channels.clear();
-        self.0.guild_emojis.clear();
-        self.0.guild_members.clear();
-        self.0.guild_presences.clear();
-        self.0.guild_roles.clear();
-        self.0.members.clear();
-        self.0.messages.clear();
-        self.0.roles.clear();
-        self.0.unavailable_guilds.clear();
-        self.0.users.clear();
-        self.0.voice_states.clear();
+            .guilds
+            .get(&guild_id)
+            .map(|r| r.features.iter().any(|f| f == feature))
+            .unwrap_or(false)
     }
 
-    /// Gets the guild-level permissions for a given member.
-    /// If the guild or any of the roles are not present, this will return
+    /// Gets the set of channels in a guild.
+    ///
+    /// This is a O(m) operation, where m is the amount of channels in the
+    /// guild. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_channels(&self, guild_id: GuildId) -> Option<HashSet<ChannelId>> {
+        self.0
+            .guild_channels
+            .get(&guild_id)
+            .map(|r| r.value().clone())
+    }
+
+    /// Gets all of the channels in a guild of a given type.
+    ///
+    /// Returns `None` if the guild's channels are not cached, and an empty Vec if
+    /// the guild is cached but has no channels of that type.
+    ///
+    /// This is a O(m) operation, where m is the amount of channels in the
+    /// guild. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_channels_of_type(
+        &self,
+        guild_id: GuildId,
+        kind: ChannelType,
+    ) -> Option<Vec<Arc<GuildChannel>>> {
+        let channels = self.0.guild_channels.get(&guild_id)?;
+        Some(
+            channels
+                .iter()
+                .filter_map(|id| self.get_guild_channel(*id))
+                .filter(|channel| guild_channel_kind(channel) == kind)
+                .collect(),
+        )
+    }
+
+    /// Gets every cached guild channel across all guilds, in no particular order.
+    ///
+    /// This is a O(n) operation, where n is the total amount of channels in every guild, and
+    /// allocates a Vec with an entry for each of them. Deployments in many guilds can have
+    /// hundreds of thousands of channels, so prefer [`for_each_guild_channel`] where possible.
+    /// This requires the [`GUILDS`] intent.
+    ///
+    /// [`for_each_guild_channel`]: Self::for_each_guild_channel
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn all_guild_channels(&self) -> Vec<Arc<GuildChannel>> {
+        self.0
+            .channels_guild
+            .iter()
+            .map(|item| Arc::clone(&item.data))
+            .collect()
+    }
+
+    /// Calls `f` with every cached guild channel across all guilds and the ID of the guild it
+    /// belongs to, in no particular order, without collecting them.
+    ///
+    /// This is a O(n) operation, where n is the total amount of channels in every guild. Parts
+    /// of the cache are locked while iterating, so `f` must not modify the cache's channels.
+    /// This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn for_each_guild_channel(&self, mut f: impl FnMut(GuildId, &GuildChannel)) {
+        for item in self.0.channels_guild.iter() {
+            f(item.guild_id, &item.data);
+        }
+    }
+
+    /// Gets all of the channels in a guild in ascending order of position, with ties broken by
+    /// the lower channel ID. Positions are kept current by [`ChannelUpdate`] events, so this
+    /// reflects reorders.
+    ///
+    /// This is a O(m log m) operation, where m is the amount of channels in the
+    /// guild. This requires the [`GUILDS`] intent.
+    ///
+    /// [`ChannelUpdate`]: ::twilight_model::gateway::payload::ChannelUpdate
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_channels_sorted(&self, guild_id: GuildId) -> Option<Vec<Arc<GuildChannel>>> {
+        let mut channels: Vec<Arc<GuildChannel>> = self
+            .0
+            .guild_channels
+            .get(&guild_id)?
+            .iter()
+            .filter_map(|id| self.get_guild_channel(*id))
+            .collect();
+        channels.sort_by_key(|channel| (guild_channel_position(channel), channel.id()));
+        Some(channels)
+    }
+
+    /// Gets the set of emojis in a guild.
+    ///
+    /// This is a O(m) operation, where m is the amount of emojis in the guild.
+    /// This requires both the [`GUILDS`] and [`GUILD_EMOJIS`] intents.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
+    pub fn guild_emojis(&self, guild_id: GuildId) -> Option<HashSet<EmojiId>> {
+        self.0
+            .guild_emojis
+            .get(&guild_id)
+            .map(|r| r.value().clone())
+    }
+
+    /// Checks if a user is banned from a guild.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_BANS`] intent.
+    ///
+    /// [`GUILD_BANS`]: ::twilight_model::gateway::Intents::GUILD_BANS
+    pub fn is_banned(&self, guild_id: GuildId, user_id: UserId) -> bool {
+        self.0
+            .bans
+            .get(&guild_id)
+            .map(|bans| bans.contains(&user_id))
+            .unwrap_or(false)
+    }
+
+    /// Gets the IDs of users banned from a guild.
+    ///
+    /// Only bans seen since startup are tracked, so this list may be incomplete.
+    ///
+    /// This is a O(m) operation, where m is the amount of bans in the guild.
+    /// This requires the [`GUILD_BANS`] intent.
+    ///
+    /// [`GUILD_BANS`]: ::twilight_model::gateway::Intents::GUILD_BANS
+    pub fn guild_bans(&self, guild_id: GuildId) -> Option<Vec<UserId>> {
+        self.0
+            .bans
+            .get(&guild_id)
+            .map(|bans| bans.iter().copied().collect())
+    }
+
+    /// Gets the IDs of the integrations connected to a guild.
+    ///
+    /// The gateway only signals that a guild's integrations changed, not what changed, so these
+    /// must be populated via [`cache_integrations`] after fetching them over HTTP. Returns None if
+    /// the guild's integrations have not been cached or have since changed.
+    ///
+    /// This is a O(m) operation, where m is the amount of integrations in the guild. This
+    /// requires the [`GUILD_INTEGRATIONS`] intent and the [`INTEGRATION`] resource type.
+    ///
+    /// [`cache_integrations`]: Self::cache_integrations
+    /// [`GUILD_INTEGRATIONS`]: ::twilight_model::gateway::Intents::GUILD_INTEGRATIONS
+    /// [`INTEGRATION`]: ResourceType::INTEGRATION
+    pub fn guild_integrations(&self, guild_id: GuildId) -> Option<HashSet<IntegrationId>> {
+        self.0
+            .guild_integrations
+            .get(&guild_id)
+            .map(|r| r.value().clone())
+    }
+
+    /// Replaces the cached set of integrations connected to a guild. Does nothing unless the
+    /// [`INTEGRATION`] resource type is enabled.
+    ///
+    /// [`INTEGRATION`]: ResourceType::INTEGRATION
+    pub fn cache_integrations(
+        &self,
+        guild_id: GuildId,
+        integration_ids: impl IntoIterator<Item = IntegrationId>,
+    ) {
+        if !self.wants(ResourceType::INTEGRATION) {
+            return;
+        }
+
+        self.0
+            .guild_integrations
+            .insert(guild_id, integration_ids.into_iter().collect());
+    }
+
+    /// Gets the number of emojis in a guild. Returns 0 if the guild is not cached.
+    ///
+    /// This is an O(1) operation. This requires both the [`GUILDS`] and [`GUILD_EMOJIS`] intents.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
+    pub fn guild_emoji_count(&self, guild_id: GuildId) -> usize {
+        self.0
+            .guild_emojis
+            .get(&guild_id)
+            .map(|r| r.value().len())
+            .unwrap_or(0)
+    }
+
+    /// Gets the number of static and animated emojis in a guild, in that order. Returns zeros if
+    /// the guild is not cached.
+    ///
+    /// This is a O(m) operation, where m is the amount of emojis in the guild.
+    /// This requires both the [`GUILDS`] and [`GUILD_EMOJIS`] intents.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
+    pub fn guild_emoji_counts(&self, guild_id: GuildId) -> (usize, usize) {
+        let guild_emojis = match self.0.guild_emojis.get(&guild_id) {
+            Some(emojis) => emojis,
+            None => return (0, 0),
+        };
+        let animated = guild_emojis
+            .iter()
+            .filter_map(|id| self.0.emojis.get(id))
+            .filter(|emoji| emoji.data.animated)
+            .count();
+        (guild_emojis.len() - animated, animated)
+    }
+
+    /// Gets the number of emojis in the cache across all guilds.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_EMOJIS`] intent.
+    ///
+    /// [`GUILD_EMOJIS`]: ::twilight_model::gateway::Intents::GUILD_EMOJIS
+    pub fn emoji_count(&self) -> usize {
+        self.0.emojis.len()
+    }
+
+    /// Checks if every member chunk requested for a guild has been received, i.e. the guild's
+    /// member list is fully cached. Returns false if the guild's members were never requested.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn guild_members_complete(&self, guild_id: GuildId) -> bool {
+        self.0
+            .guild_member_chunks
+            .get(&guild_id)
+            .map(|r| r.1.len() as u32 >= r.0)
+            .unwrap_or(false)
+    }
+
+    /// Gets the set of members in a guild.
+    ///
+    /// This list may be incomplete if not all members have been cached.
+    ///
+    /// This is a O(m) operation, where m is the amount of members in the guild.
+    /// This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn guild_members(&self, guild_id: GuildId) -> Option<HashSet<UserId>> {
+        self.0
+            .guild_members
+            .get(&guild_id)
+            .map(|r| r.value().clone())
+    }
+
+    /// Gets the set of presences in a guild.
+    ///
+    /// This list may be incomplete if not all members have been cached.
+    ///
+    /// This is a O(m) operation, where m is the amount of members in the guild.
+    /// This requires the [`GUILD_PRESENCES`] intent.
+    ///
+    /// [`GUILD_PRESENCES`]: ::twilight_model::gateway::Intents::GUILD_PRESENCES
+    pub fn guild_online(&self, guild_id: GuildId) -> Option<HashSet<UserId>> {
+        self.0
+            .guild_presences
+            .get(&guild_id)
+            .map(|r| r.value().clone())
+    }
+
+    /// Gets the users in a guild that currently have a streaming activity.
+    ///
+    /// This is a O(m) operation, where m is the amount of streaming users in the
+    /// guild. This requires the [`GUILD_PRESENCES`] intent.
+    ///
+    /// [`GUILD_PRESENCES`]: ::twilight_model::gateway::Intents::GUILD_PRESENCES
+    pub fn guild_streaming(&self, guild_id: GuildId) -> Vec<UserId> {
+        self.0
+            .guild_streaming
+            .get(&guild_id)
+            .map(|r| r.value().iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Gets the text of a user's custom status. Returns None if the user has no custom status,
+    /// or their presence is not cached.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_PRESENCES`] intent.
+    ///
+    /// [`GUILD_PRESENCES`]: ::twilight_model::gateway::Intents::GUILD_PRESENCES
+    pub fn custom_status(&self, guild_id: GuildId, user_id: UserId) -> Option<String> {
+        self.0
+            .custom_statuses
+            .get(&(guild_id, user_id))
+            .map(|r| r.value().clone())
+    }
+
+    /// Gets the set of roles in a guild.
+    ///
+    /// This is a O(m) operation, where m is the amount of roles in the guild.
+    /// This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_roles(&self, guild_id: GuildId) -> Option<HashSet<RoleId>> {
+        self.0.guild_roles.get(&guild_id).map(|r| r.value().clone())
+    }
+
+    /// Gets all of the roles in a guild in the order Discord displays them:
+    /// highest position first, with ties broken by the lower role ID.
+    ///
+    /// This is a O(m log m) operation, where m is the amount of roles in the
+    /// guild. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_roles_sorted(&self, guild_id: GuildId) -> Option<Vec<Arc<Role>>> {
+        let mut roles: Vec<Arc<Role>> = self
+            .0
+            .guild_roles
+            .get(&guild_id)?
+            .iter()
+            .filter_map(|id| self.get_role(*id))
+            .collect();
+        roles.sort_by(|a, b| b.position.cmp(&a.position).then(a.id.cmp(&b.id)));
+        Some(roles)
+    }
+
+    /// Gets a member by guild ID and user ID.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn member(&self, guild_id: GuildId, user_id: UserId) -> Option<Arc<CachedMember>> {
+        self.0.stats.record(StatResource::Member, self.get_member(guild_id, user_id))
+    }
+
+    /// Gets the name a user is displayed with in a guild: their nickname if they have one,
+    /// otherwise their username. Returns None only if neither the member nor the user is cached.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn member_display_name(&self, guild_id: GuildId, user_id: UserId) -> Option<String> {
+        match self.get_member(guild_id, user_id) {
+            Some(member) => Some(member.nick.clone().unwrap_or_else(|| member.user.name.clone())),
+            None => self.get_user(user_id).map(|user| user.name.clone()),
+        }
+    }
+
+    /// Gets the cached member for the author of a message. Returns None for messages sent outside
+    /// of a guild.
+    ///
+    /// If the member is not yet cached, it will be populated from the partial member attached to
+    /// the message, if present.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn message_author_member(&self, message: &Message) -> Option<Arc<CachedMember>> {
+        let guild_id = message.guild_id?;
+        if let Some(member) = self.get_member(guild_id, message.author.id) {
+            return Some(member);
+        }
+
+        let member = message.member.as_ref()?;
+        if !self.wants(ResourceType::MEMBER) {
+            return None;
+        }
+        let user = self.cache_user(Cow::Borrowed(&message.author), Some(guild_id));
+        Some(self.cache_borrowed_partial_member(guild_id, member, user))
+    }
+
+    /// Optimistically adds a role to a cached member, i.e. after adding it via the API. The
+    /// member update sent by the gateway will eventually reconcile the cached member with the
+    /// actual state. Does nothing if the member is not cached or already has the role.
+    ///
+    /// This is an O(n) operation, where n is the number of roles the member has.
+    pub fn add_member_role(&self, guild_id: GuildId, user_id: UserId, role_id: RoleId) {
+        if let Some(mut member) = self.0.members.get_mut(&(guild_id, user_id)) {
+            if !member.roles.contains(&role_id) {
+                Arc::make_mut(&mut member).roles.push(role_id);
+            }
+        }
+    }
+
+    /// Optimistically removes a role from a cached member, i.e. after removing it via the API.
+    /// The member update sent by the gateway will eventually reconcile the cached member with the
+    /// actual state. Does nothing if the member is not cached or does not have the role.
+    ///
+    /// This is an O(n) operation, where n is the number of roles the member has.
+    pub fn remove_member_role(&self, guild_id: GuildId, user_id: UserId, role_id: RoleId) {
+        if let Some(mut member) = self.0.members.get_mut(&(guild_id, user_id)) {
+            if member.roles.contains(&role_id) {
+                Arc::make_mut(&mut member).roles.retain(|id| *id != role_id);
+            }
+        }
+    }
+
+    /// Gets a message by channel ID and message ID.
+    ///
+    /// This is an O(log n) operation. This requires one or both of the
+    /// [`GUILD_MESSAGES`] or [`DIRECT_MESSAGES`] intents.
+    ///
+    /// [`GUILD_MESSAGES`]: ::twilight_model::gateway::Intents::GUILD_MESSAGES
+    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
+    pub fn message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Option<Arc<CachedMessage>> {
+        let message = self
+            .0
+            .messages
+            .get(&channel_id)
+            .and_then(|channel| channel.get(&message_id).cloned());
+        self.0.stats.record(StatResource::Message, message)
+    }
+
+    /// Gets the reactions on a cached message, with the number of users who added each. Returns
+    /// None if the message is not cached.
+    ///
+    /// This is an O(log n) operation. This requires one or both of the
+    /// [`GUILD_MESSAGE_REACTIONS`] or [`DIRECT_MESSAGE_REACTIONS`] intents.
+    ///
+    /// [`GUILD_MESSAGE_REACTIONS`]: ::twilight_model::gateway::Intents::GUILD_MESSAGE_REACTIONS
+    /// [`DIRECT_MESSAGE_REACTIONS`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGE_REACTIONS
+    pub fn message_reactions(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Option<Vec<MessageReaction>> {
+        let channel = self.0.messages.get(&channel_id)?;
+        channel.get(&message_id).map(|message| message.reactions.clone())
+    }
+
+    /// Gets the users who added a reaction to a message since the [`REACTOR`] resource type was
+    /// enabled. Like [`message_reactions`], only reactions added while the message is cached are
+    /// tracked, and they are dropped once the message leaves the cache. Returns None if no user
+    /// is known to have added the reaction.
+    ///
+    /// This is an O(n) operation, where n is the number of distinct reactions on the message.
+    /// This requires one or both of the [`GUILD_MESSAGE_REACTIONS`] or
+    /// [`DIRECT_MESSAGE_REACTIONS`] intents and the [`REACTOR`] resource type.
+    ///
+    /// [`GUILD_MESSAGE_REACTIONS`]: ::twilight_model::gateway::Intents::GUILD_MESSAGE_REACTIONS
+    /// [`DIRECT_MESSAGE_REACTIONS`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGE_REACTIONS
+    /// [`message_reactions`]: Self::message_reactions
+    /// [`REACTOR`]: ResourceType::REACTOR
+    pub fn message_reactors(
+        &self,
+        message_id: MessageId,
+        emoji: &ReactionType,
+    ) -> Option<HashSet<UserId>> {
+        let reactions = self.0.message_reactors.get(&message_id)?;
+        reactions
+            .iter()
+            .find(|(reaction, _)| reaction == emoji)
+            .map(|(_, users)| users.clone())
+    }
+
+    /// Gets the oldest cached message in a channel, i.e. the one with the smallest ID.
+    ///
+    /// This is an O(log n) operation. This requires one or both of the
+    /// [`GUILD_MESSAGES`] or [`DIRECT_MESSAGES`] intents.
+    ///
+    /// [`GUILD_MESSAGES`]: ::twilight_model::gateway::Intents::GUILD_MESSAGES
+    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
+    pub fn channel_oldest_message(&self, channel_id: ChannelId) -> Option<Arc<CachedMessage>> {
+        self.0
+            .messages
+            .get(&channel_id)
+            .and_then(|messages| messages.values().next().cloned())
+    }
+
+    /// Gets the newest cached message in a channel, i.e. the one with the largest ID.
+    ///
+    /// This is an O(log n) operation. This requires one or both of the
+    /// [`GUILD_MESSAGES`] or [`DIRECT_MESSAGES`] intents.
+    ///
+    /// [`GUILD_MESSAGES`]: ::twilight_model::gateway::Intents::GUILD_MESSAGES
+    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
+    pub fn channel_newest_message(&self, channel_id: ChannelId) -> Option<Arc<CachedMessage>> {
+        self.0
+            .messages
+            .get(&channel_id)
+            .and_then(|messages| messages.values().next_back().cloned())
+    }
+
+    /// Checks if a channel has any webhooks.
+    ///
+    /// The gateway only signals that a channel's webhooks changed, not how, so a channel is
+    /// assumed to have webhooks from its first change until a count is recorded via
+    /// [`cache_webhook_count`]. Channels never seen in either are assumed to have none.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_WEBHOOKS`] intent.
+    ///
+    /// [`cache_webhook_count`]: Self::cache_webhook_count
+    /// [`GUILD_WEBHOOKS`]: ::twilight_model::gateway::Intents::GUILD_WEBHOOKS
+    pub fn channel_has_webhooks(&self, channel_id: ChannelId) -> bool {
+        self.0
+            .channel_webhooks
+            .get(&channel_id)
+            .map(|count| count.map(|count| count > 0).unwrap_or(true))
+            .unwrap_or(false)
+    }
+
+    /// Gets the number of webhooks in a channel. Returns None if the count is unknown, either
+    /// because it was never recorded or because the channel's webhooks have since changed.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_WEBHOOKS`] intent.
+    ///
+    /// [`GUILD_WEBHOOKS`]: ::twilight_model::gateway::Intents::GUILD_WEBHOOKS
+    pub fn channel_webhook_count(&self, channel_id: ChannelId) -> Option<usize> {
+        *self.0.channel_webhooks.get(&channel_id)?
+    }
+
+    /// Records the number of webhooks in a channel, i.e. after fetching them over HTTP.
+    pub fn cache_webhook_count(&self, channel_id: ChannelId, count: usize) {
+        if !self.wants(ResourceType::CHANNEL) {
+            return;
+        }
+
+        self.0.channel_webhooks.insert(channel_id, Some(count));
+    }
+
+    /// Gets the ID of the latest message in a channel.
+    ///
+    /// This reads the `last_message_id` of the cached text or private channel, and falls back to
+    /// the newest message in the message cache for the channel if it's absent.
+    ///
+    /// This is an O(log n) operation. This requires the [`GUILDS`] intent and one or both of the
+    /// [`GUILD_MESSAGES`] or [`DIRECT_MESSAGES`] intents.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    /// [`GUILD_MESSAGES`]: ::twilight_model::gateway::Intents::GUILD_MESSAGES
+    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
+    pub fn channel_last_message_id(&self, channel_id: ChannelId) -> Option<MessageId> {
+        let last_message_id = match self.0.channels_guild.get(&channel_id) {
+            Some(item) => match item.data.as_ref() {
+                GuildChannel::Text(channel) => channel.last_message_id,
+                _ => None,
+            },
+            None => self
+                .0
+                .channels_private
+                .get(&channel_id)
+                .and_then(|channel| channel.last_message_id),
+        };
+
+        last_message_id.or_else(|| {
+            self.0
+                .messages
+                .get(&channel_id)
+                .and_then(|messages| messages.keys().next_back().copied())
+        })
+    }
+
+    /// Gets the total number of messages cached across all channels.
+    ///
+    /// This is an O(n) operation, where n is the number of channels with cached messages.
+    pub fn total_cached_messages(&self) -> usize {
+        self.0.messages.iter().map(|r| r.value().len()).sum()
+    }
+
+    /// Gets the number of distinct users cached. A user in several guilds is counted once.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn unique_user_count(&self) -> usize {
+        self.0.users.len()
+    }
+
+    /// Gets the number of members cached across all guilds. Unlike [`unique_user_count`], a user
+    /// in several guilds is counted once per guild.
+    ///
+    /// This is an O(n) operation, where n is the number of guilds. This requires the
+    /// [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    /// [`unique_user_count`]: Self::unique_user_count
+    pub fn total_member_count(&self) -> usize {
+        self.0.guild_members.iter().map(|r| r.value().len()).sum()
+    }
+
+    /// Gets a rough estimate of the memory used by the cache, in bytes.
+    ///
+    /// This is an approximation: each map's entry count is multiplied by a fixed per-entry size
+    /// derived from the size of the stored types. Heap allocations owned by cached values, like
+    /// names and message contents, are not counted, so the actual usage will be higher.
+    ///
+    /// This is an O(n) operation, where n is the number of guilds and channels in the cache.
+    pub fn estimated_memory_bytes(&self) -> usize {
+        fn index_entries<K: Eq + Hash, T>(map: &DashMap<K, HashSet<T>>) -> usize {
+            map.iter().map(|r| r.value().len()).sum()
+        }
+
+        let messages = self.total_cached_messages() + self.0.message_tombstones.len();
+        let indexes = index_entries(&self.0.bans) +
+            index_entries(&self.0.guild_channels) +
+            index_entries(&self.0.guild_emojis) +
+            index_entries(&self.0.guild_integrations) +
+            index_entries(&self.0.guild_members) +
+            self.0.guild_member_chunks.iter().map(|r| r.value().1.len()).sum::<usize>() +
+            index_entries(&self.0.guild_presences) +
+            index_entries(&self.0.guild_streaming) +
+            index_entries(&self.0.guild_roles) +
+            index_entries(&self.0.voice_state_channels) +
+            self.0
+                .message_reactors
+                .iter()
+                .map(|r| r.value().iter().map(|(_, users)| users.len()).sum::<usize>())
+                .sum::<usize>() +
+            self.0.channel_webhooks.len() +
+            self.0.unavailable_guilds.len();
+
+        self.0.guilds.len() * GUILD_ENTRY_BYTES +
+            self.0.channels_guild.len() * CHANNEL_ENTRY_BYTES +
+            self.0.channels_private.len() * PRIVATE_CHANNEL_ENTRY_BYTES +
+            self.0.groups.len() * GROUP_ENTRY_BYTES +
+            self.0.emojis.len() * EMOJI_ENTRY_BYTES +
+            self.0.roles.len() * ROLE_ENTRY_BYTES +
+            self.0.members.len() * MEMBER_ENTRY_BYTES +
+            self.0.users.len() * USER_ENTRY_BYTES +
+            messages * MESSAGE_ENTRY_BYTES +
+            self.0.voice_states.len() * VOICE_STATE_ENTRY_BYTES +
+            self.0.voice_servers.len() * VOICE_SERVER_ENTRY_BYTES +
+            self.0.custom_statuses.len() * CUSTOM_STATUS_ENTRY_BYTES +
+            self.0.typing.iter().map(|r| r.value().len()).sum::<usize>() * TYPING_ENTRY_BYTES +
+            indexes * INDEX_ENTRY_BYTES
+    }
+
+    /// Gets a deleted message that is still retained as a tombstone.
+    ///
+    /// This is an O(1) operation. This requires deleted message retention to be
+    /// enabled via [`InMemoryCacheBuilder::deleted_message_retention`].
+    pub fn deleted_message(&self, message_id: MessageId) -> Option<Arc<CachedMessage>> {
+        self.0
+            .message_tombstones
+            .get(&message_id)
+            .map(|r| Arc::clone(&r.value().1))
+    }
+
+    /// Removes and returns all deleted message tombstones whose retention window
+    /// has passed as of `now`, ordered by message ID.
+    ///
+    /// This is an O(n) operation, where n is the number of tombstones retained.
+    pub fn drain_expired_tombstones(&self, now: Instant) -> Vec<Arc<CachedMessage>> {
+        let mut expired = Vec::new();
+        self.0.message_tombstones.retain(|_, (expires_at, message)| {
+            if *expires_at <= now {
+                expired.push(Arc::clone(message));
+                false
+            } else {
+                true
+            }
+        });
+        expired.sort_by_key(|message| message.id);
+        expired
+    }
+
+    /// Gets the users who started typing in a channel within the last `within`, ordered by user
+    /// ID. Typing is tracked from when each [`TypingStart`] event is received.
+    ///
+    /// Entries older than `within` are pruned as they are read, so querying a channel with a
+    /// shorter window discards typers that a longer window would have returned.
+    ///
+    /// This is an O(n) operation, where n is the number of users who typed in the channel since
+    /// it was last read. This requires the [`GUILD_MESSAGE_TYPING`] or
+    /// [`DIRECT_MESSAGE_TYPING`] intents and the [`TYPING`] resource type.
+    ///
+    /// [`DIRECT_MESSAGE_TYPING`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGE_TYPING
+    /// [`GUILD_MESSAGE_TYPING`]: ::twilight_model::gateway::Intents::GUILD_MESSAGE_TYPING
+    /// [`TYPING`]: ResourceType::TYPING
+    /// [`TypingStart`]: ::twilight_model::gateway::payload::TypingStart
+    pub fn recently_typing(&self, channel_id: ChannelId, within: Duration) -> Vec<UserId> {
+        self.recently_typing_at(channel_id, within, Instant::now())
+    }
+
+    fn recently_typing_at(
+        &self,
+        channel_id: ChannelId,
+        within: Duration,
+        now: Instant
+    ) -> Vec<UserId> {
+        let mut typers = match self.0.typing.get_mut(&channel_id) {
+            Some(mut typers) => {
+                typers.retain(|_, started| now.saturating_duration_since(*started) <= within);
+                typers.keys().copied().collect::<Vec<_>>()
+            },
+            None => return Vec::new(),
+        };
+        if typers.is_empty() {
+            self.0.typing.remove_if(&channel_id, |_, typers| typers.is_empty());
+        }
+        typers.sort();
+        typers
+    }
+
+    /// Gets a presence by, optionally, guild ID, and user ID.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_PRESENCES`] intent.
+    ///
+    /// [`GUILD_PRESENCES`]: ::twilight_model::gateway::Intents::GUILD_PRESENCES
+    pub fn presence(&self, guild_id: GuildId, user_id: UserId) -> bool {
+        self.0
+            .guild_presences
+            .get(&guild_id)
+            .map(|p| p.contains(&user_id))
+            .unwrap_or(false)
+    }
+
+    /// Gets a private channel by ID.
+    ///
+    /// This is an O(1) operation. This requires the [`DIRECT_MESSAGES`] intent.
+    ///
+    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
+    pub fn private_channel(&self, channel_id: ChannelId) -> Option<Arc<PrivateChannel>> {
+        self.0
+            .channels_private
+            .get(&channel_id)
+            .map(|r| Arc::clone(r.value()))
+    }
+
+    /// Gets a private channel by the ID of its recipient.
+    ///
+    /// A DM only has one other recipient, so this returns the first matching channel.
+    ///
+    /// This is an O(n) operation, where n is the number of cached private channels. This
+    /// requires the [`DIRECT_MESSAGES`] intent.
+    ///
+    /// [`DIRECT_MESSAGES`]: ::twilight_model::gateway::Intents::DIRECT_MESSAGES
+    pub fn private_channel_by_recipient(&self, user_id: UserId) -> Option<Arc<PrivateChannel>> {
+        self.0
+            .channels_private
+            .iter()
+            .find(|r| r.recipients.iter().any(|user| user.id == user_id))
+            .map(|r| Arc::clone(r.value()))
+    }
+
+    /// Gets a role by ID.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn role(&self, role_id: RoleId) -> Option<Arc<Role>> {
+        self.0.stats.record(StatResource::Role, self.get_role(role_id))
+    }
+
+    /// Gets the permissions of a role by ID, without cloning the role.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn role_permissions(&self, role_id: RoleId) -> Option<Permissions> {
+        self.0.roles.get(&role_id).map(|role| role.data.permissions)
+    }
+
+    /// Checks if a role can be mentioned by anyone, without cloning the role.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn role_is_mentionable(&self, role_id: RoleId) -> Option<bool> {
+        self.0.roles.get(&role_id).map(|role| role.data.mentionable)
+    }
+
+    /// Checks if a role's members are displayed separately in the member list, without cloning
+    /// the role.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn role_is_hoisted(&self, role_id: RoleId) -> Option<bool> {
+        self.0.roles.get(&role_id).map(|role| role.data.hoist)
+    }
+
+    /// Gets the ID of the guild a role belongs to.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn role_guild_id(&self, role_id: RoleId) -> Option<GuildId> {
+        self.0.roles.get(&role_id).map(|role| role.guild_id)
+    }
+
+    /// Gets a user by ID.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn user(&self, user_id: UserId) -> Option<Arc<User>> {
+        self.0.stats.record(StatResource::User, self.get_user(user_id))
+    }
+
+    /// Gets several users by ID, i.e. to resolve the users mentioned in a message. The result
+    /// corresponds positionally to `user_ids`, with None for each user that is not cached.
+    ///
+    /// This is an O(n) operation, where n is the number of IDs. This requires the
+    /// [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn users(&self, user_ids: impl IntoIterator<Item = UserId>) -> Vec<Option<Arc<User>>> {
+        user_ids.into_iter().map(|user_id| self.get_user(user_id)).collect()
+    }
+
+    /// Gets the CDN URL of a user's avatar. Users without a custom avatar get the URL of their
+    /// default avatar. Returns None only if the user is not cached.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn user_avatar_url(&self, user_id: UserId) -> Option<String> {
+        let user = self.get_user(user_id)?;
+        let discriminator = user.discriminator.parse::<u16>().unwrap_or(0);
+        Some(avatar_url(user_id, discriminator, user.avatar.as_deref()))
+    }
+
+    /// Removes a message from the cache, returning it if it was cached. This is what handling a
+    /// [`MessageDelete`] event does, so the content of a just deleted message can be captured.
+    /// The message is also retained as a tombstone if deleted message retention is enabled.
+    ///
+    /// This is an O(log n) operation.
+    ///
+    /// [`MessageDelete`]: ::twilight_model::gateway::payload::MessageDelete
+    pub fn delete_message(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+    ) -> Option<Arc<CachedMessage>> {
+        let message = self.0.messages.get_mut(&channel_id)?.remove(&message_id)?;
+        self.tombstone_message(Arc::clone(&message));
+        Some(message)
+    }
+
+    /// Removes all cached messages in a channel.
+    ///
+    /// This is an O(1) operation.
+    pub fn clear_channel_messages(&self, channel_id: ChannelId) {
+        if let Some((_, messages)) = self.0.messages.remove(&channel_id) {
+            self.forget_reactors(messages.keys());
+        }
+    }
+
+    /// Removes all cached messages in every cached channel of a guild.
+    ///
+    /// This is an O(n) operation, where n is the number of channels in the guild. This requires
+    /// the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn clear_guild_messages(&self, guild_id: GuildId) {
+        if let Some(channels) = self.0.guild_channels.get(&guild_id) {
+            for channel_id in channels.iter() {
+                self.clear_channel_messages(*channel_id);
+            }
+        }
+    }
+
+    /// Clear the state of the Cache.
+    ///
+    /// This is equal to creating a new empty cache.
+    pub fn clear(&self) {
+        self.0.bans.clear();
+        self.0.channels_guild.clear();
+        self.0.channels_private.clear();
+        self.0.channel_webhooks.clear();
+        self.lock_current_user().take();
+        self.0.custom_statuses.clear();
+        self.0.emojis.clear();
+        self.0.groups.clear();
+        self.0.guilds.clear();
+        self.0.guild_channels.clear();
+        self.0.guild_emojis.clear();
+        self.0.guild_integrations.clear();
+        self.0.guild_members.clear();
+        self.0.guild_member_chunks.clear();
+        self.0.guild_presences.clear();
+        self.0.guild_streaming.clear();
+        self.0.guild_roles.clear();
+        self.0.members.clear();
+        self.0.messages.clear();
+        self.0.message_reactors.clear();
+        self.0.message_tombstones.clear();
+        self.0.roles.clear();
+        self.0.typing.clear();
+        self.0.unavailable_guilds.clear();
+        self.0.users.clear();
+        self.0.user_access.clear();
+        self.0.voice_states.clear();
+        let voice_channels = self.take_voice_state_channels();
+        self.0.voice_servers.clear();
+        self.notify_voice_channels_empty(voice_channels);
+    }
+
+    /// Clears only the cached state of the given resource types, i.e. to reclaim memory used by
+    /// presences or messages without losing the structure of guilds and their roles. The cache
+    /// continues to process events for the resource types afterwards.
+    ///
+    /// Reactions are stored on their messages, so [`REACTION`] on its own clears nothing. Online
+    /// presences are tracked per guild from each guild's [`GuildCreate`], so clearing
+    /// [`PRESENCE`] keeps tracking guilds that are currently tracked.
+    ///
+    /// [`GuildCreate`]: ::twilight_model::gateway::payload::GuildCreate
+    /// [`PRESENCE`]: ResourceType::PRESENCE
+    /// [`REACTION`]: ResourceType::REACTION
+    pub fn clear_resource(&self, resource: ResourceType) {
+        if resource.contains(ResourceType::BAN) {
+            self.0.bans.clear();
+        }
+        if resource.contains(ResourceType::CHANNEL) {
+            self.0.channels_guild.clear();
+            self.0.channels_private.clear();
+            self.0.channel_webhooks.clear();
+            self.0.groups.clear();
+            self.0.guild_channels.clear();
+        }
+        if resource.contains(ResourceType::EMOJI) {
+            self.0.emojis.clear();
+            self.0.guild_emojis.clear();
+        }
+        if resource.contains(ResourceType::GUILD) {
+            self.0.guilds.clear();
+            self.0.unavailable_guilds.clear();
+        }
+        if resource.contains(ResourceType::INTEGRATION) {
+            self.0.guild_integrations.clear();
+        }
+        if resource.contains(ResourceType::MEMBER) {
+            self.0.guild_members.clear();
+            self.0.guild_member_chunks.clear();
+            self.0.members.clear();
+        }
+        if resource.contains(ResourceType::MESSAGE) {
+            self.0.messages.clear();
+            self.0.message_tombstones.clear();
+            self.0.message_reactors.clear();
+        }
+        if resource.contains(ResourceType::PRESENCE) {
+            self.0.custom_statuses.clear();
+            self.0.guild_streaming.clear();
+            for mut presences in self.0.guild_presences.iter_mut() {
+                presences.value_mut().clear();
+            }
+        }
+        if resource.contains(ResourceType::REACTOR) {
+            self.0.message_reactors.clear();
+        }
+        if resource.contains(ResourceType::ROLE) {
+            self.0.guild_roles.clear();
+            self.0.roles.clear();
+        }
+        if resource.contains(ResourceType::TYPING) {
+            self.0.typing.clear();
+        }
+        if resource.contains(ResourceType::USER_CURRENT) {
+            self.lock_current_user().take();
+        }
+        if resource.contains(ResourceType::USER) {
+            self.0.users.clear();
+            self.0.user_access.clear();
+        }
+        if resource.contains(ResourceType::VOICE_STATE) {
+            self.0.voice_states.clear();
+            let voice_channels = self.take_voice_state_channels();
+            self.0.voice_servers.clear();
+            self.notify_voice_channels_empty(voice_channels);
+        }
+    }
+
+    /// Removes every voice channel from the voice channel index, returning their IDs.
+    fn take_voice_state_channels(&self) -> Vec<ChannelId> {
+        let channel_ids: Vec<ChannelId> =
+            self.0.voice_state_channels.iter().map(|r| *r.key()).collect();
+        for channel_id in &channel_ids {
+            self.0.voice_state_channels.remove(channel_id);
+        }
+        channel_ids
+    }
+
+    /// Marks a user as an additional owner of a guild, i.e. a bot acting on behalf of the
+    /// application team that owns the guild. Additional owners are granted every permission by
+    /// [`guild_permissions`], just like the guild's actual owner.
+    ///
+    /// [`guild_permissions`]: Self::guild_permissions
+    pub fn add_guild_owner(&self, guild_id: GuildId, user_id: UserId) {
+        self.0.extra_owners.entry(guild_id).or_default().insert(user_id);
+    }
+
+    /// Removes a user previously added via [`add_guild_owner`].
+    ///
+    /// [`add_guild_owner`]: Self::add_guild_owner
+    pub fn remove_guild_owner(&self, guild_id: GuildId, user_id: UserId) {
+        if let Some(mut owners) = self.0.extra_owners.get_mut(&guild_id) {
+            owners.remove(&user_id);
+        }
+        self.0
+            .extra_owners
+            .remove_if(&guild_id, |_, owners| owners.is_empty());
+    }
+
+    /// Gets the member that owns a guild. Returns None if either the guild or the owner's member
+    /// is not cached.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] and [`GUILD_MEMBERS`] intents.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn guild_owner(&self, guild_id: GuildId) -> Option<Arc<CachedMember>> {
+        let owner_id = self.0.guilds.get(&guild_id)?.owner_id;
+        self.get_member(guild_id, owner_id)
+    }
+
+    /// Gets up to `limit` of a guild's members, most recently joined first. Members without a
+    /// known join date, i.e. those cached from partial member data, are skipped. Only cached
+    /// members are considered, so this may miss members of guilds that are not fully chunked.
+    ///
+    /// This is a O(m log m) operation, where m is the amount of members in the guild. This
+    /// requires the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn guild_members_by_join_date(
+        &self,
+        guild_id: GuildId,
+        limit: usize,
+    ) -> Vec<Arc<CachedMember>> {
+        let mut members: Vec<_> = match self.0.guild_members.get(&guild_id) {
+            Some(user_ids) => user_ids
+                .iter()
+                .filter_map(|user_id| self.0.members.get(&(guild_id, *user_id)))
+                .filter_map(|member| {
+                    let (date_time, nanos) = timestamp_key(member.joined_at.as_deref()?)?;
+                    Some(((date_time.to_owned(), nanos), Arc::clone(member.value())))
+                })
+                .collect(),
+            None => return Vec::new(),
+        };
+        members.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.user.id.cmp(&b.1.user.id)));
+        members.into_iter().take(limit).map(|(_, member)| member).collect()
+    }
+
+    /// Gets the server boost level of a guild.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_boost_tier(&self, guild_id: GuildId) -> Option<PremiumTier> {
+        self.0.guilds.get(&guild_id).map(|guild| guild.premium_tier)
+    }
+
+    /// Gets the number of server boosts a guild has. Returns None if the guild is not cached or
+    /// Discord did not provide the count.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn guild_boost_count(&self, guild_id: GuildId) -> Option<u64> {
+        self.0.guilds.get(&guild_id)?.premium_subscription_count
+    }
+
+    /// Gets the IDs of the cached members boosting a guild, in ascending order. Members that are
+    /// not cached are not included, so this may be incomplete for guilds that have not been
+    /// fully chunked.
+    ///
+    /// This is a O(m) operation, where m is the amount of members in the guild. This requires
+    /// the [`GUILD_MEMBERS`] intent.
+    ///
+    /// [`GUILD_MEMBERS`]: ::twilight_model::gateway::Intents::GUILD_MEMBERS
+    pub fn guild_boosters(&self, guild_id: GuildId) -> Vec<UserId> {
+        let mut boosters: Vec<UserId> = match self.0.guild_members.get(&guild_id) {
+            Some(members) => members
+                .iter()
+                .filter(|user_id| {
+                    self.0
+                        .members
+                        .get(&(guild_id, **user_id))
+                        .map(|member| member.premium_since.is_some())
+                        .unwrap_or(false)
+                })
+                .copied()
+                .collect(),
+            None => return Vec::new(),
+        };
+        boosters.sort();
+        boosters
+    }
+
+    /// Checks if a user is the owner of a guild, or has been added as an additional owner.
+    ///
+    /// This is an O(1) operation. This requires the [`GUILDS`] intent.
+    ///
+    /// [`GUILDS`]: ::twilight_model::gateway::Intents::GUILDS
+    pub fn is_guild_owner(&self, guild_id: GuildId, user_id: UserId) -> bool {
+        let is_owner = self
+            .0
+            .guilds
+            .get(&guild_id)
+            .map(|guild| guild.owner_id == user_id)
+            .unwrap_or(false);
+        is_owner ||
+            self.0
+                .extra_owners
+                .get(&guild_id)
+                .map(|owners| owners.contains(&user_id))
+                .unwrap_or(false)
+    }
+
+    /// Gets the guild-level permissions for a given member.
+    /// If the guild or any of the roles are not present, this will return
     /// Permissions::empty.
     pub fn guild_permissions<T>(
         &self,
@@ -447,20 +1856,15 @@ impl InMemoryCache {
         where T: Iterator<Item=RoleId>
     {
         // The owner has all permissions.
-        if let Some(guild) = self.guild(guild_id) {
-            if guild.owner_id == user_id {
-                return Permissions::all();
-            }
+        if self.is_guild_owner(guild_id, user_id) {
+            return Permissions::all();
         }
 
         // The everyone role ID is the same as the guild ID.
-        let everyone_perms = self.role(RoleId(guild_id.0))
-            .map(|role| role.permissions)
+        let everyone_perms = self.role_permissions(RoleId(guild_id.0))
             .unwrap_or_else(|| Permissions::empty());
         let perms = role_ids
-                        .map(|id| self.role(id))
-                        .filter_map(|role| role)
-                        .map(|role| role.permissions)
+                        .filter_map(|id| self.role_permissions(id))
                         .fold(everyone_perms, |acc, perm|  acc | perm);
 
         // Administrators by default have every permission enabled.
@@ -471,8 +1875,53 @@ impl InMemoryCache {
         }
     }
 
+    /// Gets the permissions a member has in a guild channel, after applying the channel's
+    /// permission overwrites. Permissions that do not apply to the channel's type are removed, see
+    /// [`channel_type_permissions`]. If the channel is not cached, this will return
+    /// Permissions::empty.
+    pub fn channel_permissions<T>(
+        &self,
+        channel_id: ChannelId,
+        user_id: UserId,
+        role_ids: T) -> Permissions
+        where T: Iterator<Item=RoleId>
+    {
+        let (channel, guild_id) = match self.0.channels_guild.get(&channel_id) {
+            Some(item) => (Arc::clone(&item.data), item.guild_id),
+            None => return Permissions::empty(),
+        };
+        let role_ids: Vec<RoleId> = role_ids.collect();
+        let mut perms = self.guild_permissions(guild_id, user_id, role_ids.iter().copied());
+
+        // Administrators and owners bypass channel overwrites entirely.
+        if !perms.contains(Permissions::ADMINISTRATOR) {
+            let overwrites = guild_channel_overwrites(&channel);
+            let find = |kind: PermissionOverwriteType| {
+                overwrites.iter().filter(move |overwrite| overwrite.kind == kind)
+            };
+
+            // Overwrites apply in order: @everyone, then all roles at once, then the member.
+            for overwrite in find(PermissionOverwriteType::Role(RoleId(guild_id.0))) {
+                perms = (perms - overwrite.deny) | overwrite.allow;
+            }
+            let (mut deny, mut allow) = (Permissions::empty(), Permissions::empty());
+            for role_id in role_ids {
+                for overwrite in find(PermissionOverwriteType::Role(role_id)) {
+                    deny |= overwrite.deny;
+                    allow |= overwrite.allow;
+                }
+            }
+            perms = (perms - deny) | allow;
+            for overwrite in find(PermissionOverwriteType::Member(user_id)) {
+                perms = (perms - overwrite.deny) | overwrite.allow;
+            }
+        }
+
+        perms & channel_type_permissions(guild_channel_kind(&channel))
+    }
+
     fn cache_current_user(&self, mut current_user: CurrentUser) {
-        let mut user = self.0.current_user.lock().expect("current user poisoned");
+        let mut user = self.lock_current_user();
 
         if let Some(mut user) = user.as_mut() {
             if let Some(user) = Arc::get_mut(&mut user) {
@@ -603,7 +2052,9 @@ impl InMemoryCache {
         }
 
         if self.wants(ResourceType::MEMBER) {
-            self.0.guild_members.insert(guild.id, HashSet::new());
+            self.0
+                .guild_members
+                .insert(guild.id, HashSet::with_capacity(guild.members.len()));
             self.cache_members(guild.id, guild.members);
         }
 
@@ -623,15 +2074,23 @@ impl InMemoryCache {
 
         let guild = CachedGuild {
             id: guild.id,
+            afk_channel_id: guild.afk_channel_id,
+            afk_timeout: guild.afk_timeout,
+            banner: guild.banner,
             description: guild.description,
             features: guild.features,
             icon: guild.icon,
             member_count: guild.member_count,
+            name: guild.name,
             owner_id: guild.owner_id,
             premium_subscription_count: guild.premium_subscription_count,
             premium_tier: guild.premium_tier,
+            rules_channel_id: guild.rules_channel_id,
+            system_channel_flags: guild.system_channel_flags,
+            system_channel_id: guild.system_channel_id,
             unavailable: guild.unavailable,
             vanity_url_code: guild.vanity_url_code,
+            widget_channel_id: guild.widget_channel_id,
         };
 
         self.0.unavailable_guilds.remove(&guild.id);
@@ -702,25 +2161,41 @@ impl InMemoryCache {
     }
 
     fn cache_members(&self, guild_id: GuildId, members: impl IntoIterator<Item = Member>) {
+        let members = members.into_iter();
+        // Member chunks can hold thousands of members, so avoid rehashing the guild's member set
+        // repeatedly while they're inserted one at a time.
+        self.0
+            .guild_members
+            .entry(guild_id)
+            .or_default()
+            .reserve(members.size_hint().0);
         for member in members {
             self.cache_member(guild_id, member);
         }
     }
 
+    fn tombstone_message(&self, message: Arc<CachedMessage>) {
+        if let Some(retention) = self.0.config.deleted_message_retention() {
+            self.0
+                .message_tombstones
+                .insert(message.id, (Instant::now() + retention, message));
+        }
+    }
+
     fn cache_presences(&self, guild_id: GuildId, presences: impl IntoIterator<Item = Presence>) {
-        if let Some(mut kv) = self.0.guild_presences.get_mut(&guild_id) {
-            for presence in presences {
-                let user_id = presence_user_id(&presence);
-                if presence.status == Status::Online {
-                    kv.value_mut().insert(user_id);
-                } else {
-                    kv.value_mut().remove(&user_id);
-                }
-            }
+        for presence in presences {
+            let user_id = presence_user_id(&presence);
+            self.cache_presence(guild_id, user_id, presence.status, &presence.activities);
         }
     }
 
-    fn cache_presence(&self, guild_id: GuildId, user_id: UserId, status: Status) -> bool {
+    fn cache_presence(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        status: Status,
+        activities: &[Activity],
+    ) -> bool {
         let online = status == Status::Online;
         if let Some(mut kv) = self.0.guild_presences.get_mut(&guild_id) {
             if online {
@@ -729,6 +2204,33 @@ impl InMemoryCache {
                 kv.value_mut().remove(&user_id);
             }
         }
+
+        let streaming = activities
+            .iter()
+            .any(|activity| activity.kind == ActivityType::Streaming);
+        if streaming {
+            self.0
+                .guild_streaming
+                .entry(guild_id)
+                .or_default()
+                .insert(user_id);
+        } else if let Some(mut kv) = self.0.guild_streaming.get_mut(&guild_id) {
+            kv.value_mut().remove(&user_id);
+        }
+
+        let custom_status = activities
+            .iter()
+            .find(|activity| activity.kind == ActivityType::Custom)
+            .and_then(|activity| activity.state.clone());
+        match custom_status {
+            Some(status) => {
+                self.0.custom_statuses.insert((guild_id, user_id), status);
+            }
+            None => {
+                self.0.custom_statuses.remove(&(guild_id, user_id));
+            }
+        }
+
         online
     }
 
@@ -771,10 +2273,29 @@ impl InMemoryCache {
                     u.1.insert(guild_id);
                 }
 
-                return Arc::clone(&u.value().0);
+                let user = Arc::clone(&u.value().0);
+                drop(u);
+                self.touch_user(user.id);
+                return user;
             }
-            Some(_) | None => {}
+            Some(mut u) => {
+                // Replace the outdated user, but keep the guilds it has already
+                // been seen in.
+                let user = Arc::new(user.into_owned());
+                u.0 = Arc::clone(&user);
+                if let Some(guild_id) = guild_id {
+                    u.1.insert(guild_id);
+                }
+
+                drop(u);
+                self.touch_user(user.id);
+                return user;
+            }
+            None => {}
         }
+        // Users are only stored while they share a guild with the bot, mirroring
+        // their removal on `MemberRemove`. A user seen outside of any guild is
+        // returned but not stored.
         let user = Arc::new(user.into_owned());
         if let Some(guild_id) = guild_id {
             let mut guild_id_set = BTreeSet::new();
@@ -782,11 +2303,65 @@ impl InMemoryCache {
             self.0
                 .users
                 .insert(user.id, (Arc::clone(&user), guild_id_set));
+            self.touch_user(user.id);
+            self.evict_users();
         }
 
         user
     }
 
+    /// Marks a user as recently used, if the number of cached users is capped.
+    fn touch_user(&self, user_id: UserId) {
+        if self.0.config.max_users().is_some() {
+            self.0.user_access.touch(user_id);
+        }
+    }
+
+    /// Evicts the least recently used users until the number of cached users is within the
+    /// configured cap. This is an O(log n) operation per evicted user, where n is the number of
+    /// cached users.
+    fn evict_users(&self) {
+        let max_users = match self.0.config.max_users() {
+            Some(max_users) => max_users,
+            None => return,
+        };
+
+        while self.0.users.len() > max_users {
+            match self.0.user_access.pop_least_recent() {
+                Some(user_id) => self.0.users.remove(&user_id),
+                None => return,
+            };
+        }
+    }
+
+    /// Removes a guild from the guilds a user has been seen in, removing the user entirely once
+    /// it no longer shares any guilds with the bot.
+    fn remove_user_guild(&self, user_id: UserId, guild_id: GuildId) {
+        // Avoid a deadlock by mutating the user, dropping the lock to the map,
+        // and then maybe conditionally removing the user later.
+        match self.0.users.get_mut(&user_id) {
+            Some(mut user) => user.1.remove(&guild_id),
+            None => return,
+        };
+
+        let removed = self
+            .0
+            .users
+            .remove_if(&user_id, |_, (_, guilds)| guilds.is_empty())
+            .is_some();
+        if removed {
+            self.0.user_access.remove(user_id);
+        }
+    }
+
+    /// Only the current user's voice sessions are needed, so everyone else's are dropped.
+    fn voice_session_to_keep(&self, vs: &VoiceState) -> Option<String> {
+        match self.lock_current_user().as_ref() {
+            Some(user) if user.id == vs.user_id => Some(vs.session_id.clone()),
+            _ => None,
+        }
+    }
+
     fn cache_voice_states(&self, voice_states: impl IntoIterator<Item = VoiceState>) {
         for voice_state in voice_states {
             self.cache_voice_state(&voice_state);
@@ -800,9 +2375,100 @@ impl InMemoryCache {
         };
 
         let key = (guild_id, vs.user_id);
-        match vs.channel_id {
-            Some(id) => {self.0.voice_states.insert(key, id);},
-            None => {self.0.voice_states.remove(&key);},
+        let previous = match vs.channel_id {
+            Some(channel_id) => self.0.voice_states.insert(key, CachedVoiceState {
+                channel_id,
+                session_id: self.voice_session_to_keep(vs),
+                suppress: vs.suppress,
+            }),
+            None => self.0.voice_states.remove(&key).map(|(_, state)| state),
+        }
+        .map(|state| state.channel_id);
+
+        if previous == vs.channel_id {
+            return;
+        }
+
+        let mut emptied = None;
+        if let Some(previous) = previous {
+            if let Some(mut users) = self.0.voice_state_channels.get_mut(&previous) {
+                users.remove(&vs.user_id);
+            }
+            emptied = self
+                .0
+                .voice_state_channels
+                .remove_if(&previous, |_, users| users.is_empty())
+                .map(|(channel_id, _)| channel_id);
+        }
+
+        let mut populated = None;
+        if let Some(id) = vs.channel_id {
+            match self.0.voice_state_channels.entry(id) {
+                Entry::Occupied(mut users) => {
+                    users.get_mut().insert(vs.user_id);
+                },
+                Entry::Vacant(entry) => {
+                    entry.insert(std::iter::once(vs.user_id).collect());
+                    populated = Some(id);
+                },
+            }
+        }
+
+        if emptied.is_none() && populated.is_none() {
+            return;
+        }
+        if let Some(observer) = self.observer() {
+            if let Some(channel_id) = emptied {
+                observer.on_voice_channel_empty(channel_id);
+            }
+            if let Some(channel_id) = populated {
+                observer.on_voice_channel_populated(channel_id);
+            }
+        }
+    }
+
+    fn add_reactor(
+        &self,
+        channel_id: ChannelId,
+        message_id: MessageId,
+        emoji: &ReactionType,
+        user_id: UserId,
+    ) {
+        // Only reactions on cached messages are tracked, so that they can be dropped along with
+        // the message. The channel is held until the reactor is added so that the message cannot
+        // be evicted in between.
+        let channel = match self.0.messages.get(&channel_id) {
+            Some(channel) => channel,
+            None => return,
+        };
+        if !channel.contains_key(&message_id) {
+            return;
+        }
+        let mut reactions = self.0.message_reactors.entry(message_id).or_default();
+        match reactions.iter_mut().find(|(reaction, _)| reaction == emoji) {
+            Some((_, users)) => {
+                users.insert(user_id);
+            },
+            None => reactions.push((emoji.clone(), std::iter::once(user_id).collect())),
+        }
+    }
+
+    fn remove_reactors(
+        &self,
+        message_id: MessageId,
+        mut predicate: impl FnMut(&ReactionType, &mut HashSet<UserId>) -> bool,
+    ) {
+        if let Some(mut reactions) = self.0.message_reactors.get_mut(&message_id) {
+            reactions.retain(|(reaction, users)| !predicate(reaction, users) && !users.is_empty());
+        }
+        self.0
+            .message_reactors
+            .remove_if(&message_id, |_, reactions| reactions.is_empty());
+    }
+
+    fn forget_reactors<'a>(&self, message_ids: impl IntoIterator<Item = &'a MessageId>) {
+        for message_id in message_ids {
+            self.0.message_reactors.remove(message_id);
         }
     }
 
@@ -821,6 +2487,11 @@ impl InMemoryCache {
     /// of channels will be deleted.
     fn delete_guild_channel(&self, channel_id: ChannelId) -> Option<Arc<GuildChannel>> {
         let GuildItem { data, guild_id } = self.0.channels_guild.remove(&channel_id)?.1;
+        self.0.channel_webhooks.remove(&channel_id);
+        self.0.typing.remove(&channel_id);
+        if let Some(messages) = self.0.messages.get(&channel_id) {
+            self.forget_reactors(messages.keys());
+        }
 
         if let Some(mut guild_channels) = self.0.guild_channels.get_mut(&guild_id) {
             guild_channels.remove(&channel_id);
@@ -842,7 +2513,116 @@ impl InMemoryCache {
     /// Determine whether the configured cache wants a specific resource to be
     /// processed.
     fn wants(&self, resource_type: ResourceType) -> bool {
-        self.0.config.resource_types().contains(resource_type)
+        self.0.resource_types.load().contains(resource_type)
+    }
+}
+
+/// Builds a Discord CDN URL for an image hash. Animated hashes are prefixed with "a_" and are
+/// served as GIFs.
+fn cdn_image_url(path: &str, id: u64, hash: &str) -> String {
+    let ext = if is_animated_hash(hash) { "gif" } else { "png" };
+    format!("https://cdn.discordapp.com/{}/{}/{}.{}", path, id, hash, ext)
+}
+
+/// Converts an ISO 8601 timestamp from Discord, i.e. "2021-01-01T00:00:00.123+00:00", into a
+/// key that sorts chronologically. Discord always sends UTC timestamps, but fractional seconds
+/// are optional and vary in precision, so they're compared numerically. Returns None if the
+/// timestamp is malformed.
+fn timestamp_key(timestamp: &str) -> Option<(&str, u32)> {
+    let (date_time, rest) = (timestamp.get(..19)?, timestamp.get(19..)?);
+    let valid = date_time.bytes().enumerate().all(|(idx, b)| match idx {
+        4 | 7 => b == b'-',
+        10 => b == b'T',
+        13 | 16 => b == b':',
+        _ => b.is_ascii_digit(),
+    });
+    if !valid {
+        return None;
+    }
+
+    let nanos = match rest.strip_prefix('.') {
+        Some(fraction) => {
+            let digits: String = fraction
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .take(9)
+                .collect();
+            format!("{:0<9}", digits).parse().ok()?
+        }
+        None => 0,
+    };
+    Some((date_time, nanos))
+}
+
+fn is_animated_hash(hash: &str) -> bool {
+    hash.starts_with("a_")
+}
+
+/// Builds the CDN URL of a user's avatar from its hash. Users without a custom avatar get the URL
+/// of their default avatar, which is determined by their discriminator.
+pub fn avatar_url(user_id: UserId, discriminator: u16, avatar: Option<&str>) -> String {
+    match avatar {
+        Some(hash) => cdn_image_url("avatars", user_id.0, hash),
+        None => format!("https://cdn.discordapp.com/embed/avatars/{}.png", discriminator % 5),
+    }
+}
+
+/// Gets the permissions that can apply to a channel of the given type.
+///
+/// - Text, news, and store channels exclude the voice permissions: `CONNECT`, `SPEAK`, `STREAM`,
+///   `USE_VAD`, `PRIORITY_SPEAKER`, `MUTE_MEMBERS`, `DEAFEN_MEMBERS`, and `MOVE_MEMBERS`.
+/// - Voice channels exclude the text permissions: `SEND_MESSAGES`, `SEND_TTS_MESSAGES`,
+///   `MANAGE_MESSAGES`, `EMBED_LINKS`, `ATTACH_FILES`, `READ_MESSAGE_HISTORY`,
+///   `MENTION_EVERYONE`, `USE_EXTERNAL_EMOJIS`, and `ADD_REACTIONS`.
+/// - All other channel types, i.e. categories, can have every permission.
+pub fn channel_type_permissions(kind: ChannelType) -> Permissions {
+    let voice = Permissions::CONNECT |
+        Permissions::SPEAK |
+        Permissions::STREAM |
+        Permissions::USE_VAD |
+        Permissions::PRIORITY_SPEAKER |
+        Permissions::MUTE_MEMBERS |
+        Permissions::DEAFEN_MEMBERS |
+        Permissions::MOVE_MEMBERS;
+    let text = Permissions::SEND_MESSAGES |
+        Permissions::SEND_TTS_MESSAGES |
+        Permissions::MANAGE_MESSAGES |
+        Permissions::EMBED_LINKS |
+        Permissions::ATTACH_FILES |
+        Permissions::READ_MESSAGE_HISTORY |
+        Permissions::MENTION_EVERYONE |
+        Permissions::USE_EXTERNAL_EMOJIS |
+        Permissions::ADD_REACTIONS;
+    match kind {
+        ChannelType::GuildText | ChannelType::GuildNews | ChannelType::GuildStore => {
+            Permissions::all() - voice
+        }
+        ChannelType::GuildVoice => Permissions::all() - text,
+        _ => Permissions::all(),
+    }
+}
+
+fn guild_channel_overwrites(channel: &GuildChannel) -> &[PermissionOverwrite] {
+    match channel {
+        GuildChannel::Category(c) => &c.permission_overwrites,
+        GuildChannel::Text(c) => &c.permission_overwrites,
+        GuildChannel::Voice(c) => &c.permission_overwrites,
+    }
+}
+
+fn guild_channel_position(channel: &GuildChannel) -> i64 {
+    match channel {
+        GuildChannel::Category(c) => c.position,
+        GuildChannel::Text(c) => c.position,
+        GuildChannel::Voice(c) => c.position,
+    }
+}
+
+fn guild_channel_kind(channel: &GuildChannel) -> ChannelType {
+    match channel {
+        GuildChannel::Category(c) => c.kind,
+        GuildChannel::Text(c) => c.kind,
+        GuildChannel::Voice(c) => c.kind,
     }
 }
 
@@ -856,15 +2636,25 @@ pub fn presence_user_id(presence: &Presence) -> UserId {
 #[cfg(test)]
 mod tests {
     use crate::InMemoryCache;
-    use std::borrow::Cow;
+    use super::{
+        channel_type_permissions, CacheObserver, ResourceType, EMOJI_ENTRY_BYTES,
+        INDEX_ENTRY_BYTES, MEMBER_ENTRY_BYTES, USER_ENTRY_BYTES,
+    };
+    use std::{borrow::Cow, sync::{Arc, Mutex}, time::Duration};
     use twilight_model::{
-        channel::{ChannelType, GuildChannel, TextChannel},
-        gateway::payload::{GuildEmojisUpdate, MemberRemove, RoleDelete},
+        channel::{
+            permission_overwrite::{PermissionOverwrite, PermissionOverwriteType},
+            CategoryChannel, ChannelType, GuildChannel, PrivateChannel, TextChannel, VoiceChannel,
+        },
+        gateway::{
+            payload::{GuildEmojisUpdate, MemberRemove, RoleDelete},
+            presence::{Activity, ActivityType, Status},
+        },
         guild::{
             DefaultMessageNotificationLevel, Emoji, ExplicitContentFilter, Guild, Member, MfaLevel,
             Permissions, PremiumTier, Role, SystemChannelFlags, VerificationLevel,
         },
-        id::{ChannelId, EmojiId, GuildId, RoleId, UserId},
+        id::{ChannelId, EmojiId, GuildId, MessageId, RoleId, UserId},
         user::{CurrentUser, User},
         voice::VoiceState,
     };
@@ -886,6 +2676,117 @@ mod tests {
         }
     }
 
+    fn activity(kind: ActivityType) -> Activity {
+        Activity {
+            application_id: None,
+            assets: None,
+            created_at: None,
+            details: None,
+            emoji: None,
+            flags: None,
+            id: None,
+            instance: None,
+            kind,
+            name: "test".to_owned(),
+            party: None,
+            secrets: None,
+            state: None,
+            timestamps: None,
+            url: None,
+        }
+    }
+
+    fn category_channel(guild_id: GuildId, id: ChannelId, position: i64) -> GuildChannel {
+        GuildChannel::Category(CategoryChannel {
+            guild_id: Some(guild_id),
+            id,
+            kind: ChannelType::GuildCategory,
+            name: "category".to_owned(),
+            permission_overwrites: Vec::new(),
+            position,
+        })
+    }
+
+    fn text_channel(guild_id: GuildId, id: ChannelId, position: i64) -> GuildChannel {
+        GuildChannel::Text(TextChannel {
+            guild_id: Some(guild_id),
+            id,
+            kind: ChannelType::GuildText,
+            last_message_id: None,
+            last_pin_timestamp: None,
+            name: "text".to_owned(),
+            nsfw: false,
+            permission_overwrites: Vec::new(),
+            parent_id: None,
+            position,
+            rate_limit_per_user: None,
+            topic: None,
+        })
+    }
+
+    fn voice_channel(guild_id: GuildId, id: ChannelId, position: i64) -> GuildChannel {
+        GuildChannel::Voice(VoiceChannel {
+            bitrate: 64000,
+            guild_id: Some(guild_id),
+            id,
+            kind: ChannelType::GuildVoice,
+            name: "voice".to_owned(),
+            parent_id: None,
+            permission_overwrites: Vec::new(),
+            position,
+            user_limit: None,
+        })
+    }
+
+    fn guild(id: GuildId) -> Guild {
+        Guild {
+            id,
+            afk_channel_id: None,
+            afk_timeout: 300,
+            application_id: None,
+            banner: None,
+            channels: Vec::new(),
+            default_message_notifications: DefaultMessageNotificationLevel::Mentions,
+            description: None,
+            discovery_splash: None,
+            emojis: Vec::new(),
+            explicit_content_filter: ExplicitContentFilter::AllMembers,
+            features: Vec::new(),
+            icon: None,
+            joined_at: Some("".to_owned()),
+            large: false,
+            lazy: Some(true),
+            max_members: Some(50),
+            max_presences: Some(100),
+            member_count: Some(25),
+            members: Vec::new(),
+            mfa_level: MfaLevel::Elevated,
+            name: "guild".to_owned(),
+            owner: Some(false),
+            owner_id: UserId(456),
+            permissions: Some(Permissions::SEND_MESSAGES),
+            preferred_locale: "en-GB".to_owned(),
+            premium_subscription_count: Some(0),
+            premium_tier: PremiumTier::None,
+            presences: Vec::new(),
+            region: "us-east".to_owned(),
+            roles: Vec::new(),
+            splash: None,
+            system_channel_id: None,
+            system_channel_flags: SystemChannelFlags::empty(),
+            rules_channel_id: None,
+            unavailable: false,
+            verification_level: VerificationLevel::VeryHigh,
+            voice_states: Vec::new(),
+            vanity_url_code: None,
+            widget_channel_id: None,
+            widget_enabled: None,
+            max_video_channel_users: None,
+            approximate_member_count: None,
+            approximate_presence_count: None,
+        }
+    }
+
     fn emoji(id: EmojiId, user: Option<User>) -> Emoji {
         Emoji {
             animated: false,
@@ -946,6 +2847,23 @@ mod tests {
         }
     }
 
+    fn voice_state(guild_id: GuildId, channel_id: Option<ChannelId>, user_id: UserId) -> VoiceState {
+        VoiceState {
+            channel_id,
+            deaf: false,
+            guild_id: Some(guild_id),
+            member: None,
+            mute: false,
+            self_deaf: false,
+            self_mute: false,
+            self_stream: false,
+            session_id: "a".to_owned(),
+            suppress: false,
+            token: None,
+            user_id,
+        }
+    }
+
     /// Test retrieval of the current user, notably that it doesn't simply
     /// panic or do anything funny. This is the only synchronous mutex that we
     /// might have trouble with across await points if we're not careful.
@@ -957,6 +2875,26 @@ mod tests {
         assert!(cache.current_user().is_some());
     }
 
+    #[test]
+    fn test_current_user_recovers_from_poison() {
+        let cache = InMemoryCache::new();
+        cache.cache_current_user(current_user(1));
+
+        let poisoner = cache.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = poisoner.0.current_user.lock().unwrap();
+            panic!("poison the current user");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(cache.0.current_user.is_poisoned());
+
+        assert_eq!(cache.try_current_user().unwrap().id, UserId(1));
+        assert_eq!(cache.current_user().unwrap().id, UserId(1));
+        cache.cache_current_user(current_user(2));
+        assert_eq!(cache.try_current_user().unwrap().id, UserId(2));
+    }
+
     #[test]
     fn test_guild_create_channels_have_guild_ids() {
         let mut channels = Vec::new();
@@ -1012,31 +2950,316 @@ mod tests {
             system_channel_flags: SystemChannelFlags::SUPPRESS_JOIN_NOTIFICATIONS,
             rules_channel_id: None,
             unavailable: false,
-            verification_level: VerificationLevel::VeryHigh,
-            voice_states: Vec::new(),
-            vanity_url_code: None,
-            widget_channel_id: None,
-            widget_enabled: None,
-            max_video_channel_users: None,
-            approximate_member_count: None,
-            approximate_presence_count: None,
-        };
+            verification_level: VerificationLevel::VeryHigh,
+            voice_states: Vec::new(),
+            vanity_url_code: None,
+            widget_channel_id: None,
+            widget_enabled: None,
+            max_video_channel_users: None,
+            approximate_member_count: None,
+            approximate_presence_count: None,
+        };
+
+        let cache = InMemoryCache::new();
+        cache.cache_guild(guild);
+
+        let channel = cache.guild_channel(ChannelId(111)).unwrap();
+
+        // The channel was given to the cache without a guild ID, but because
+        // it's part of a guild create, the cache can automatically attach the
+        // guild ID to it. So now, the channel's guild ID is present with the
+        // correct value.
+        match *channel {
+            GuildChannel::Text(ref c) => {
+                assert_eq!(Some(GuildId(123)), c.guild_id);
+            }
+            _ => panic!("{:?}", channel),
+        }
+    }
+
+    #[test]
+    fn test_guild_count() {
+        let cache = InMemoryCache::new();
+        assert_eq!(0, cache.guild_count());
+        cache.cache_guild(guild(GuildId(1)));
+        cache.cache_guild(guild(GuildId(2)));
+        assert_eq!(2, cache.guild_count());
+        assert_eq!(cache.guilds().len(), cache.guild_count());
+    }
+
+    #[test]
+    fn test_guild_features() {
+        let cache = InMemoryCache::new();
+        let mut guild = guild(GuildId(1));
+        guild.features = vec!["COMMUNITY".to_owned(), "VANITY_URL".to_owned()];
+        cache.cache_guild(guild);
+
+        assert!(cache.guild_has_feature(GuildId(1), "COMMUNITY"));
+        assert!(!cache.guild_has_feature(GuildId(1), "BANNER"));
+        assert_eq!(
+            Some(vec!["COMMUNITY".to_owned(), "VANITY_URL".to_owned()]),
+            cache.guild_features(GuildId(1))
+        );
+
+        // Uncached guilds have no features.
+        assert!(!cache.guild_has_feature(GuildId(2), "COMMUNITY"));
+        assert!(cache.guild_features(GuildId(2)).is_none());
+    }
+
+    #[test]
+    fn test_guild_afk() {
+        let cache = InMemoryCache::new();
+        let mut with_afk = guild(GuildId(1));
+        with_afk.afk_channel_id = Some(ChannelId(2));
+        with_afk.afk_timeout = 900;
+        cache.cache_guild(with_afk);
+        cache.cache_guild(guild(GuildId(3)));
+
+        assert_eq!(Some(ChannelId(2)), cache.guild_afk_channel(GuildId(1)));
+        assert_eq!(Some(Duration::from_secs(900)), cache.guild_afk_timeout(GuildId(1)));
+        assert_eq!(None, cache.guild_afk_channel(GuildId(3)));
+        assert_eq!(None, cache.guild_afk_timeout(GuildId(4)));
+    }
+
+    #[test]
+    fn test_guild_system_channel() {
+        let cache = InMemoryCache::new();
+        let mut with_system = guild(GuildId(1));
+        with_system.system_channel_id = Some(ChannelId(2));
+        with_system.system_channel_flags = SystemChannelFlags::SUPPRESS_JOIN_NOTIFICATIONS;
+        cache.cache_guild(with_system);
+        cache.cache_guild(guild(GuildId(3)));
+
+        assert_eq!(Some(ChannelId(2)), cache.guild_system_channel(GuildId(1)));
+        assert_eq!(
+            Some(SystemChannelFlags::SUPPRESS_JOIN_NOTIFICATIONS),
+            cache.guild_system_channel_flags(GuildId(1))
+        );
+        assert_eq!(None, cache.guild_system_channel(GuildId(3)));
+        assert_eq!(Some(SystemChannelFlags::empty()), cache.guild_system_channel_flags(GuildId(3)));
+    }
+
+    #[test]
+    fn test_guild_rules_and_widget_channels() {
+        let cache = InMemoryCache::new();
+        let mut community = guild(GuildId(1));
+        community.rules_channel_id = Some(ChannelId(2));
+        community.widget_channel_id = Some(ChannelId(3));
+        cache.cache_guild(community);
+        cache.cache_guild(guild(GuildId(4)));
+
+        assert_eq!(Some(ChannelId(2)), cache.guild_rules_channel(GuildId(1)));
+        assert_eq!(Some(ChannelId(3)), cache.guild_widget_channel(GuildId(1)));
+        assert_eq!(None, cache.guild_rules_channel(GuildId(4)));
+        assert_eq!(None, cache.guild_widget_channel(GuildId(4)));
+    }
+
+    #[test]
+    fn test_guild_image_urls() {
+        let cache = InMemoryCache::new();
+        let mut with_images = guild(GuildId(1));
+        with_images.icon = Some("a_icon".to_owned());
+        with_images.banner = Some("banner".to_owned());
+        cache.cache_guild(with_images);
+        cache.cache_guild(guild(GuildId(2)));
+
+        assert_eq!(
+            Some("https://cdn.discordapp.com/icons/1/a_icon.gif".to_owned()),
+            cache.guild_icon_url(GuildId(1))
+        );
+        assert_eq!(
+            Some("https://cdn.discordapp.com/banners/1/banner.png".to_owned()),
+            cache.guild_banner_url(GuildId(1))
+        );
+
+        // Missing hashes and uncached guilds have no URL.
+        assert_eq!(None, cache.guild_icon_url(GuildId(2)));
+        assert_eq!(None, cache.guild_banner_url(GuildId(2)));
+        assert_eq!(None, cache.guild_icon_url(GuildId(3)));
+    }
+
+    #[test]
+    fn test_guild_images_animated() {
+        let cache = InMemoryCache::new();
+        let mut with_images = guild(GuildId(1));
+        with_images.icon = Some("a_icon".to_owned());
+        with_images.banner = Some("banner".to_owned());
+        cache.cache_guild(with_images);
+        cache.cache_guild(guild(GuildId(2)));
+
+        assert_eq!(Some(true), cache.guild_icon_is_animated(GuildId(1)));
+        assert_eq!(Some(false), cache.guild_banner_is_animated(GuildId(1)));
+        assert_eq!(None, cache.guild_icon_is_animated(GuildId(2)));
+        assert_eq!(None, cache.guild_banner_is_animated(GuildId(2)));
+        assert_eq!(None, cache.guild_icon_is_animated(GuildId(3)));
+    }
+
+    #[test]
+    fn test_guild_vanity_url() {
+        let cache = InMemoryCache::new();
+        let mut vanity = guild(GuildId(1));
+        vanity.vanity_url_code = Some("touhou".to_owned());
+        cache.cache_guild(vanity);
+        cache.cache_guild(guild(GuildId(2)));
+
+        assert_eq!(
+            cache.guild_vanity_url(GuildId(1)).as_deref(),
+            Some("https://discord.gg/touhou"));
+        assert_eq!(cache.guild_vanity_url(GuildId(2)), None);
+        assert_eq!(cache.guild_vanity_url(GuildId(3)), None);
+    }
+
+    #[test]
+    fn test_max_users() {
+        let cache = InMemoryCache::builder().max_users(2).build();
+        cache.cache_member(GuildId(1), member(UserId(1), GuildId(1)));
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        // Accessing the first user makes the second the least recently used.
+        assert!(cache.user(UserId(1)).is_some());
+
+        cache.cache_member(GuildId(1), member(UserId(3), GuildId(1)));
+        assert_eq!(cache.0.users.len(), 2);
+        assert!(cache.user(UserId(1)).is_some());
+        assert!(cache.user(UserId(2)).is_none());
+        assert!(cache.user(UserId(3)).is_some());
+
+        // The evicted user's member still holds onto the user.
+        assert_eq!(cache.member(GuildId(1), UserId(2)).unwrap().user.id, UserId(2));
+
+        // Users removed by other means stop being tracked as well.
+        cache.update(&twilight_model::gateway::payload::MemberRemove {
+            guild_id: GuildId(1),
+            user: user(UserId(3)),
+        });
+        assert!(cache.user(UserId(3)).is_none());
+        cache.update(&twilight_model::gateway::payload::GuildDelete {
+            id: GuildId(1),
+            unavailable: false,
+        });
+        assert!(cache.0.users.is_empty());
+        assert!(cache.0.user_access.is_empty());
+    }
 
+    #[test]
+    fn test_users() {
         let cache = InMemoryCache::new();
-        cache.cache_guild(guild);
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        cache.cache_member(GuildId(1), member(UserId(4), GuildId(1)));
 
-        let channel = cache.guild_channel(ChannelId(111)).unwrap();
+        let users = cache.users(vec![UserId(4), UserId(3), UserId(2), UserId(4)]);
+        let ids: Vec<Option<UserId>> = users.iter().map(|u| u.as_ref().map(|u| u.id)).collect();
+        assert_eq!(ids, vec![Some(UserId(4)), None, Some(UserId(2)), Some(UserId(4))]);
+        assert!(cache.users(std::iter::empty()).is_empty());
+    }
 
-        // The channel was given to the cache without a guild ID, but because
-        // it's part of a guild create, the cache can automatically attach the
-        // guild ID to it. So now, the channel's guild ID is present with the
-        // correct value.
-        match *channel {
-            GuildChannel::Text(ref c) => {
-                assert_eq!(Some(GuildId(123)), c.guild_id);
-            }
-            _ => panic!("{:?}", channel),
+    #[test]
+    fn test_users_unlimited_by_default() {
+        let cache = InMemoryCache::new();
+        for id in 1..=10 {
+            cache.cache_member(GuildId(1), member(UserId(id), GuildId(1)));
+        }
+        assert_eq!(cache.0.users.len(), 10);
+        assert!(cache.0.user_access.is_empty());
+    }
+
+    #[test]
+    fn test_hit_miss_stats() {
+        let cache = InMemoryCache::builder().hit_miss_stats(true).build();
+        cache.cache_guild(guild(GuildId(1)));
+        cache.cache_role(GuildId(1), role(RoleId(2)));
+
+        assert!(cache.guild(GuildId(1)).is_some());
+        assert!(cache.guild(GuildId(1)).is_some());
+        assert!(cache.guild(GuildId(3)).is_none());
+        assert!(cache.role(RoleId(2)).is_some());
+        assert!(cache.message(ChannelId(4), MessageId(5)).is_none());
+
+        let stats = cache.hit_miss_stats();
+        assert_eq!(stats["guild"], (2, 1));
+        assert_eq!(stats["role"], (1, 0));
+        assert_eq!(stats["message"], (0, 1));
+        assert_eq!(stats["member"], (0, 0));
+        assert_eq!(stats.len(), 6);
+
+        // Lookups made internally by other accessors aren't counted.
+        cache.cache_member(GuildId(1), member(UserId(6), GuildId(1)));
+        assert!(cache.guild_roles_sorted(GuildId(1)).is_some());
+        assert!(cache.guild_owner(GuildId(1)).is_none());
+        assert!(cache.member_display_name(GuildId(1), UserId(6)).is_some());
+        assert!(cache.user_avatar_url(UserId(6)).is_some());
+        assert_eq!(cache.hit_miss_stats(), stats);
+    }
+
+    #[test]
+    fn test_hit_miss_stats_disabled_by_default() {
+        let cache = InMemoryCache::new();
+        cache.cache_guild(guild(GuildId(1)));
+        assert!(cache.guild(GuildId(1)).is_some());
+        assert!(cache.user(UserId(2)).is_none());
+
+        assert!(cache.hit_miss_stats().values().all(|counts| *counts == (0, 0)));
+    }
+
+    #[test]
+    fn test_user_avatar_url() {
+        let cache = InMemoryCache::new();
+        let mut custom = user(UserId(1));
+        custom.avatar = Some("hash".to_owned());
+        let mut animated = user(UserId(2));
+        animated.avatar = Some("a_hash".to_owned());
+        let mut default = user(UserId(3));
+        default.discriminator = "0007".to_owned();
+        for user in vec![custom, animated, default] {
+            cache.cache_user(Cow::Owned(user), Some(GuildId(1)));
         }
+
+        assert_eq!(
+            Some("https://cdn.discordapp.com/avatars/1/hash.png".to_owned()),
+            cache.user_avatar_url(UserId(1))
+        );
+        assert_eq!(
+            Some("https://cdn.discordapp.com/avatars/2/a_hash.gif".to_owned()),
+            cache.user_avatar_url(UserId(2))
+        );
+        assert_eq!(
+            Some("https://cdn.discordapp.com/embed/avatars/2.png".to_owned()),
+            cache.user_avatar_url(UserId(3))
+        );
+        assert_eq!(None, cache.user_avatar_url(UserId(4)));
+    }
+
+    #[test]
+    fn test_item_guild_ids() {
+        let cache = InMemoryCache::new();
+        cache.cache_guild_channel(GuildId(1), text_channel(GuildId(1), ChannelId(2), 0));
+        cache.cache_emojis(GuildId(1), vec![emoji(EmojiId(3), None)]);
+        cache.cache_role(GuildId(4), role(RoleId(5)));
+
+        assert_eq!(Some(GuildId(1)), cache.channel_guild_id(ChannelId(2)));
+        assert_eq!(Some(GuildId(1)), cache.emoji_guild_id(EmojiId(3)));
+        assert_eq!(Some(GuildId(4)), cache.role_guild_id(RoleId(5)));
+
+        assert_eq!(None, cache.channel_guild_id(ChannelId(99)));
+        assert_eq!(None, cache.emoji_guild_id(EmojiId(99)));
+        assert_eq!(None, cache.role_guild_id(RoleId(99)));
+    }
+
+    #[test]
+    fn test_private_channel_by_recipient() {
+        let cache = InMemoryCache::new();
+        cache.cache_private_channel(PrivateChannel {
+            id: ChannelId(1),
+            last_message_id: None,
+            last_pin_timestamp: None,
+            kind: ChannelType::Private,
+            recipients: vec![user(UserId(2))],
+        });
+
+        assert_eq!(
+            ChannelId(1),
+            cache.private_channel_by_recipient(UserId(2)).unwrap().id
+        );
+        assert!(cache.private_channel_by_recipient(UserId(3)).is_none());
     }
 
     #[test]
@@ -1092,6 +3315,52 @@ mod tests {
         assert!(!cache.0.users.contains_key(&user_id));
     }
 
+    #[test]
+    fn test_cache_user_equal_user_new_guild() {
+        let user_id = UserId(2);
+        let cache = InMemoryCache::new();
+        let first = cache.cache_user(Cow::Owned(user(user_id)), Some(GuildId(1)));
+        let second = cache.cache_user(Cow::Owned(user(user_id)), Some(GuildId(3)));
+
+        assert!(Arc::ptr_eq(&first, &second));
+        let entry = cache.0.users.get(&user_id).unwrap();
+        assert!(entry.1.contains(&GuildId(1)));
+        assert!(entry.1.contains(&GuildId(3)));
+    }
+
+    #[test]
+    fn test_cache_user_changed_user_keeps_guilds() {
+        let user_id = UserId(2);
+        let cache = InMemoryCache::new();
+        cache.cache_user(Cow::Owned(user(user_id)), Some(GuildId(1)));
+
+        let mut renamed = user(user_id);
+        renamed.name = "renamed".to_owned();
+        cache.cache_user(Cow::Owned(renamed), Some(GuildId(3)));
+
+        let entry = cache.0.users.get(&user_id).unwrap();
+        assert_eq!("renamed", entry.0.name);
+        assert_eq!(2, entry.1.len());
+    }
+
+    #[test]
+    fn test_cache_user_without_guild() {
+        let user_id = UserId(2);
+        let cache = InMemoryCache::new();
+
+        // Users outside of any guild are not stored.
+        cache.cache_user(Cow::Owned(user(user_id)), None);
+        assert!(cache.user(user_id).is_none());
+
+        // But an already cached user is still updated.
+        cache.cache_user(Cow::Owned(user(user_id)), Some(GuildId(1)));
+        let mut renamed = user(user_id);
+        renamed.name = "renamed".to_owned();
+        cache.cache_user(Cow::Owned(renamed), None);
+        assert_eq!("renamed", cache.user(user_id).unwrap().name);
+        assert_eq!(1, cache.0.users.get(&user_id).unwrap().1.len());
+    }
+
     #[test]
     fn test_voice_state_inserts_and_removes() {
         let cache = InMemoryCache::new();
@@ -1241,6 +3510,236 @@ mod tests {
         assert!(cache.voice_channel_states(ChannelId(0)).is_none());
     }
 
+    #[test]
+    fn test_voice_channel_user_count() {
+        let cache = InMemoryCache::new();
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(3)));
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(4)));
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(5)), UserId(6)));
+        assert_eq!(2, cache.voice_channel_user_count(ChannelId(2)));
+        assert_eq!(1, cache.voice_channel_user_count(ChannelId(5)));
+
+        // Moving between channels updates both counts.
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(5)), UserId(4)));
+        assert_eq!(1, cache.voice_channel_user_count(ChannelId(2)));
+        assert_eq!(2, cache.voice_channel_user_count(ChannelId(5)));
+
+        // Disconnecting the last user removes the channel entirely.
+        cache.cache_voice_state(&voice_state(GuildId(1), None, UserId(3)));
+        assert_eq!(0, cache.voice_channel_user_count(ChannelId(2)));
+        assert!(!cache.0.voice_state_channels.contains_key(&ChannelId(2)));
+    }
+
+    #[test]
+    fn test_voice_channel_observer() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(&'static str, ChannelId)>>);
+
+        impl CacheObserver for Recorder {
+            fn on_voice_channel_empty(&self, channel_id: ChannelId) {
+                self.0.lock().unwrap().push(("empty", channel_id));
+            }
+
+            fn on_voice_channel_populated(&self, channel_id: ChannelId) {
+                self.0.lock().unwrap().push(("populated", channel_id));
+            }
+        }
+
+        let cache = InMemoryCache::new();
+        let recorder = Arc::new(Recorder::default());
+        cache.set_observer(recorder.clone());
+        let take = || std::mem::take(&mut *recorder.0.lock().unwrap());
+
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(3)));
+        assert_eq!(take(), vec![("populated", ChannelId(2))]);
+
+        // Joining, or staying in, an occupied channel changes nothing.
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(4)));
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(4)));
+        assert!(take().is_empty());
+
+        cache.cache_voice_state(&voice_state(GuildId(1), None, UserId(3)));
+        assert!(take().is_empty());
+
+        // Moving the last user out empties one channel and populates the other.
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(5)), UserId(4)));
+        assert_eq!(take(), vec![("empty", ChannelId(2)), ("populated", ChannelId(5))]);
+
+        cache.cache_voice_state(&voice_state(GuildId(1), None, UserId(4)));
+        assert_eq!(take(), vec![("empty", ChannelId(5))]);
+
+        // Removing a guild empties all of its occupied channels, and only those.
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(3)));
+        cache.cache_voice_state(&voice_state(GuildId(6), Some(ChannelId(7)), UserId(3)));
+        take();
+        cache.update(&twilight_model::gateway::payload::GuildDelete {
+            id: GuildId(1),
+            unavailable: false,
+        });
+        assert_eq!(take(), vec![("empty", ChannelId(2))]);
+
+        cache.clear();
+        assert_eq!(take(), vec![("empty", ChannelId(7))]);
+        cache.clear();
+        assert!(take().is_empty());
+    }
+
+    #[test]
+    fn test_voice_channel_users_paged() {
+        let cache = InMemoryCache::new();
+        for id in (1..=5).rev() {
+            cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(id)));
+        }
+
+        assert_eq!(vec![UserId(1), UserId(2)], cache.voice_channel_users_paged(ChannelId(2), 0, 2));
+        assert_eq!(vec![UserId(3), UserId(4)], cache.voice_channel_users_paged(ChannelId(2), 2, 2));
+        assert_eq!(vec![UserId(5)], cache.voice_channel_users_paged(ChannelId(2), 4, 2));
+        assert!(cache.voice_channel_users_paged(ChannelId(2), 6, 2).is_empty());
+        assert!(cache.voice_channel_users_paged(ChannelId(3), 0, 2).is_empty());
+    }
+
+    #[test]
+    fn test_member_voice_channel() {
+        let cache = InMemoryCache::new();
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(3)));
+        assert_eq!(Some(ChannelId(2)), cache.member_voice_channel(GuildId(1), UserId(3)));
+        assert_eq!(None, cache.member_voice_channel(GuildId(1), UserId(4)));
+    }
+
+    #[test]
+    fn test_voice_session_id() {
+        let cache = InMemoryCache::new();
+        cache.cache_current_user(current_user(3));
+        let mut state = voice_state(GuildId(1), Some(ChannelId(2)), UserId(3));
+        state.session_id = "session".to_owned();
+        cache.cache_voice_state(&state);
+        assert_eq!(Some("session".to_owned()), cache.voice_session_id(GuildId(1), UserId(3)));
+
+        // Other users' sessions are not kept.
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(4)));
+        assert_eq!(Some(ChannelId(2)), cache.voice_state(GuildId(1), UserId(4)));
+        assert_eq!(None, cache.voice_session_id(GuildId(1), UserId(4)));
+
+        // Disconnecting drops the session.
+        cache.cache_voice_state(&voice_state(GuildId(1), None, UserId(3)));
+        assert_eq!(None, cache.voice_session_id(GuildId(1), UserId(3)));
+    }
+
+    #[test]
+    fn test_is_speaker() {
+        let cache = InMemoryCache::new();
+        let mut audience = voice_state(GuildId(1), Some(ChannelId(2)), UserId(3));
+        audience.suppress = true;
+        cache.cache_voice_state(&audience);
+        cache.cache_voice_state(&voice_state(GuildId(1), Some(ChannelId(2)), UserId(4)));
+
+        assert_eq!(Some(false), cache.is_speaker(GuildId(1), UserId(3)));
+        assert_eq!(Some(true), cache.is_speaker(GuildId(1), UserId(4)));
+        assert_eq!(None, cache.is_speaker(GuildId(1), UserId(5)));
+
+        // Being invited to speak updates the state without changing channels.
+        audience.suppress = false;
+        cache.cache_voice_state(&audience);
+        assert_eq!(Some(true), cache.is_speaker(GuildId(1), UserId(3)));
+        assert_eq!(2, cache.voice_channel_user_count(ChannelId(2)));
+    }
+
+    #[test]
+    fn test_guild_streaming() {
+        let cache = InMemoryCache::new();
+        let guild_id = GuildId(1);
+        cache.cache_presence(
+            guild_id,
+            UserId(2),
+            Status::Online,
+            &[activity(ActivityType::Playing), activity(ActivityType::Streaming)],
+        );
+        cache.cache_presence(guild_id, UserId(3), Status::Online, &[activity(ActivityType::Playing)]);
+        assert_eq!(vec![UserId(2)], cache.guild_streaming(guild_id));
+
+        // Ending the stream removes the user.
+        cache.cache_presence(guild_id, UserId(2), Status::Online, &[]);
+        assert!(cache.guild_streaming(guild_id).is_empty());
+    }
+
+    #[test]
+    fn test_custom_status() {
+        let cache = InMemoryCache::new();
+        let guild_id = GuildId(1);
+        let mut custom = activity(ActivityType::Custom);
+        custom.state = Some("away".to_owned());
+        cache.cache_presence(guild_id, UserId(2), Status::Online, &[custom]);
+        assert_eq!(Some("away".to_owned()), cache.custom_status(guild_id, UserId(2)));
+        assert_eq!(None, cache.custom_status(GuildId(3), UserId(2)));
+
+        // A presence without a custom activity clears the status.
+        cache.cache_presence(guild_id, UserId(2), Status::Online, &[activity(ActivityType::Playing)]);
+        assert_eq!(None, cache.custom_status(guild_id, UserId(2)));
+    }
+
+    #[test]
+    fn test_all_guild_channels() {
+        let cache = InMemoryCache::new();
+        assert!(cache.all_guild_channels().is_empty());
+
+        cache.cache_guild_channel(GuildId(1), text_channel(GuildId(1), ChannelId(2), 0));
+        cache.cache_guild_channel(GuildId(1), voice_channel(GuildId(1), ChannelId(3), 1));
+        cache.cache_guild_channel(GuildId(4), text_channel(GuildId(4), ChannelId(5), 0));
+
+        let mut ids: Vec<ChannelId> = cache.all_guild_channels().iter().map(|c| c.id()).collect();
+        ids.sort();
+        assert_eq!(ids, vec![ChannelId(2), ChannelId(3), ChannelId(5)]);
+
+        let mut visited = Vec::new();
+        cache.for_each_guild_channel(|guild_id, channel| visited.push((guild_id, channel.id())));
+        visited.sort();
+        assert_eq!(
+            visited,
+            vec![
+                (GuildId(1), ChannelId(2)),
+                (GuildId(1), ChannelId(3)),
+                (GuildId(4), ChannelId(5)),
+            ]);
+    }
+
+    #[test]
+    fn test_guild_channels_of_type() {
+        let cache = InMemoryCache::new();
+        let guild_id = GuildId(1);
+        assert!(cache
+            .guild_channels_of_type(guild_id, ChannelType::GuildText)
+            .is_none());
+
+        cache.cache_guild_channels(
+            guild_id,
+            vec![
+                category_channel(guild_id, ChannelId(10), 0),
+                text_channel(guild_id, ChannelId(11), 1),
+                voice_channel(guild_id, ChannelId(12), 2),
+                voice_channel(guild_id, ChannelId(13), 3),
+            ],
+        );
+
+        let voice = cache
+            .guild_channels_of_type(guild_id, ChannelType::GuildVoice)
+            .unwrap();
+        assert_eq!(2, voice.len());
+        assert!(voice
+            .iter()
+            .all(|c| matches!(**c, GuildChannel::Voice(_))));
+
+        let text = cache
+            .guild_channels_of_type(guild_id, ChannelType::GuildText)
+            .unwrap();
+        assert_eq!(1, text.len());
+        assert_eq!(ChannelId(11), text[0].id());
+
+        assert!(cache
+            .guild_channels_of_type(guild_id, ChannelType::GuildNews)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_cache_role() {
         let cache = InMemoryCache::new();
@@ -1296,6 +3795,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_cache_role_dedup() {
+        let cache = InMemoryCache::new();
+        let first = cache.cache_role(GuildId(1), role(RoleId(2)));
+        let second = cache.cache_role(GuildId(1), role(RoleId(2)));
+
+        // Caching an identical role reuses the existing allocation.
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(1, cache.guild_roles(GuildId(1)).unwrap().len());
+
+        let mut changed = role(RoleId(2));
+        changed.name = "changed".to_owned();
+        let third = cache.cache_role(GuildId(1), changed);
+        assert!(!Arc::ptr_eq(&first, &third));
+        assert_eq!(1, cache.guild_roles(GuildId(1)).unwrap().len());
+    }
+
+    #[test]
+    fn test_guild_roles_sorted() {
+        let cache = InMemoryCache::new();
+        assert!(cache.guild_roles_sorted(GuildId(1)).is_none());
+
+        let positions = [(RoleId(1), 0), (RoleId(2), 2), (RoleId(4), 1), (RoleId(3), 1)];
+        for (id, position) in positions.iter() {
+            let mut role = role(*id);
+            role.position = *position;
+            cache.cache_role(GuildId(1), role);
+        }
+
+        let sorted: Vec<RoleId> = cache
+            .guild_roles_sorted(GuildId(1))
+            .unwrap()
+            .iter()
+            .map(|role| role.id)
+            .collect();
+        assert_eq!(vec![RoleId(2), RoleId(3), RoleId(4), RoleId(1)], sorted);
+    }
+
     #[test]
     fn test_cache_guild_member() {
         let cache = InMemoryCache::new();
@@ -1353,6 +3890,234 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_member_role_changes() {
+        let cache = InMemoryCache::new();
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        let before = cache.member(GuildId(1), UserId(2)).unwrap();
+
+        cache.add_member_role(GuildId(1), UserId(2), RoleId(3));
+        cache.add_member_role(GuildId(1), UserId(2), RoleId(3));
+        assert_eq!(vec![RoleId(3)], cache.member(GuildId(1), UserId(2)).unwrap().roles);
+        // Previously returned members are left untouched.
+        assert!(before.roles.is_empty());
+
+        cache.remove_member_role(GuildId(1), UserId(2), RoleId(3));
+        assert!(cache.member(GuildId(1), UserId(2)).unwrap().roles.is_empty());
+
+        // Uncached members are ignored.
+        cache.add_member_role(GuildId(1), UserId(4), RoleId(3));
+        assert!(cache.member(GuildId(1), UserId(4)).is_none());
+    }
+
+    #[test]
+    fn test_member_display_name() {
+        let cache = InMemoryCache::new();
+        assert!(cache.member_display_name(GuildId(1), UserId(2)).is_none());
+
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        assert_eq!(cache.member_display_name(GuildId(1), UserId(2)).unwrap(), "user");
+
+        let mut nicked = member(UserId(2), GuildId(1));
+        nicked.nick = Some("nick".to_owned());
+        cache.cache_member(GuildId(1), nicked);
+        assert_eq!(cache.member_display_name(GuildId(1), UserId(2)).unwrap(), "nick");
+
+        // Falls back to the user outside of guilds the member is cached in.
+        assert_eq!(cache.member_display_name(GuildId(3), UserId(2)).unwrap(), "user");
+    }
+
+    #[test]
+    fn test_role_permissions() {
+        let cache = InMemoryCache::new();
+        assert!(cache.role_permissions(RoleId(1)).is_none());
+
+        let mut everyone = role(RoleId(1));
+        everyone.permissions = Permissions::SEND_MESSAGES;
+        let mut kick = role(RoleId(2));
+        kick.permissions = Permissions::KICK_MEMBERS;
+        cache.cache_roles(GuildId(1), vec![everyone, kick]);
+        assert_eq!(cache.role_permissions(RoleId(2)), Some(Permissions::KICK_MEMBERS));
+
+        let perms = cache.guild_permissions(
+            GuildId(1), UserId(3), vec![RoleId(2), RoleId(4)].into_iter());
+        assert_eq!(perms, Permissions::SEND_MESSAGES | Permissions::KICK_MEMBERS);
+    }
+
+    #[test]
+    fn test_role_is_mentionable_and_hoisted() {
+        let cache = InMemoryCache::new();
+        assert!(cache.role_is_mentionable(RoleId(1)).is_none());
+        assert!(cache.role_is_hoisted(RoleId(1)).is_none());
+
+        let mut mentionable = role(RoleId(1));
+        mentionable.mentionable = true;
+        let mut hoisted = role(RoleId(2));
+        hoisted.hoist = true;
+        cache.cache_roles(GuildId(1), vec![mentionable, hoisted]);
+
+        assert_eq!(cache.role_is_mentionable(RoleId(1)), Some(true));
+        assert_eq!(cache.role_is_hoisted(RoleId(1)), Some(false));
+        assert_eq!(cache.role_is_mentionable(RoleId(2)), Some(false));
+        assert_eq!(cache.role_is_hoisted(RoleId(2)), Some(true));
+    }
+
+    #[test]
+    fn test_guild_owner() {
+        let cache = InMemoryCache::new();
+        assert!(cache.guild_owner(GuildId(1)).is_none());
+
+        cache.cache_guild(guild(GuildId(1)));
+        assert!(cache.guild_owner(GuildId(1)).is_none());
+
+        cache.cache_member(GuildId(1), member(UserId(456), GuildId(1)));
+        assert_eq!(cache.guild_owner(GuildId(1)).unwrap().user.id, UserId(456));
+    }
+
+    #[test]
+    fn test_guild_members_by_join_date() {
+        let cache = InMemoryCache::new();
+        assert!(cache.guild_members_by_join_date(GuildId(1), 10).is_empty());
+
+        let joined = [
+            (UserId(2), Some("2020-05-01T12:00:00+00:00")),
+            (UserId(3), Some("2021-01-01T00:00:00.5+00:00")),
+            (UserId(4), None),
+            (UserId(5), Some("2021-01-01T00:00:00.123+00:00")),
+            (UserId(6), Some("2019-12-31T23:59:59.999999+00:00")),
+        ];
+        for (user_id, joined_at) in joined.iter() {
+            let mut member = member(*user_id, GuildId(1));
+            member.joined_at = joined_at.map(str::to_owned);
+            cache.cache_member(GuildId(1), member);
+        }
+
+        let ids = |limit| -> Vec<UserId> {
+            cache
+                .guild_members_by_join_date(GuildId(1), limit)
+                .iter()
+                .map(|member| member.user.id)
+                .collect()
+        };
+        assert_eq!(ids(10), vec![UserId(3), UserId(5), UserId(2), UserId(6)]);
+        assert_eq!(ids(2), vec![UserId(3), UserId(5)]);
+        assert!(ids(0).is_empty());
+    }
+
+    #[test]
+    fn test_guild_boosts() {
+        let cache = InMemoryCache::new();
+        assert!(cache.guild_boost_tier(GuildId(1)).is_none());
+        assert!(cache.guild_boosters(GuildId(1)).is_empty());
+
+        let mut boosted = guild(GuildId(1));
+        boosted.premium_tier = PremiumTier::Tier1;
+        boosted.premium_subscription_count = Some(2);
+        cache.cache_guild(boosted);
+        assert_eq!(cache.guild_boost_tier(GuildId(1)), Some(PremiumTier::Tier1));
+        assert_eq!(cache.guild_boost_count(GuildId(1)), Some(2));
+
+        let mut booster = member(UserId(3), GuildId(1));
+        booster.premium_since = Some("2021-01-01T00:00:00+00:00".to_owned());
+        cache.cache_member(GuildId(1), booster);
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        assert_eq!(cache.guild_boosters(GuildId(1)), vec![UserId(3)]);
+    }
+
+    #[test]
+    fn test_channel_permissions_text_channel() {
+        let cache = InMemoryCache::new();
+        let mut everyone = role(RoleId(1));
+        everyone.permissions = Permissions::VIEW_CHANNEL |
+            Permissions::SEND_MESSAGES |
+            Permissions::CONNECT |
+            Permissions::SPEAK;
+        cache.cache_roles(GuildId(1), vec![everyone, role(RoleId(3))]);
+
+        let mut channel = text_channel(GuildId(1), ChannelId(2), 0);
+        if let GuildChannel::Text(ref mut c) = channel {
+            c.permission_overwrites = vec![
+                PermissionOverwrite {
+                    allow: Permissions::empty(),
+                    deny: Permissions::SEND_MESSAGES,
+                    kind: PermissionOverwriteType::Role(RoleId(1)),
+                },
+                PermissionOverwrite {
+                    allow: Permissions::SEND_MESSAGES,
+                    deny: Permissions::empty(),
+                    kind: PermissionOverwriteType::Role(RoleId(3)),
+                },
+            ];
+        }
+        cache.cache_guild_channel(GuildId(1), channel);
+
+        // Voice permissions never apply to text channels.
+        let perms = cache.channel_permissions(ChannelId(2), UserId(4), std::iter::empty());
+        assert_eq!(perms, Permissions::VIEW_CHANNEL);
+        let perms = cache.channel_permissions(ChannelId(2), UserId(4), vec![RoleId(3)].into_iter());
+        assert_eq!(perms, Permissions::VIEW_CHANNEL | Permissions::SEND_MESSAGES);
+
+        let overwrites = cache.channel_permission_overwrites(ChannelId(2)).unwrap();
+        assert_eq!(overwrites.len(), 2);
+        assert_eq!(overwrites[0].kind, PermissionOverwriteType::Role(RoleId(1)));
+        assert_eq!(overwrites[1].allow, Permissions::SEND_MESSAGES);
+        assert!(cache.channel_permission_overwrites(ChannelId(5)).is_none());
+    }
+
+    #[test]
+    fn test_channel_permissions_voice_channel() {
+        let cache = InMemoryCache::new();
+        let mut everyone = role(RoleId(1));
+        everyone.permissions = Permissions::VIEW_CHANNEL |
+            Permissions::SEND_MESSAGES |
+            Permissions::CONNECT |
+            Permissions::SPEAK;
+        cache.cache_role(GuildId(1), everyone);
+        cache.cache_guild_channel(GuildId(1), voice_channel(GuildId(1), ChannelId(2), 0));
+
+        // Text permissions never apply to voice channels.
+        let perms = cache.channel_permissions(ChannelId(2), UserId(4), std::iter::empty());
+        assert_eq!(perms, Permissions::VIEW_CHANNEL | Permissions::CONNECT | Permissions::SPEAK);
+
+        assert_eq!(
+            cache.channel_permissions(ChannelId(5), UserId(4), std::iter::empty()),
+            Permissions::empty());
+    }
+
+    #[test]
+    fn test_channel_type_permissions() {
+        let text = channel_type_permissions(ChannelType::GuildText);
+        assert!(text.contains(Permissions::SEND_MESSAGES));
+        assert!(!text.contains(Permissions::CONNECT));
+
+        let voice = channel_type_permissions(ChannelType::GuildVoice);
+        assert!(voice.contains(Permissions::CONNECT));
+        assert!(!voice.contains(Permissions::SEND_MESSAGES));
+
+        assert_eq!(channel_type_permissions(ChannelType::GuildCategory), Permissions::all());
+    }
+
+    #[test]
+    fn test_guild_permissions_extra_owners() {
+        let cache = InMemoryCache::new();
+        cache.cache_guild(guild(GuildId(1)));
+
+        // Only the actual owner has every permission by default.
+        let owner = cache.guild_permissions(GuildId(1), UserId(456), std::iter::empty());
+        assert_eq!(Permissions::all(), owner);
+        let user = cache.guild_permissions(GuildId(1), UserId(2), std::iter::empty());
+        assert_eq!(Permissions::empty(), user);
+
+        cache.add_guild_owner(GuildId(1), UserId(2));
+        assert!(cache.is_guild_owner(GuildId(1), UserId(2)));
+        let user = cache.guild_permissions(GuildId(1), UserId(2), std::iter::empty());
+        assert_eq!(Permissions::all(), user);
+
+        cache.remove_guild_owner(GuildId(1), UserId(2));
+        assert!(!cache.is_guild_owner(GuildId(1), UserId(2)));
+        assert!(cache.is_guild_owner(GuildId(1), UserId(456)));
+    }
+
     #[test]
     fn test_cache_emoji() {
         let cache = InMemoryCache::new();
@@ -1419,6 +4184,76 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_guild_emoji_counts() {
+        let cache = InMemoryCache::new();
+        assert_eq!(cache.guild_emoji_count(GuildId(1)), 0);
+        assert_eq!(cache.guild_emoji_counts(GuildId(1)), (0, 0));
+
+        let mut animated = emoji(EmojiId(2), None);
+        animated.animated = true;
+        cache.cache_emojis(GuildId(1), vec![emoji(EmojiId(1), None), animated]);
+        cache.cache_emoji(GuildId(1), emoji(EmojiId(3), None));
+        cache.cache_emoji(GuildId(4), emoji(EmojiId(5), None));
+
+        assert_eq!(cache.guild_emoji_count(GuildId(1)), 3);
+        assert_eq!(cache.guild_emoji_counts(GuildId(1)), (2, 1));
+        assert_eq!(cache.guild_emoji_counts(GuildId(4)), (1, 0));
+        assert_eq!(cache.emoji_count(), 4);
+    }
+
+    #[test]
+    fn test_user_and_member_counts() {
+        let cache = InMemoryCache::new();
+        assert_eq!(cache.unique_user_count(), 0);
+        assert_eq!(cache.total_member_count(), 0);
+
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        cache.cache_member(GuildId(1), member(UserId(3), GuildId(1)));
+        cache.cache_member(GuildId(4), member(UserId(2), GuildId(4)));
+        assert_eq!(cache.unique_user_count(), 2);
+        assert_eq!(cache.total_member_count(), 3);
+    }
+
+    #[test]
+    fn test_estimated_memory_bytes() {
+        let cache = InMemoryCache::new();
+        assert_eq!(cache.estimated_memory_bytes(), 0);
+
+        cache.cache_emoji(GuildId(1), emoji(EmojiId(2), None));
+        // The emoji itself and its entry in the guild's emoji index.
+        assert_eq!(cache.estimated_memory_bytes(), EMOJI_ENTRY_BYTES + INDEX_ENTRY_BYTES);
+
+        cache.cache_member(GuildId(1), member(UserId(3), GuildId(1)));
+        assert!(cache.estimated_memory_bytes() >=
+            EMOJI_ENTRY_BYTES + MEMBER_ENTRY_BYTES + USER_ENTRY_BYTES);
+
+        cache.clear();
+        assert_eq!(cache.estimated_memory_bytes(), 0);
+    }
+
+    #[test]
+    fn test_emoji_usable_by_member() {
+        let cache = InMemoryCache::new();
+        let mut restricted = emoji(EmojiId(2), None);
+        restricted.roles = vec![RoleId(3)];
+        cache.cache_emojis(GuildId(1), vec![emoji(EmojiId(1), None), restricted]);
+        cache.cache_member(GuildId(1), member(UserId(4), GuildId(1)));
+        let mut with_role = member(UserId(5), GuildId(1));
+        with_role.roles = vec![RoleId(3)];
+        cache.cache_member(GuildId(1), with_role);
+
+        assert!(cache.emoji_usable_by_member(GuildId(1), EmojiId(1), UserId(4)));
+        assert!(cache.emoji_usable_by_member(GuildId(1), EmojiId(1), UserId(5)));
+        assert!(!cache.emoji_usable_by_member(GuildId(1), EmojiId(2), UserId(4)));
+        assert!(cache.emoji_usable_by_member(GuildId(1), EmojiId(2), UserId(5)));
+
+        // Unknown emojis, members, and emojis from other guilds.
+        assert!(!cache.emoji_usable_by_member(GuildId(1), EmojiId(6), UserId(4)));
+        assert!(!cache.emoji_usable_by_member(GuildId(1), EmojiId(1), UserId(7)));
+        assert!(!cache.emoji_usable_by_member(GuildId(8), EmojiId(1), UserId(4)));
+    }
+
     #[test]
     fn test_clear() {
         let cache = InMemoryCache::new();
@@ -1429,6 +4264,33 @@ mod tests {
         assert!(cache.0.members.is_empty());
     }
 
+    #[test]
+    fn test_clear_resource() {
+        let cache = InMemoryCache::new();
+        cache.cache_guild(guild(GuildId(1)));
+        cache.cache_member(GuildId(1), member(UserId(2), GuildId(1)));
+        cache.cache_roles(GuildId(1), vec![role(RoleId(3))]);
+        cache.cache_presence(GuildId(1), UserId(2), Status::Online, &[]);
+        assert!(cache.presence(GuildId(1), UserId(2)));
+
+        cache.clear_resource(ResourceType::PRESENCE);
+        assert!(!cache.presence(GuildId(1), UserId(2)));
+        assert!(cache.guild(GuildId(1)).is_some());
+        assert!(cache.member(GuildId(1), UserId(2)).is_some());
+        assert!(cache.role(RoleId(3)).is_some());
+
+        // Presences are still tracked for the guild after clearing.
+        cache.cache_presence(GuildId(1), UserId(2), Status::Online, &[]);
+        assert!(cache.presence(GuildId(1), UserId(2)));
+
+        cache.clear_resource(ResourceType::MEMBER | ResourceType::ROLE);
+        assert!(cache.member(GuildId(1), UserId(2)).is_none());
+        assert!(cache.guild_members(GuildId(1)).is_none());
+        assert!(cache.role(RoleId(3)).is_none());
+        assert!(cache.guild(GuildId(1)).is_some());
+        assert!(cache.presence(GuildId(1), UserId(2)));
+    }
+
     #[test]
     fn test_emoji_removal() {
         let cache = InMemoryCache::new();
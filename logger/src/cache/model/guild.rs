@@ -1,19 +1,27 @@
 use serde::Serialize;
 use twilight_model::{
-    guild::PremiumTier,
-    id::{GuildId, UserId},
+    guild::{PremiumTier, SystemChannelFlags},
+    id::{ChannelId, GuildId, UserId},
 };
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize)]
 pub struct CachedGuild {
     pub id: GuildId,
+    pub afk_channel_id: Option<ChannelId>,
+    pub afk_timeout: u64,
+    pub banner: Option<String>,
     pub description: Option<String>,
     pub features: Vec<String>,
     pub icon: Option<String>,
     pub member_count: Option<u64>,
+    pub name: String,
     pub owner_id: UserId,
     pub premium_subscription_count: Option<u64>,
     pub premium_tier: PremiumTier,
+    pub rules_channel_id: Option<ChannelId>,
+    pub system_channel_flags: SystemChannelFlags,
+    pub system_channel_id: Option<ChannelId>,
     pub unavailable: bool,
     pub vanity_url_code: Option<String>,
+    pub widget_channel_id: Option<ChannelId>,
 }
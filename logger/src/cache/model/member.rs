@@ -19,6 +19,26 @@ pub struct CachedMember {
     pub user: Arc<User>,
 }
 
+impl CachedMember {
+    /// Compares the member's roles against a new set of roles, i.e. from a member update.
+    /// Returns the roles that were added and the roles that were removed, in their original
+    /// order.
+    pub fn role_diff(&self, roles: &[RoleId]) -> (Vec<RoleId>, Vec<RoleId>) {
+        let added = roles
+            .iter()
+            .filter(|role| !self.roles.contains(role))
+            .copied()
+            .collect();
+        let removed = self
+            .roles
+            .iter()
+            .filter(|role| !roles.contains(role))
+            .copied()
+            .collect();
+        (added, removed)
+    }
+}
+
 impl PartialEq<Member> for CachedMember {
     fn eq(&self, other: &Member) -> bool {
         (
@@ -103,6 +123,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_role_diff_added() {
+        let mut member = cached_member();
+        member.roles = vec![RoleId(1)];
+        let (added, removed) = member.role_diff(&[RoleId(1), RoleId(2), RoleId(3)]);
+        assert_eq!(added, vec![RoleId(2), RoleId(3)]);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn test_role_diff_removed() {
+        let mut member = cached_member();
+        member.roles = vec![RoleId(1), RoleId(2), RoleId(3)];
+        let (added, removed) = member.role_diff(&[RoleId(2)]);
+        assert!(added.is_empty());
+        assert_eq!(removed, vec![RoleId(1), RoleId(3)]);
+    }
+
+    #[test]
+    fn test_role_diff_mixed() {
+        let mut member = cached_member();
+        member.roles = vec![RoleId(1), RoleId(2)];
+        let (added, removed) = member.role_diff(&[RoleId(3), RoleId(2)]);
+        assert_eq!(added, vec![RoleId(3)]);
+        assert_eq!(removed, vec![RoleId(1)]);
+
+        // Reordering the same roles is not a change.
+        assert_eq!(member.role_diff(&[RoleId(2), RoleId(1)]), (Vec::new(), Vec::new()));
+    }
+
     #[test]
     fn test_eq_member() {
         let member = Member {
@@ -0,0 +1,15 @@
+use serde::Serialize;
+use twilight_model::id::ChannelId;
+
+/// The subset of a user's voice state kept by the cache. Only users that are connected to a
+/// channel have a cached voice state.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct CachedVoiceState {
+    pub channel_id: ChannelId,
+    /// The session ID of the voice connection. Only kept for the current user, which needs it to
+    /// connect to voice.
+    pub session_id: Option<String>,
+    /// Whether the user has been suppressed. In stage channels, suppressed users are part of the
+    /// audience rather than speakers.
+    pub suppress: bool,
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+use twilight_model::id::ChannelId;
+
+/// Receives notifications of changes to the cache as they are made. Every method defaults to a
+/// no-op, so implementors only override the notifications they are interested in.
+///
+/// Notifications are delivered synchronously on the thread processing the event, after the
+/// cache has been updated and without any of its locks held, so an observer may read from the
+/// cache. Long-running work should be handed off to another task.
+///
+/// Set an observer with [`InMemoryCache::set_observer`].
+///
+/// [`InMemoryCache::set_observer`]: crate::cache::InMemoryCache::set_observer
+pub trait CacheObserver: Send + Sync {
+    /// Called when the last user leaves a voice channel, either by disconnecting or by moving
+    /// to another channel. Also called for every occupied voice channel when its guild is
+    /// removed from the cache, or the cache's voice states are cleared.
+    #[allow(unused_variables)]
+    fn on_voice_channel_empty(&self, channel_id: ChannelId) {}
+
+    /// Called when a user joins a voice channel that had no users in it.
+    #[allow(unused_variables)]
+    fn on_voice_channel_populated(&self, channel_id: ChannelId) {}
+}
+
+impl fmt::Debug for dyn CacheObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CacheObserver")
+    }
+}
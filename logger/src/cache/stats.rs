@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+/// The resources whose getters record cache hits and misses.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum StatResource {
+    Guild = 0,
+    User = 1,
+    Member = 2,
+    Message = 3,
+    Role = 4,
+    Channel = 5,
+}
+
+impl StatResource {
+    const ALL: [StatResource; 6] = [
+        StatResource::Guild,
+        StatResource::User,
+        StatResource::Member,
+        StatResource::Message,
+        StatResource::Role,
+        StatResource::Channel,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            StatResource::Guild => "guild",
+            StatResource::User => "user",
+            StatResource::Member => "member",
+            StatResource::Message => "message",
+            StatResource::Role => "role",
+            StatResource::Channel => "channel",
+        }
+    }
+}
+
+/// Counts of cache hits and misses per resource. Nothing is counted unless enabled.
+#[derive(Debug, Default)]
+pub(super) struct HitMissStats {
+    enabled: AtomicBool,
+    counts: [(AtomicU64, AtomicU64); 6],
+}
+
+impl HitMissStats {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Records the result of a lookup as a hit or a miss, passing the result through.
+    pub fn record<T>(&self, resource: StatResource, value: Option<T>) -> Option<T> {
+        if self.enabled.load(Ordering::Relaxed) {
+            let (hits, misses) = &self.counts[resource as usize];
+            let counter = if value.is_some() { hits } else { misses };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        value
+    }
+
+    /// Gets the number of hits and misses, in that order, keyed by resource name.
+    pub fn snapshot(&self) -> HashMap<&'static str, (u64, u64)> {
+        StatResource::ALL
+            .iter()
+            .map(|resource| {
+                let (hits, misses) = &self.counts[*resource as usize];
+                let counts = (hits.load(Ordering::Relaxed), misses.load(Ordering::Relaxed));
+                (resource.name(), counts)
+            })
+            .collect()
+    }
+}
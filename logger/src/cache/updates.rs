@@ -1,17 +1,43 @@
 use super::{config::ResourceType, InMemoryCache};
 use dashmap::DashMap;
-use std::{borrow::Cow, collections::HashSet, hash::Hash, ops::Deref, sync::Arc};
+use std::{borrow::Cow, collections::HashSet, hash::Hash, ops::Deref, sync::Arc, time::Instant};
 use twilight_model::{
     channel::{message::MessageReaction, Channel, GuildChannel, ReactionType},
     gateway::{event::Event, payload::*, presence::UserOrId},
     guild::GuildStatus,
-    id::GuildId,
+    id::{GuildId, RoleId, UserId},
 };
 
+/// A change made to the cache by an event, as reported by [`UpdateCache::update_returning`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CacheUpdate {
+    RoleCreated(GuildId, RoleId),
+    RoleChanged(GuildId, RoleId),
+    RoleDeleted(GuildId, RoleId),
+    MemberJoined(GuildId, UserId),
+    MemberChanged(GuildId, UserId),
+    /// Reported instead of `MemberChanged` if the member's roles changed, along with any other
+    /// changes to the member.
+    MemberRolesChanged {
+        guild_id: GuildId,
+        user_id: UserId,
+        added: Vec<RoleId>,
+        removed: Vec<RoleId>,
+    },
+    MemberLeft(GuildId, UserId),
+}
+
 pub trait UpdateCache {
     // Allow this for presentation purposes in documentation.
     #[allow(unused_variables)]
     fn update(&self, cache: &InMemoryCache) {}
+
+    /// Updates the cache, reporting what was changed. Returns None if the event did not change
+    /// the cache, or if the event does not support reporting changes.
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
+        self.update(cache);
+        None
+    }
 }
 
 impl UpdateCache for Event {
@@ -20,8 +46,8 @@ impl UpdateCache for Event {
         use Event::*;
 
         match self {
-            BanAdd(_) => {}
-            BanRemove(_) => {}
+            BanAdd(v) => c.update(v),
+            BanRemove(v) => c.update(v),
             ChannelCreate(v) => c.update(v),
             ChannelDelete(v) => c.update(v),
             ChannelPinsUpdate(v) => c.update(v),
@@ -73,11 +99,47 @@ impl UpdateCache for Event {
             WebhooksUpdate(v) => c.update(v),
         }
     }
+
+    fn update_returning(&self, c: &InMemoryCache) -> Option<CacheUpdate> {
+        use Event::*;
+
+        match self {
+            MemberAdd(v) => v.update_returning(c),
+            MemberRemove(v) => v.update_returning(c),
+            MemberUpdate(v) => v.update_returning(c),
+            RoleCreate(v) => v.update_returning(c),
+            RoleDelete(v) => v.update_returning(c),
+            RoleUpdate(v) => v.update_returning(c),
+            _ => {
+                self.update(c);
+                None
+            }
+        }
+    }
+}
+
+impl UpdateCache for BanAdd {
+    fn update(&self, cache: &InMemoryCache) {
+        if !cache.wants(ResourceType::BAN) {
+            return;
+        }
+
+        cache.0.bans.entry(self.guild_id).or_default().insert(self.user.id);
+    }
 }
 
-impl UpdateCache for BanAdd {}
+impl UpdateCache for BanRemove {
+    fn update(&self, cache: &InMemoryCache) {
+        if !cache.wants(ResourceType::BAN) {
+            return;
+        }
 
-impl UpdateCache for BanRemove {}
+        if let Some(mut bans) = cache.0.bans.get_mut(&self.guild_id) {
+            bans.remove(&self.user.id);
+        }
+        cache.0.bans.remove_if(&self.guild_id, |_, bans| bans.is_empty());
+    }
+}
 
 impl UpdateCache for ChannelCreate {
     fn update(&self, cache: &InMemoryCache) {
@@ -203,7 +265,19 @@ impl UpdateCache for GuildDelete {
 
         cache.0.guilds.remove(&id);
 
+        if cache.wants(ResourceType::BAN) {
+            cache.0.bans.remove(&id);
+        }
+
         if cache.wants(ResourceType::CHANNEL) {
+            if let Some(channel_ids) = cache.0.guild_channels.get(&id) {
+                for channel_id in channel_ids.iter() {
+                    cache.0.channel_webhooks.remove(channel_id);
+                    if let Some(messages) = cache.0.messages.get(channel_id) {
+                        cache.forget_reactors(messages.keys());
+                    }
+                }
+            }
             remove_ids(&cache.0.guild_channels, &cache.0.channels_guild, id);
         }
 
@@ -211,24 +285,46 @@ impl UpdateCache for GuildDelete {
             remove_ids(&cache.0.guild_emojis, &cache.0.emojis, id);
         }
 
+        if cache.wants(ResourceType::INTEGRATION) {
+            cache.0.guild_integrations.remove(&id);
+        }
+
         if cache.wants(ResourceType::ROLE) {
             remove_ids(&cache.0.guild_roles, &cache.0.roles, id);
         }
 
         if cache.wants(ResourceType::VOICE_STATE) {
-            cache.0.voice_states.retain(|(g, _), _| *g != id);
+            cache.0.voice_servers.remove(&id);
+            let mut channels = HashSet::new();
+            cache.0.voice_states.retain(|(g, _), state| {
+                if *g == id {
+                    channels.insert(state.channel_id);
+                    false
+                } else {
+                    true
+                }
+            });
+            let emptied: Vec<_> = channels
+                .into_iter()
+                .filter(|channel_id| cache.0.voice_state_channels.remove(channel_id).is_some())
+                .collect();
+            cache.notify_voice_channels_empty(emptied);
         }
 
         if cache.wants(ResourceType::MEMBER) {
+            cache.0.guild_member_chunks.remove(&id);
             if let Some((_, ids)) = cache.0.guild_members.remove(&id) {
                 for user_id in ids {
                     cache.0.members.remove(&(id, user_id));
+                    cache.remove_user_guild(user_id, id);
                 }
             }
         }
 
         if cache.wants(ResourceType::PRESENCE) {
             cache.0.guild_presences.remove(&id);
+            cache.0.guild_streaming.remove(&id);
+            cache.0.custom_statuses.retain(|(g, _), _| *g != id);
         }
     }
 }
@@ -243,7 +339,16 @@ impl UpdateCache for GuildEmojisUpdate {
     }
 }
 
-impl UpdateCache for GuildIntegrationsUpdate {}
+impl UpdateCache for GuildIntegrationsUpdate {
+    fn update(&self, cache: &InMemoryCache) {
+        if !cache.wants(ResourceType::INTEGRATION) {
+            return;
+        }
+
+        // The event does not say what changed, so drop the stale set until it is refetched.
+        cache.0.guild_integrations.remove(&self.guild_id);
+    }
+}
 
 impl UpdateCache for GuildUpdate {
     fn update(&self, cache: &InMemoryCache) {
@@ -253,23 +358,35 @@ impl UpdateCache for GuildUpdate {
 
         if let Some(mut guild) = cache.0.guilds.get_mut(&self.0.id) {
             let mut guild = Arc::make_mut(&mut guild);
+            guild.afk_channel_id = self.afk_channel_id;
+            guild.afk_timeout = self.afk_timeout;
+            guild.banner = self.banner.clone();
             guild.description = self.description.clone();
             guild.features = self.features.clone();
             guild.icon = self.icon.clone();
+            guild.name = self.name.clone();
             guild.owner_id = self.owner_id;
             guild.premium_tier = self.premium_tier;
+            guild.rules_channel_id = self.rules_channel_id;
+            guild.system_channel_flags = self.system_channel_flags;
+            guild.system_channel_id = self.system_channel_id;
             guild
                 .premium_subscription_count
                 .replace(self.premium_subscription_count.unwrap_or_default());
             guild.vanity_url_code = self.vanity_url_code.clone();
+            guild.widget_channel_id = self.widget_channel_id;
         };
     }
 }
 
 impl UpdateCache for MemberAdd {
     fn update(&self, cache: &InMemoryCache) {
+        self.update_returning(cache);
+    }
+
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
         if !cache.wants(ResourceType::MEMBER) {
-            return;
+            return None;
         }
 
         cache.cache_member(self.guild_id, self.0.clone());
@@ -280,6 +397,8 @@ impl UpdateCache for MemberAdd {
             .entry(self.guild_id)
             .or_default()
             .insert(self.0.user.id);
+
+        Some(CacheUpdate::MemberJoined(self.guild_id, self.0.user.id))
     }
 }
 
@@ -289,11 +408,22 @@ impl UpdateCache for MemberChunk {
             return;
         }
 
+        {
+            let mut chunks = cache.0.guild_member_chunks.entry(self.guild_id).or_default();
+            // A differing count means this chunk belongs to a new request for the guild.
+            if chunks.0 != self.chunk_count {
+                *chunks = (self.chunk_count, HashSet::new());
+            }
+            chunks.1.insert(self.chunk_index);
+        }
+
         if self.members.is_empty() {
             return;
         }
 
         cache.cache_members(self.guild_id, self.members.clone());
+        // Unchanged members are not reinserted by cache_members, but the guild's member set may
+        // have been reset since they were cached, i.e. by a GuildCreate after a reconnect.
         let mut guild = cache.0.guild_members.entry(self.guild_id).or_default();
         guild.extend(self.members.iter().map(|member| member.user.id));
     }
@@ -301,73 +431,97 @@ impl UpdateCache for MemberChunk {
 
 impl UpdateCache for MemberRemove {
     fn update(&self, cache: &InMemoryCache) {
+        self.update_returning(cache);
+    }
+
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
         if !cache.wants(ResourceType::MEMBER) {
-            return;
+            return None;
         }
 
-        cache.0.members.remove(&(self.guild_id, self.user.id));
+        let removed = cache.0.members.remove(&(self.guild_id, self.user.id)).is_some();
 
         if let Some(mut members) = cache.0.guild_members.get_mut(&self.guild_id) {
             members.remove(&self.user.id);
         }
 
-        // Avoid a deadlock by mutating the user, dropping the lock to the map,
-        // and then maybe conditionally removing the user later.
-        let mut maybe_remove_user = false;
-
-        if let Some(mut user_tuple) = cache.0.users.get_mut(&self.user.id) {
-            user_tuple.1.remove(&self.guild_id);
-
-            maybe_remove_user = true;
-        }
+        cache.remove_user_guild(self.user.id, self.guild_id);
 
-        if maybe_remove_user {
-            cache
-                .0
-                .users
-                .remove_if(&self.user.id, |_, guild_set| guild_set.1.is_empty());
+        if removed {
+            Some(CacheUpdate::MemberLeft(self.guild_id, self.user.id))
+        } else {
+            None
         }
     }
 }
 
 impl UpdateCache for MemberUpdate {
     fn update(&self, cache: &InMemoryCache) {
+        self.update_returning(cache);
+    }
+
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
         if !cache.wants(ResourceType::MEMBER) {
-            return;
+            return None;
         }
 
-        let mut member = match cache.0.members.get_mut(&(self.guild_id, self.user.id)) {
-            Some(member) => member,
-            None => return,
-        };
+        let mut member = cache.0.members.get_mut(&(self.guild_id, self.user.id))?;
+        let unchanged = member.nick == self.nick &&
+            member.roles == self.roles &&
+            member.joined_at.as_ref() == Some(&self.joined_at) &&
+            member.pending == self.pending;
+        if unchanged {
+            return None;
+        }
+
+        let (added, removed) = member.role_diff(&self.roles);
         let mut member = Arc::make_mut(&mut member);
 
         member.nick = self.nick.clone();
         member.roles = self.roles.clone();
         member.joined_at.replace(self.joined_at.clone());
         member.pending = self.pending;
+
+        if added.is_empty() && removed.is_empty() {
+            Some(CacheUpdate::MemberChanged(self.guild_id, self.user.id))
+        } else {
+            Some(CacheUpdate::MemberRolesChanged {
+                guild_id: self.guild_id,
+                user_id: self.user.id,
+                added,
+                removed,
+            })
+        }
     }
 }
 
 impl UpdateCache for MessageCreate {
     fn update(&self, cache: &InMemoryCache) {
-        if !cache.wants(ResourceType::MESSAGE) {
-            return;
-        }
+        if cache.wants(ResourceType::MESSAGE) {
+            let mut channel = cache.0.messages.entry(self.0.channel_id).or_default();
 
-        let mut channel = cache.0.messages.entry(self.0.channel_id).or_default();
+            channel.insert(self.0.id, Arc::new(From::from(self.0.clone())));
 
-        if channel.len() > cache.0.config.message_cache_size() {
-            if let Some(k) = channel.iter().next_back().map(|x| *x.0) {
-                channel.remove(&k);
+            // Evict the oldest messages once the channel is over capacity.
+            let message_cache_size = cache.0.config.message_cache_size();
+            while channel.len() > message_cache_size {
+                if let Some(k) = channel.keys().next().copied() {
+                    channel.remove(&k);
+                    cache.0.message_reactors.remove(&k);
+                }
             }
         }
 
-        channel.insert(self.0.id, Arc::new(From::from(self.0.clone())));
+        let wants_member = cache.wants(ResourceType::MEMBER);
+        if !cache.wants(ResourceType::MESSAGE) && !wants_member {
+            return;
+        }
 
         let user = cache.cache_user(Cow::Borrowed(&self.author), self.guild_id);
 
-        if let (Some(member), Some(guild_id)) = (&self.member, self.guild_id) {
+        // The partial member attached to guild messages is enough to populate the member cache
+        // ahead of any member chunks for the guild.
+        if let (Some(member), Some(guild_id), true) = (&self.member, self.guild_id, wants_member) {
             cache.cache_borrowed_partial_member(guild_id, member, user);
         }
     }
@@ -375,17 +529,26 @@ impl UpdateCache for MessageCreate {
 
 impl UpdateCache for MessageDelete {
     fn update(&self, cache: &InMemoryCache) {
+        if cache.wants(ResourceType::REACTOR) {
+            cache.0.message_reactors.remove(&self.id);
+        }
+
         if !cache.wants(ResourceType::MESSAGE) {
             return;
         }
 
-        let mut channel = cache.0.messages.entry(self.channel_id).or_default();
-        channel.remove(&self.id);
+        cache.delete_message(self.channel_id, self.id);
     }
 }
 
 impl UpdateCache for MessageDeleteBulk {
     fn update(&self, cache: &InMemoryCache) {
+        if cache.wants(ResourceType::REACTOR) {
+            for id in &self.ids {
+                cache.0.message_reactors.remove(id);
+            }
+        }
+
         if !cache.wants(ResourceType::MESSAGE) {
             return;
         }
@@ -393,7 +556,9 @@ impl UpdateCache for MessageDeleteBulk {
         let mut channel = cache.0.messages.entry(self.channel_id).or_default();
 
         for id in &self.ids {
-            channel.remove(id);
+            if let Some(message) = channel.remove(id) {
+                cache.tombstone_message(message);
+            }
         }
     }
 }
@@ -463,12 +628,21 @@ impl UpdateCache for PresenceUpdate {
             UserOrId::UserId { id } => id,
         };
 
-        cache.cache_presence(self.guild_id, user_id, self.status);
+        cache.cache_presence(self.guild_id, user_id, self.status, &self.activities);
     }
 }
 
 impl UpdateCache for ReactionAdd {
     fn update(&self, cache: &InMemoryCache) {
+        if cache.wants(ResourceType::REACTOR) {
+            cache.add_reactor(
+                self.0.channel_id,
+                self.0.message_id,
+                &self.0.emoji,
+                self.0.user_id,
+            );
+        }
+
         if !cache.wants(ResourceType::REACTION) {
             return;
         }
@@ -509,6 +683,15 @@ impl UpdateCache for ReactionAdd {
 
 impl UpdateCache for ReactionRemove {
     fn update(&self, cache: &InMemoryCache) {
+        if cache.wants(ResourceType::REACTOR) {
+            cache.remove_reactors(self.0.message_id, |emoji, users| {
+                if *emoji == self.0.emoji {
+                    users.remove(&self.0.user_id);
+                }
+                false
+            });
+        }
+
         if !cache.wants(ResourceType::REACTION) {
             return;
         }
@@ -542,6 +725,10 @@ impl UpdateCache for ReactionRemove {
 
 impl UpdateCache for ReactionRemoveAll {
     fn update(&self, cache: &InMemoryCache) {
+        if cache.wants(ResourceType::REACTOR) {
+            cache.0.message_reactors.remove(&self.message_id);
+        }
+
         if !cache.wants(ResourceType::REACTION) {
             return;
         }
@@ -560,6 +747,12 @@ impl UpdateCache for ReactionRemoveAll {
 
 impl UpdateCache for ReactionRemoveEmoji {
     fn update(&self, cache: &InMemoryCache) {
+        if cache.wants(ResourceType::REACTOR) {
+            cache.remove_reactors(self.message_id, |emoji, _| {
+                emoji_has_name(emoji, &self.emoji.name)
+            });
+        }
+
         if !cache.wants(ResourceType::REACTION) {
             return;
         }
@@ -571,13 +764,10 @@ impl UpdateCache for ReactionRemoveEmoji {
             None => return,
         };
 
-        let index = message.reactions.iter().position(|r| {
-            matches!(&r.emoji,
-                ReactionType::Unicode { name, .. }
-                    | ReactionType::Custom { name: Some(name), .. }
-                    if *name == self.emoji.name
-            )
-        });
+        let index = message
+            .reactions
+            .iter()
+            .position(|r| emoji_has_name(&r.emoji, &self.emoji.name));
 
         if let Some(index) = index {
             let msg = Arc::make_mut(&mut message);
@@ -586,6 +776,14 @@ impl UpdateCache for ReactionRemoveEmoji {
     }
 }
 
+fn emoji_has_name(emoji: &ReactionType, emoji_name: &str) -> bool {
+    matches!(emoji,
+        ReactionType::Unicode { name, .. }
+            | ReactionType::Custom { name: Some(name), .. }
+            if name == emoji_name
+    )
+}
+
 impl UpdateCache for Ready {
     fn update(&self, cache: &InMemoryCache) {
         if cache.wants(ResourceType::USER_CURRENT) {
@@ -609,40 +807,75 @@ impl UpdateCache for Ready {
 
 impl UpdateCache for RoleCreate {
     fn update(&self, cache: &InMemoryCache) {
+        self.update_returning(cache);
+    }
+
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
         if !cache.wants(ResourceType::ROLE) {
-            return;
+            return None;
         }
 
-        super::upsert_guild_item(
-            &cache.0.roles,
-            self.guild_id,
-            self.role.id,
-            self.role.clone(),
-        );
+        cache_role_returning(cache, self.guild_id, &self.role)
     }
 }
 
 impl UpdateCache for RoleDelete {
     fn update(&self, cache: &InMemoryCache) {
+        self.update_returning(cache);
+    }
+
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
         if !cache.wants(ResourceType::ROLE) {
-            return;
+            return None;
         }
 
-        cache.delete_role(self.role_id);
+        cache.delete_role(self.role_id)?;
+        Some(CacheUpdate::RoleDeleted(self.guild_id, self.role_id))
     }
 }
 
 impl UpdateCache for RoleUpdate {
     fn update(&self, cache: &InMemoryCache) {
+        self.update_returning(cache);
+    }
+
+    fn update_returning(&self, cache: &InMemoryCache) -> Option<CacheUpdate> {
         if !cache.wants(ResourceType::ROLE) {
-            return;
+            return None;
         }
 
-        cache.cache_role(self.guild_id, self.role.clone());
+        cache_role_returning(cache, self.guild_id, &self.role)
     }
 }
 
-impl UpdateCache for TypingStart {}
+fn cache_role_returning(
+    cache: &InMemoryCache,
+    guild_id: GuildId,
+    role: &twilight_model::guild::Role,
+) -> Option<CacheUpdate> {
+    let existing = cache.0.roles.get(&role.id).map(|item| *item.data == *role);
+    cache.cache_role(guild_id, role.clone());
+    match existing {
+        None => Some(CacheUpdate::RoleCreated(guild_id, role.id)),
+        Some(false) => Some(CacheUpdate::RoleChanged(guild_id, role.id)),
+        Some(true) => None,
+    }
+}
+
+impl UpdateCache for TypingStart {
+    fn update(&self, cache: &InMemoryCache) {
+        if !cache.wants(ResourceType::TYPING) {
+            return;
+        }
+
+        cache
+            .0
+            .typing
+            .entry(self.channel_id)
+            .or_default()
+            .insert(self.user_id, Instant::now());
+    }
+}
 
 impl UpdateCache for UnavailableGuild {
     fn update(&self, cache: &InMemoryCache) {
@@ -666,7 +899,29 @@ impl UpdateCache for UserUpdate {
 }
 
 impl UpdateCache for VoiceServerUpdate {
-    fn update(&self, _: &InMemoryCache) {}
+    fn update(&self, cache: &InMemoryCache) {
+        if !cache.wants(ResourceType::VOICE_STATE) {
+            return;
+        }
+
+        let guild_id = match self.guild_id {
+            Some(guild_id) => guild_id,
+            None => return,
+        };
+
+        // A missing endpoint means the voice server went away and a new one will be allocated.
+        match &self.endpoint {
+            Some(endpoint) => {
+                cache
+                    .0
+                    .voice_servers
+                    .insert(guild_id, (endpoint.clone(), self.token.clone()));
+            }
+            None => {
+                cache.0.voice_servers.remove(&guild_id);
+            }
+        }
+    }
 }
 
 impl UpdateCache for VoiceStateUpdate {
@@ -679,26 +934,39 @@ impl UpdateCache for VoiceStateUpdate {
     }
 }
 
-impl UpdateCache for WebhooksUpdate {}
+impl UpdateCache for WebhooksUpdate {
+    fn update(&self, cache: &InMemoryCache) {
+        if !cache.wants(ResourceType::CHANNEL) {
+            return;
+        }
+
+        // The event does not carry the webhooks, so the count is unknown until refetched.
+        cache.0.channel_webhooks.insert(self.channel_id, None);
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::ResourceType;
+    use crate::{config::ResourceType, test_support::message};
     use twilight_model::{
         channel::{
             message::{MessageFlags, MessageType},
             ChannelType, GuildChannel, Message, Reaction, TextChannel,
         },
-        gateway::payload::{reaction_remove_emoji::PartialEmoji, ChannelDelete},
+        gateway::{
+            payload::{reaction_remove_emoji::PartialEmoji, ChannelDelete},
+            presence::{ClientStatus, Status},
+        },
         guild::{
             DefaultMessageNotificationLevel, ExplicitContentFilter, Guild, Member, MfaLevel,
             PartialGuild, PartialMember, PremiumTier, SystemChannelFlags, VerificationLevel,
         },
-        id::{ChannelId, GuildId, MessageId, UserId},
+        id::{ChannelId, GuildId, IntegrationId, MessageId, RoleId, UserId},
         user::User,
         voice::VoiceState,
     };
+    use std::{collections::HashMap, time::{Duration, Instant}};
 
     fn guild_channel_text() -> (GuildId, ChannelId, GuildChannel) {
         let guild_id = GuildId(1);
@@ -981,6 +1249,66 @@ mod tests {
             .contains(&channel_id));
     }
 
+    #[test]
+    fn test_channel_update_reorders_channels() {
+        let cache = InMemoryCache::new();
+        let channel = |id: u64, position: i64| {
+            let (_, _, mut channel) = guild_channel_text();
+            if let GuildChannel::Text(ref mut c) = channel {
+                c.id = ChannelId(id);
+                c.position = position;
+            }
+            channel
+        };
+        let sorted_ids = |cache: &InMemoryCache| -> Vec<ChannelId> {
+            cache
+                .guild_channels_sorted(GuildId(1))
+                .unwrap()
+                .iter()
+                .map(|c| c.id())
+                .collect()
+        };
+        assert!(cache.guild_channels_sorted(GuildId(1)).is_none());
+
+        cache.update(&ChannelCreate(Channel::Guild(channel(2, 0))));
+        cache.update(&ChannelCreate(Channel::Guild(channel(3, 1))));
+        assert_eq!(sorted_ids(&cache), vec![ChannelId(2), ChannelId(3)]);
+
+        // Discord sends a ChannelUpdate for every channel whose position changed.
+        cache.update(&ChannelUpdate(Channel::Guild(channel(2, 1))));
+        cache.update(&ChannelUpdate(Channel::Guild(channel(3, 0))));
+        assert_eq!(sorted_ids(&cache), vec![ChannelId(3), ChannelId(2)]);
+    }
+
+    #[test]
+    fn test_channel_pins_update() {
+        let cache = InMemoryCache::new();
+        let (guild_id, channel_id, channel) = guild_channel_text();
+        cache.cache_guild_channel(guild_id, channel);
+
+        cache.update(&ChannelPinsUpdate {
+            channel_id,
+            guild_id: Some(guild_id),
+            last_pin_timestamp: Some("2021-03-11T00:00:00+00:00".to_owned()),
+        });
+
+        match *cache.guild_channel(channel_id).unwrap() {
+            GuildChannel::Text(ref c) => assert_eq!(
+                c.last_pin_timestamp.as_deref(),
+                Some("2021-03-11T00:00:00+00:00")
+            ),
+            ref c => panic!("{:?}", c),
+        }
+
+        // Uncached channels are ignored.
+        cache.update(&ChannelPinsUpdate {
+            channel_id: ChannelId(99),
+            guild_id: Some(guild_id),
+            last_pin_timestamp: None,
+        });
+        assert!(cache.guild_channel(ChannelId(99)).is_none());
+    }
+
     #[test]
     fn test_voice_states_with_no_cached_guilds() {
         let cache = InMemoryCache::builder()
@@ -1062,6 +1390,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_set_resource_types() {
+        let cache = InMemoryCache::new();
+        cache.0.guild_presences.insert(GuildId(1), HashSet::new());
+
+        let presence = |user_id| PresenceUpdate {
+            activities: Vec::new(),
+            client_status: ClientStatus {
+                desktop: Some(Status::Online),
+                mobile: None,
+                web: None,
+            },
+            game: None,
+            guild_id: GuildId(1),
+            nick: None,
+            status: Status::Online,
+            user: UserOrId::UserId { id: user_id },
+        };
+
+        cache.update(&presence(UserId(2)));
+        assert!(cache.presence(GuildId(1), UserId(2)));
+
+        cache.set_resource_types(ResourceType::all() - ResourceType::PRESENCE);
+        assert!(!cache.config().resource_types().contains(ResourceType::PRESENCE));
+
+        cache.update(&presence(UserId(3)));
+        assert!(!cache.presence(GuildId(1), UserId(3)));
+    }
+
     #[test]
     fn test_message_create() {
         let cache = InMemoryCache::builder()
@@ -1133,6 +1490,355 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_message_delete_tombstone() {
+        let cache = InMemoryCache::builder()
+            .deleted_message_retention(Duration::from_secs(60))
+            .build();
+        let message = cache_with_message_and_reactions()
+            .message(ChannelId(2), MessageId(4))
+            .unwrap();
+        cache
+            .0
+            .messages
+            .entry(ChannelId(2))
+            .or_default()
+            .insert(MessageId(4), message);
+
+        cache.update(&MessageDelete {
+            channel_id: ChannelId(2),
+            guild_id: Some(GuildId(1)),
+            id: MessageId(4),
+        });
+
+        assert!(cache.message(ChannelId(2), MessageId(4)).is_none());
+        assert_eq!(cache.deleted_message(MessageId(4)).unwrap().content, "ping");
+    }
+
+    #[test]
+    fn test_drain_expired_tombstones() {
+        let cache = cache_with_message_and_reactions();
+        let message = cache.message(ChannelId(2), MessageId(4)).unwrap();
+        let now = Instant::now();
+
+        for (id, expiry) in &[(4, 10), (5, 20), (6, 30)] {
+            let mut tombstone = (*message).clone();
+            tombstone.id = MessageId(*id);
+            cache.0.message_tombstones.insert(
+                tombstone.id,
+                (now + Duration::from_secs(*expiry), Arc::new(tombstone)),
+            );
+        }
+
+        let drained = cache.drain_expired_tombstones(now + Duration::from_secs(20));
+        let ids: Vec<MessageId> = drained.iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec![MessageId(4), MessageId(5)]);
+        assert!(cache.deleted_message(MessageId(5)).is_none());
+        assert!(cache.deleted_message(MessageId(6)).is_some());
+        assert!(cache.drain_expired_tombstones(now).is_empty());
+    }
+
+    #[test]
+    fn test_typing_start() {
+        let typing = |user_id| TypingStart {
+            channel_id: ChannelId(2),
+            guild_id: Some(GuildId(1)),
+            member: None,
+            timestamp: 0,
+            user_id: UserId(user_id),
+        };
+
+        // Typing is not tracked by default.
+        let cache = InMemoryCache::new();
+        cache.update(&typing(3));
+        assert!(cache.recently_typing(ChannelId(2), Duration::from_secs(10)).is_empty());
+
+        let cache = InMemoryCache::builder().resource_types(ResourceType::TYPING).build();
+        cache.update(&typing(4));
+        cache.update(&typing(3));
+        assert_eq!(
+            cache.recently_typing(ChannelId(2), Duration::from_secs(10)),
+            vec![UserId(3), UserId(4)]);
+        assert!(cache.recently_typing(ChannelId(5), Duration::from_secs(10)).is_empty());
+    }
+
+    #[test]
+    fn test_recently_typing_prunes_outside_window() {
+        let cache = InMemoryCache::builder().resource_types(ResourceType::TYPING).build();
+        let now = Instant::now();
+        let mut typers = HashMap::new();
+        typers.insert(UserId(3), now);
+        typers.insert(UserId(4), now + Duration::from_secs(5));
+        cache.0.typing.insert(ChannelId(2), typers);
+
+        let later = now + Duration::from_secs(12);
+        let typers = cache.recently_typing_at(ChannelId(2), Duration::from_secs(10), later);
+        assert_eq!(typers, vec![UserId(4)]);
+        assert_eq!(cache.0.typing.get(&ChannelId(2)).unwrap().len(), 1);
+
+        // Channels without any recent typers are dropped entirely.
+        let later = now + Duration::from_secs(30);
+        assert!(cache.recently_typing_at(ChannelId(2), Duration::from_secs(10), later).is_empty());
+        assert!(cache.0.typing.get(&ChannelId(2)).is_none());
+    }
+
+    #[test]
+    fn test_message_create_caches_message_and_author() {
+        let cache = InMemoryCache::builder().message_cache_size(2).build();
+
+        for id in 1..=3 {
+            cache.update(&MessageCreate(message(ChannelId(2), MessageId(id), UserId(3))));
+        }
+
+        // Only the newest messages are kept once over capacity.
+        assert!(cache.message(ChannelId(2), MessageId(1)).is_none());
+        assert_eq!(
+            cache.message(ChannelId(2), MessageId(3)).unwrap().content,
+            "ping"
+        );
+        assert_eq!(cache.0.messages.get(&ChannelId(2)).unwrap().len(), 2);
+        assert_eq!(cache.user(UserId(3)).unwrap().name, "test");
+    }
+
+    #[test]
+    fn test_message_create_caches_partial_member() {
+        let cache = InMemoryCache::builder()
+            .resource_types(ResourceType::MEMBER)
+            .build();
+
+        let mut msg = message(ChannelId(2), MessageId(4), UserId(3));
+        msg.member = Some(PartialMember {
+            deaf: false,
+            joined_at: None,
+            mute: false,
+            nick: Some("member nick".to_owned()),
+            premium_since: None,
+            roles: Vec::new(),
+        });
+        cache.update(&MessageCreate(msg));
+
+        let member = cache.member(GuildId(1), UserId(3)).unwrap();
+        assert_eq!(member.nick.as_deref(), Some("member nick"));
+        assert_eq!(member.user.id, UserId(3));
+        assert!(cache.guild_members(GuildId(1)).unwrap().contains(&UserId(3)));
+        // Messages themselves are not cached without the MESSAGE resource type.
+        assert!(cache.message(ChannelId(2), MessageId(4)).is_none());
+    }
+
+    #[test]
+    fn test_message_author_member() {
+        let cache = InMemoryCache::new();
+
+        let mut msg = message(ChannelId(2), MessageId(4), UserId(3));
+        assert!(cache.message_author_member(&msg).is_none());
+
+        msg.member = Some(PartialMember {
+            deaf: false,
+            joined_at: None,
+            mute: false,
+            nick: Some("member nick".to_owned()),
+            premium_since: None,
+            roles: Vec::new(),
+        });
+        let member = cache.message_author_member(&msg).unwrap();
+        assert_eq!(member.nick.as_deref(), Some("member nick"));
+        assert_eq!(member.user.id, UserId(3));
+        assert!(cache.member(GuildId(1), UserId(3)).is_some());
+
+        // Messages sent outside of guilds have no member.
+        msg.guild_id = None;
+        assert!(cache.message_author_member(&msg).is_none());
+    }
+
+    #[test]
+    fn test_bans() {
+        let cache = InMemoryCache::new();
+        let user = message(ChannelId(2), MessageId(4), UserId(3)).author;
+
+        cache.update(&BanAdd { guild_id: GuildId(1), user: user.clone() });
+        cache.update(&BanAdd { guild_id: GuildId(5), user: user.clone() });
+        assert!(cache.is_banned(GuildId(1), UserId(3)));
+        assert!(!cache.is_banned(GuildId(1), UserId(6)));
+        assert_eq!(cache.guild_bans(GuildId(1)), Some(vec![UserId(3)]));
+
+        cache.update(&BanRemove { guild_id: GuildId(1), user });
+        assert!(!cache.is_banned(GuildId(1), UserId(3)));
+        assert!(cache.guild_bans(GuildId(1)).is_none());
+
+        cache.update(&GuildDelete { id: GuildId(5), unavailable: false });
+        assert!(!cache.is_banned(GuildId(5), UserId(3)));
+    }
+
+    #[test]
+    fn test_bans_require_resource_type() {
+        let cache = InMemoryCache::builder()
+            .resource_types(ResourceType::GUILD)
+            .build();
+        let user = message(ChannelId(2), MessageId(4), UserId(3)).author;
+
+        cache.update(&BanAdd { guild_id: GuildId(1), user });
+        assert!(!cache.is_banned(GuildId(1), UserId(3)));
+    }
+
+    #[test]
+    fn test_integrations() {
+        let cache = InMemoryCache::builder()
+            .resource_types(ResourceType::GUILD | ResourceType::INTEGRATION)
+            .build();
+        assert!(cache.guild_integrations(GuildId(1)).is_none());
+
+        cache.cache_integrations(GuildId(1), vec![IntegrationId(2), IntegrationId(3)]);
+        cache.cache_integrations(GuildId(4), vec![IntegrationId(5)]);
+        let integrations = cache.guild_integrations(GuildId(1)).unwrap();
+        assert_eq!(integrations.len(), 2);
+        assert!(integrations.contains(&IntegrationId(2)));
+
+        cache.update(&GuildIntegrationsUpdate { guild_id: GuildId(1) });
+        assert!(cache.guild_integrations(GuildId(1)).is_none());
+
+        cache.update(&GuildDelete { id: GuildId(4), unavailable: false });
+        assert!(cache.guild_integrations(GuildId(4)).is_none());
+    }
+
+    #[test]
+    fn test_integrations_disabled_by_default() {
+        let cache = InMemoryCache::new();
+        cache.cache_integrations(GuildId(1), vec![IntegrationId(2)]);
+        assert!(cache.guild_integrations(GuildId(1)).is_none());
+    }
+
+    #[test]
+    fn test_channel_webhooks() {
+        let cache = InMemoryCache::new();
+        let (guild_id, channel_id, channel) = guild_channel_text();
+        cache.cache_guild_channel(guild_id, channel.clone());
+        assert!(!cache.channel_has_webhooks(channel_id));
+
+        cache.update(&WebhooksUpdate { channel_id, guild_id });
+        assert!(cache.channel_has_webhooks(channel_id));
+        assert!(cache.channel_webhook_count(channel_id).is_none());
+
+        cache.cache_webhook_count(channel_id, 0);
+        assert!(!cache.channel_has_webhooks(channel_id));
+        cache.cache_webhook_count(channel_id, 2);
+        assert!(cache.channel_has_webhooks(channel_id));
+        assert_eq!(cache.channel_webhook_count(channel_id), Some(2));
+
+        cache.update(&ChannelDelete(Channel::Guild(channel)));
+        assert!(!cache.channel_has_webhooks(channel_id));
+        assert!(cache.channel_webhook_count(channel_id).is_none());
+    }
+
+    #[test]
+    fn test_voice_server() {
+        let cache = InMemoryCache::new();
+        assert!(cache.voice_server(GuildId(1)).is_none());
+
+        let update = |endpoint: Option<&str>, token: &str| VoiceServerUpdate {
+            channel_id: None,
+            endpoint: endpoint.map(String::from),
+            guild_id: Some(GuildId(1)),
+            token: token.to_owned(),
+        };
+        cache.update(&update(Some("us-east1.discord.media:443"), "token"));
+        assert_eq!(
+            cache.voice_server(GuildId(1)),
+            Some(("us-east1.discord.media:443".to_owned(), "token".to_owned())));
+
+        // Reconnecting replaces the previous server.
+        cache.update(&update(Some("us-west1.discord.media:443"), "new token"));
+        assert_eq!(
+            cache.voice_server(GuildId(1)),
+            Some(("us-west1.discord.media:443".to_owned(), "new token".to_owned())));
+
+        cache.update(&update(None, "new token"));
+        assert!(cache.voice_server(GuildId(1)).is_none());
+    }
+
+    #[test]
+    fn test_delete_message_returns_message() {
+        let cache = InMemoryCache::new();
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(4), UserId(3))));
+
+        let deleted = cache.delete_message(ChannelId(2), MessageId(4)).unwrap();
+        assert_eq!(deleted.content, "ping");
+        assert!(cache.message(ChannelId(2), MessageId(4)).is_none());
+
+        // Deleting an uncached message returns nothing.
+        assert!(cache.delete_message(ChannelId(2), MessageId(4)).is_none());
+        assert!(cache.delete_message(ChannelId(5), MessageId(6)).is_none());
+    }
+
+    #[test]
+    fn test_total_cached_messages() {
+        let cache = InMemoryCache::new();
+        assert_eq!(0, cache.total_cached_messages());
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(4), UserId(3))));
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(5), UserId(3))));
+        cache.update(&MessageCreate(message(ChannelId(6), MessageId(7), UserId(3))));
+        assert_eq!(3, cache.total_cached_messages());
+    }
+
+    #[test]
+    fn test_clear_messages() {
+        let cache = InMemoryCache::new();
+        let (guild_id, channel_id, channel) = guild_channel_text();
+        cache.cache_guild_channel(guild_id, channel);
+        cache.update(&MessageCreate(message(channel_id, MessageId(4), UserId(3))));
+        cache.update(&MessageCreate(message(ChannelId(5), MessageId(6), UserId(3))));
+        cache.update(&MessageCreate(message(ChannelId(7), MessageId(8), UserId(3))));
+
+        cache.clear_channel_messages(ChannelId(5));
+        assert!(cache.message(ChannelId(5), MessageId(6)).is_none());
+        assert!(cache.message(channel_id, MessageId(4)).is_some());
+
+        // Only channels belonging to the guild are cleared.
+        cache.clear_guild_messages(guild_id);
+        assert!(cache.message(channel_id, MessageId(4)).is_none());
+        assert!(cache.message(ChannelId(7), MessageId(8)).is_some());
+    }
+
+    #[test]
+    fn test_channel_oldest_and_newest_message() {
+        let cache = InMemoryCache::new();
+        assert!(cache.channel_oldest_message(ChannelId(2)).is_none());
+        assert!(cache.channel_newest_message(ChannelId(2)).is_none());
+
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(5), UserId(3))));
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(4), UserId(3))));
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(6), UserId(3))));
+        assert_eq!(cache.channel_oldest_message(ChannelId(2)).unwrap().id, MessageId(4));
+        assert_eq!(cache.channel_newest_message(ChannelId(2)).unwrap().id, MessageId(6));
+
+        // Cleared channels have neither.
+        cache.clear_channel_messages(ChannelId(2));
+        assert!(cache.channel_oldest_message(ChannelId(2)).is_none());
+        assert!(cache.channel_newest_message(ChannelId(2)).is_none());
+    }
+
+    #[test]
+    fn test_channel_last_message_id() {
+        let cache = InMemoryCache::new();
+        let (guild_id, channel_id, channel) = guild_channel_text();
+        cache.cache_guild_channel(guild_id, channel);
+        assert_eq!(None, cache.channel_last_message_id(channel_id));
+
+        // Without a last_message_id on the channel, the newest cached message is used.
+        cache.update(&MessageCreate(message(channel_id, MessageId(5), UserId(3))));
+        cache.update(&MessageCreate(message(channel_id, MessageId(4), UserId(3))));
+        assert_eq!(Some(MessageId(5)), cache.channel_last_message_id(channel_id));
+
+        let (_, _, mut channel) = guild_channel_text();
+        if let GuildChannel::Text(ref mut c) = channel {
+            c.last_message_id = Some(MessageId(7));
+        }
+        cache.cache_guild_channel(guild_id, channel);
+        assert_eq!(Some(MessageId(7)), cache.channel_last_message_id(channel_id));
+
+        // Uncached channels have neither source.
+        assert_eq!(None, cache.channel_last_message_id(ChannelId(99)));
+    }
+
     #[test]
     fn test_reaction_add() {
         let cache = cache_with_message_and_reactions();
@@ -1219,6 +1925,111 @@ mod tests {
         assert!(smiley_react.is_none());
     }
 
+    #[test]
+    fn test_message_reactions() {
+        let cache = cache_with_message_and_reactions();
+        let reactions = cache.message_reactions(ChannelId(2), MessageId(4)).unwrap();
+        assert_eq!(reactions.len(), 2);
+        assert_eq!(reactions.iter().map(|r| r.count).sum::<u64>(), 3);
+        assert!(cache.message_reactions(ChannelId(2), MessageId(5)).is_none());
+    }
+
+    #[test]
+    fn test_message_reactors() {
+        let reaction = |name: &str, user_id| Reaction {
+            channel_id: ChannelId(2),
+            emoji: ReactionType::Unicode {
+                name: name.to_owned(),
+            },
+            guild_id: Some(GuildId(1)),
+            member: None,
+            message_id: MessageId(4),
+            user_id: UserId(user_id),
+        };
+        let smiley = ReactionType::Unicode { name: "😀".to_owned() };
+        let world = ReactionType::Unicode { name: "🗺️".to_owned() };
+
+        // Reactors are not tracked by default.
+        let cache = InMemoryCache::new();
+        cache.update(&ReactionAdd(reaction("😀", 5)));
+        assert!(cache.message_reactors(MessageId(4), &smiley).is_none());
+
+        // Reactors are not tracked for uncached messages.
+        let cache = InMemoryCache::builder()
+            .resource_types(
+                ResourceType::CHANNEL |
+                    ResourceType::GUILD |
+                    ResourceType::MESSAGE |
+                    ResourceType::REACTOR,
+            )
+            .message_cache_size(1)
+            .build();
+        cache.update(&ReactionAdd(reaction("😀", 5)));
+        assert!(cache.0.message_reactors.is_empty());
+
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(4), UserId(3))));
+        cache.update(&ReactionAdd(reaction("😀", 5)));
+        cache.update(&ReactionAdd(reaction("😀", 6)));
+        cache.update(&ReactionAdd(reaction("🗺️", 5)));
+        let users = cache.message_reactors(MessageId(4), &smiley).unwrap();
+        assert_eq!(users.len(), 2);
+        assert!(users.contains(&UserId(5)) && users.contains(&UserId(6)));
+
+        cache.update(&ReactionRemove(reaction("😀", 5)));
+        let users = cache.message_reactors(MessageId(4), &smiley).unwrap();
+        assert_eq!(users.into_iter().collect::<Vec<_>>(), vec![UserId(6)]);
+        cache.update(&ReactionRemove(reaction("😀", 6)));
+        assert!(cache.message_reactors(MessageId(4), &smiley).is_none());
+        assert!(cache.message_reactors(MessageId(4), &world).is_some());
+
+        cache.update(&ReactionRemoveEmoji {
+            channel_id: ChannelId(2),
+            emoji: PartialEmoji {
+                id: None,
+                name: "🗺️".to_owned(),
+            },
+            guild_id: GuildId(1),
+            message_id: MessageId(4),
+        });
+        assert!(cache.message_reactors(MessageId(4), &world).is_none());
+        assert!(cache.0.message_reactors.is_empty());
+
+        cache.update(&ReactionAdd(reaction("😀", 5)));
+        cache.update(&ReactionAdd(reaction("🗺️", 6)));
+        cache.update(&ReactionRemoveAll {
+            channel_id: ChannelId(2),
+            message_id: MessageId(4),
+            guild_id: Some(GuildId(1)),
+        });
+        assert!(cache.message_reactors(MessageId(4), &smiley).is_none());
+        assert!(cache.0.message_reactors.is_empty());
+
+        // Reactors are dropped along with evicted messages.
+        cache.update(&ReactionAdd(reaction("😀", 5)));
+        cache.update(&MessageCreate(message(ChannelId(2), MessageId(7), UserId(3))));
+        assert!(cache.0.message_reactors.is_empty());
+
+        // And with the messages of deleted channels and guilds.
+        let (guild_id, channel_id, channel) = guild_channel_text();
+        let add_reaction_to_new_message = |id| {
+            cache.update(&MessageCreate(message(channel_id, MessageId(id), UserId(3))));
+            cache.update(&ReactionAdd(Reaction {
+                message_id: MessageId(id),
+                ..reaction("😀", 5)
+            }));
+            assert!(cache.message_reactors(MessageId(id), &smiley).is_some());
+        };
+        cache.cache_guild_channel(guild_id, channel.clone());
+        add_reaction_to_new_message(8);
+        cache.update(&ChannelDelete(Channel::Guild(channel.clone())));
+        assert!(cache.0.message_reactors.is_empty());
+
+        cache.cache_guild_channel(guild_id, channel);
+        add_reaction_to_new_message(9);
+        cache.update(&GuildDelete { id: guild_id, unavailable: false });
+        assert!(cache.0.message_reactors.is_empty());
+    }
+
     #[test]
     fn test_reaction_remove_all() {
         let cache = cache_with_message_and_reactions();
@@ -1232,4 +2043,426 @@ mod tests {
 
         assert_eq!(msg.reactions.len(), 0);
     }
+
+    /// Checks that each handled gateway event is reflected by the corresponding getter.
+    mod coverage {
+        use super::*;
+        use twilight_model::guild::{Permissions, Role};
+
+        const GUILD_ID: GuildId = GuildId(1);
+
+        fn guild() -> Guild {
+            Guild {
+                afk_channel_id: None,
+                afk_timeout: 0,
+                application_id: None,
+                approximate_member_count: None,
+                approximate_presence_count: None,
+                banner: None,
+                channels: Vec::new(),
+                default_message_notifications: DefaultMessageNotificationLevel::Mentions,
+                description: None,
+                discovery_splash: None,
+                emojis: Vec::new(),
+                explicit_content_filter: ExplicitContentFilter::None,
+                features: Vec::new(),
+                icon: None,
+                id: GUILD_ID,
+                joined_at: None,
+                large: false,
+                lazy: None,
+                max_members: None,
+                max_presences: None,
+                max_video_channel_users: None,
+                member_count: None,
+                members: Vec::new(),
+                mfa_level: MfaLevel::None,
+                name: "test".to_owned(),
+                owner_id: UserId(1),
+                owner: None,
+                permissions: None,
+                preferred_locale: "en_us".to_owned(),
+                premium_subscription_count: None,
+                premium_tier: PremiumTier::None,
+                presences: Vec::new(),
+                region: "us".to_owned(),
+                roles: Vec::new(),
+                rules_channel_id: None,
+                splash: None,
+                system_channel_flags: SystemChannelFlags::empty(),
+                system_channel_id: None,
+                unavailable: false,
+                vanity_url_code: None,
+                verification_level: VerificationLevel::VeryHigh,
+                voice_states: Vec::new(),
+                widget_channel_id: None,
+                widget_enabled: None,
+            }
+        }
+
+        fn role(name: &str) -> Role {
+            Role {
+                color: 0,
+                hoist: false,
+                id: RoleId(3),
+                managed: false,
+                mentionable: false,
+                name: name.to_owned(),
+                permissions: Permissions::empty(),
+                position: 0,
+                tags: None,
+            }
+        }
+
+        fn member(nick: Option<&str>) -> Member {
+            Member {
+                deaf: false,
+                guild_id: GUILD_ID,
+                hoisted_role: None,
+                joined_at: None,
+                mute: false,
+                nick: nick.map(String::from),
+                pending: false,
+                premium_since: None,
+                roles: Vec::new(),
+                user: message(ChannelId(2), MessageId(4), UserId(5)).author,
+            }
+        }
+
+        #[test]
+        fn test_guild_create() {
+            let cache = InMemoryCache::new();
+            cache.update(&GuildCreate(guild()));
+            assert_eq!(cache.guild(GUILD_ID).unwrap().name, "test");
+        }
+
+        #[test]
+        fn test_guild_update_keeps_dependents() {
+            let cache = InMemoryCache::new();
+            let (_, channel_id, channel) = guild_channel_text();
+            let mut guild = guild();
+            guild.channels = vec![channel];
+            guild.roles = vec![role("role")];
+            guild.members = vec![member(None)];
+            cache.update(&GuildCreate(guild.clone()));
+
+            cache.update(&GuildUpdate(PartialGuild {
+                id: guild.id,
+                afk_channel_id: guild.afk_channel_id,
+                afk_timeout: guild.afk_timeout,
+                application_id: guild.application_id,
+                banner: guild.banner,
+                default_message_notifications: guild.default_message_notifications,
+                description: guild.description,
+                discovery_splash: guild.discovery_splash,
+                emojis: guild.emojis,
+                explicit_content_filter: guild.explicit_content_filter,
+                features: vec!["COMMUNITY".to_owned()],
+                icon: Some("icon".to_owned()),
+                max_members: guild.max_members,
+                max_presences: guild.max_presences,
+                member_count: guild.member_count,
+                mfa_level: guild.mfa_level,
+                name: "renamed".to_owned(),
+                owner_id: guild.owner_id,
+                owner: guild.owner,
+                permissions: guild.permissions,
+                preferred_locale: guild.preferred_locale,
+                premium_subscription_count: guild.premium_subscription_count,
+                premium_tier: guild.premium_tier,
+                region: guild.region,
+                roles: Vec::new(),
+                rules_channel_id: guild.rules_channel_id,
+                splash: guild.splash,
+                system_channel_flags: guild.system_channel_flags,
+                system_channel_id: guild.system_channel_id,
+                verification_level: guild.verification_level,
+                vanity_url_code: guild.vanity_url_code,
+                widget_channel_id: guild.widget_channel_id,
+                widget_enabled: guild.widget_enabled,
+            }));
+
+            let cached = cache.guild(GUILD_ID).unwrap();
+            assert_eq!(cached.name, "renamed");
+            assert_eq!(cached.icon.as_deref(), Some("icon"));
+            assert_eq!(cached.features, vec!["COMMUNITY".to_owned()]);
+
+            // The guild's channels, roles, and members are left intact.
+            assert!(cache.guild_channels(GUILD_ID).unwrap().contains(&channel_id));
+            assert!(cache.guild_channel(channel_id).is_some());
+            assert!(cache.guild_roles(GUILD_ID).unwrap().contains(&RoleId(3)));
+            assert!(cache.member(GUILD_ID, UserId(5)).is_some());
+        }
+
+        #[test]
+        fn test_channel_events() {
+            let cache = InMemoryCache::new();
+            let (_, channel_id, channel) = guild_channel_text();
+
+            cache.update(&ChannelCreate(Channel::Guild(channel.clone())));
+            assert!(cache.guild_channel(channel_id).is_some());
+            assert!(cache.guild_channels(GUILD_ID).unwrap().contains(&channel_id));
+
+            let mut updated = channel.clone();
+            if let GuildChannel::Text(ref mut c) = updated {
+                c.name = "updated".to_owned();
+            }
+            cache.update(&ChannelUpdate(Channel::Guild(updated)));
+            assert_eq!(cache.guild_channel(channel_id).unwrap().name(), "updated");
+
+            cache.update(&ChannelDelete(Channel::Guild(channel)));
+            assert!(cache.guild_channel(channel_id).is_none());
+            assert!(!cache.guild_channels(GUILD_ID).unwrap().contains(&channel_id));
+        }
+
+        #[test]
+        fn test_role_events() {
+            let cache = InMemoryCache::new();
+
+            cache.update(&RoleCreate { guild_id: GUILD_ID, role: role("created") });
+            assert_eq!(cache.role(RoleId(3)).unwrap().name, "created");
+            assert!(cache.guild_roles(GUILD_ID).unwrap().contains(&RoleId(3)));
+
+            cache.update(&RoleUpdate { guild_id: GUILD_ID, role: role("updated") });
+            assert_eq!(cache.role(RoleId(3)).unwrap().name, "updated");
+
+            cache.update(&RoleDelete { guild_id: GUILD_ID, role_id: RoleId(3) });
+            assert!(cache.role(RoleId(3)).is_none());
+            assert!(!cache.guild_roles(GUILD_ID).unwrap().contains(&RoleId(3)));
+        }
+
+        #[test]
+        fn test_member_events() {
+            let cache = InMemoryCache::new();
+
+            cache.update(&MemberAdd(member(None)));
+            assert!(cache.member(GUILD_ID, UserId(5)).is_some());
+            assert!(cache.guild_members(GUILD_ID).unwrap().contains(&UserId(5)));
+
+            let member = member(Some("nick"));
+            cache.update(&MemberUpdate {
+                guild_id: GUILD_ID,
+                joined_at: "2021-01-01T00:00:00+00:00".to_owned(),
+                nick: member.nick.clone(),
+                pending: false,
+                premium_since: None,
+                roles: vec![RoleId(3)],
+                user: member.user.clone(),
+            });
+            let cached = cache.member(GUILD_ID, UserId(5)).unwrap();
+            assert_eq!(cached.nick.as_deref(), Some("nick"));
+            assert_eq!(cached.roles, vec![RoleId(3)]);
+
+            cache.update(&MemberRemove { guild_id: GUILD_ID, user: member.user });
+            assert!(cache.member(GUILD_ID, UserId(5)).is_none());
+            assert!(!cache.guild_members(GUILD_ID).unwrap().contains(&UserId(5)));
+        }
+
+        #[test]
+        fn test_role_update_returning() {
+            let cache = InMemoryCache::new();
+
+            let created = RoleCreate { guild_id: GUILD_ID, role: role("created") };
+            assert_eq!(
+                cache.update_returning(&created),
+                Some(CacheUpdate::RoleCreated(GUILD_ID, RoleId(3))));
+
+            let updated = RoleUpdate { guild_id: GUILD_ID, role: role("updated") };
+            assert_eq!(
+                cache.update_returning(&updated),
+                Some(CacheUpdate::RoleChanged(GUILD_ID, RoleId(3))));
+            // Redelivering the same role changes nothing.
+            assert_eq!(cache.update_returning(&updated), None);
+
+            let deleted = RoleDelete { guild_id: GUILD_ID, role_id: RoleId(3) };
+            assert_eq!(
+                cache.update_returning(&deleted),
+                Some(CacheUpdate::RoleDeleted(GUILD_ID, RoleId(3))));
+            assert_eq!(cache.update_returning(&deleted), None);
+        }
+
+        #[test]
+        fn test_member_update_returning() {
+            let cache = InMemoryCache::new();
+
+            assert_eq!(
+                cache.update_returning(&Event::MemberAdd(Box::new(MemberAdd(member(None))))),
+                Some(CacheUpdate::MemberJoined(GUILD_ID, UserId(5))));
+
+            let member = member(None);
+            let update = MemberUpdate {
+                guild_id: GUILD_ID,
+                joined_at: "2021-01-01T00:00:00+00:00".to_owned(),
+                nick: Some("nick".to_owned()),
+                pending: false,
+                premium_since: None,
+                roles: Vec::new(),
+                user: member.user.clone(),
+            };
+            assert_eq!(
+                cache.update_returning(&update),
+                Some(CacheUpdate::MemberChanged(GUILD_ID, UserId(5))));
+            assert_eq!(cache.update_returning(&update), None);
+
+            let remove = MemberRemove { guild_id: GUILD_ID, user: member.user };
+            assert_eq!(
+                cache.update_returning(&remove),
+                Some(CacheUpdate::MemberLeft(GUILD_ID, UserId(5))));
+            assert_eq!(cache.update_returning(&remove), None);
+        }
+
+        #[test]
+        fn test_member_update_returning_role_diff() {
+            let cache = InMemoryCache::new();
+            cache.update(&MemberAdd(member(None)));
+
+            let member = member(None);
+            let update = |roles: Vec<RoleId>| MemberUpdate {
+                guild_id: GUILD_ID,
+                joined_at: "2021-01-01T00:00:00+00:00".to_owned(),
+                nick: None,
+                pending: false,
+                premium_since: None,
+                roles,
+                user: member.user.clone(),
+            };
+            assert_eq!(
+                cache.update_returning(&update(vec![RoleId(3), RoleId(4)])),
+                Some(CacheUpdate::MemberRolesChanged {
+                    guild_id: GUILD_ID,
+                    user_id: UserId(5),
+                    added: vec![RoleId(3), RoleId(4)],
+                    removed: Vec::new(),
+                }));
+            assert_eq!(
+                cache.update_returning(&update(vec![RoleId(4), RoleId(6)])),
+                Some(CacheUpdate::MemberRolesChanged {
+                    guild_id: GUILD_ID,
+                    user_id: UserId(5),
+                    added: vec![RoleId(6)],
+                    removed: vec![RoleId(3)],
+                }));
+            assert_eq!(
+                cache.member(GUILD_ID, UserId(5)).unwrap().roles,
+                vec![RoleId(4), RoleId(6)]);
+        }
+
+        #[test]
+        fn test_update_returning_unsupported_events() {
+            let cache = InMemoryCache::new();
+            let (guild_id, _, channel) = guild_channel_text();
+            assert_eq!(cache.update_returning(&ChannelCreate(Channel::Guild(channel))), None);
+            // The event is still applied to the cache.
+            assert_eq!(cache.guild_channels(guild_id).unwrap().len(), 1);
+        }
+
+        #[test]
+        fn test_presence_update() {
+            let cache = InMemoryCache::new();
+            cache.update(&GuildCreate(guild()));
+            cache.update(&PresenceUpdate {
+                activities: Vec::new(),
+                client_status: ClientStatus {
+                    desktop: Some(Status::Online),
+                    mobile: None,
+                    web: None,
+                },
+                game: None,
+                guild_id: GUILD_ID,
+                nick: None,
+                status: Status::Online,
+                user: UserOrId::UserId { id: UserId(5) },
+            });
+            assert!(cache.presence(GUILD_ID, UserId(5)));
+        }
+
+        #[test]
+        fn test_voice_state_update() {
+            let cache = InMemoryCache::new();
+            cache.update(&VoiceStateUpdate(VoiceState {
+                channel_id: Some(ChannelId(2)),
+                deaf: false,
+                guild_id: Some(GUILD_ID),
+                member: None,
+                mute: false,
+                self_deaf: false,
+                self_mute: false,
+                self_stream: false,
+                session_id: "session".to_owned(),
+                suppress: false,
+                token: None,
+                user_id: UserId(5),
+            }));
+            assert_eq!(cache.voice_state(GUILD_ID, UserId(5)), Some(ChannelId(2)));
+        }
+
+        #[test]
+        fn test_message_events() {
+            let cache = InMemoryCache::new();
+
+            cache.update(&MessageCreate(message(ChannelId(2), MessageId(4), UserId(5))));
+            assert!(cache.message(ChannelId(2), MessageId(4)).is_some());
+
+            cache.update(&MessageDelete {
+                channel_id: ChannelId(2),
+                guild_id: Some(GUILD_ID),
+                id: MessageId(4),
+            });
+            assert!(cache.message(ChannelId(2), MessageId(4)).is_none());
+        }
+
+        #[test]
+        fn test_member_chunks() {
+            let cache = InMemoryCache::new();
+            fn chunk(chunk_index: u32, members: Vec<Member>) -> MemberChunk {
+                MemberChunk {
+                    chunk_count: 2,
+                    chunk_index,
+                    guild_id: GUILD_ID,
+                    members,
+                    nonce: None,
+                    not_found: Vec::new(),
+                    presences: Vec::new(),
+                }
+            }
+            let mut other = member(None);
+            other.user.id = UserId(6);
+
+            assert!(!cache.guild_members_complete(GUILD_ID));
+            cache.update(&chunk(0, vec![member(None)]));
+            assert!(!cache.guild_members_complete(GUILD_ID));
+            // Chunks may arrive out of order or be redelivered.
+            cache.update(&chunk(0, vec![member(None)]));
+            assert!(!cache.guild_members_complete(GUILD_ID));
+            cache.update(&chunk(1, vec![other]));
+            assert!(cache.guild_members_complete(GUILD_ID));
+            assert_eq!(cache.guild_members(GUILD_ID).unwrap().len(), 2);
+
+            cache.update(&GuildDelete { id: GUILD_ID, unavailable: false });
+            assert!(!cache.guild_members_complete(GUILD_ID));
+        }
+
+        #[test]
+        fn test_member_chunk_after_guild_create_restores_unchanged_members() {
+            let cache = InMemoryCache::new();
+            let chunk = MemberChunk {
+                chunk_count: 1,
+                chunk_index: 0,
+                guild_id: GUILD_ID,
+                members: vec![member(None)],
+                nonce: None,
+                not_found: Vec::new(),
+                presences: Vec::new(),
+            };
+            cache.update(&chunk);
+
+            // The guild becoming available again resets its member set, while the member itself
+            // stays cached and is redelivered unchanged.
+            cache.update(&GuildCreate(guild()));
+            assert!(cache.guild_members(GUILD_ID).unwrap().is_empty());
+            cache.update(&chunk);
+            assert!(cache.guild_members(GUILD_ID).unwrap().contains(&UserId(5)));
+            assert!(cache.guild_members_complete(GUILD_ID));
+        }
+    }
 }
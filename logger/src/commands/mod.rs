@@ -1,18 +1,23 @@
 pub mod precondition;
 
 use crate::cache::InMemoryCache;
+use crate::db::{Cacheable, CachedGuildConfig, RedisPool};
+use crate::error::Result;
+use redis::aio::ConnectionLike;
+use std::borrow::Cow;
 use twilight_model::channel::Message;
 use thiserror::Error;
 use twilight_http::request::channel::message::*;
 
 #[derive(Debug, Clone)]
-pub struct Context<'a> {
+pub struct Context<'a, R = RedisPool> {
     pub message: &'a Message,
     pub http: twilight_http::Client,
     pub cache: InMemoryCache,
+    pub redis: R,
 }
 
-impl Context<'_> {
+impl<R> Context<'_, R> {
 
     pub fn respond(&self) -> CreateMessage {
         self.http
@@ -22,11 +27,26 @@ impl Context<'_> {
 
 }
 
+impl<R: ConnectionLike + Clone + Send> Context<'_, R> {
+
+    /// Fetches one of the configs of the guild the command was run in. Guilds without the config
+    /// set get its default value. Returns None if the command was run in a DM.
+    pub async fn config<T>(&self) -> Result<Option<T>>
+        where T: protobuf::Message + CachedGuildConfig + Send
+    {
+        match self.message.guild_id {
+            Some(guild_id) => T::get(&mut self.redis.clone(), guild_id).await,
+            None => Ok(None),
+        }
+    }
+
+}
+
 /// The sum type of all errors that might result from fetching
 #[derive(Error, Debug)]
 pub enum CommandError {
     #[error("User failed to satisfy preconditions: {}", .0)]
-    FailedPrecondition(&'static str),
+    FailedPrecondition(Cow<'static, str>),
     #[error("Invalid Argument: {}", .0)]
     InvalidArgument(String),
     #[error("Something went wrong: {}", .0)]
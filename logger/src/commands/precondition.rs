@@ -1,9 +1,282 @@
-use crate::prelude::*;
+use crate::{prelude::*, proto::guild_configs::LoggingConfig, snowflake};
 use super::{Context, CommandError};
+use redis::aio::ConnectionLike;
+use std::time::SystemTime;
+use twilight_model::id::ChannelId;
 
-pub fn require_in_guild(ctx: &Context<'_>) -> Result<GuildId> {
+pub fn require_in_guild<R>(ctx: &Context<'_, R>) -> Result<GuildId> {
     ctx.message
        .guild_id
        .ok_or_else(||
-           CommandError::FailedPrecondition( "Command must be run in a server.").into())
+           CommandError::FailedPrecondition("Command must be run in a server.".into()).into())
+}
+
+pub fn require_in_dm<R>(ctx: &Context<'_, R>) -> Result<()> {
+    match ctx.message.guild_id {
+        Some(_) => Err(
+            CommandError::FailedPrecondition("This command can only be used in DMs.".into())
+                .into()),
+        None => Ok(()),
+    }
+}
+
+/// Gets the guild's configured modlog channel, failing if the command was not run in a guild or
+/// the guild has not configured one.
+pub async fn require_logging_channel<R>(ctx: &Context<'_, R>) -> Result<ChannelId>
+    where R: ConnectionLike + Clone + Send
+{
+    require_in_guild(ctx)?;
+    let config = ctx.config::<LoggingConfig>().await?.unwrap_or_default();
+    if config.has_modlog_channel_id() {
+        Ok(ChannelId(config.get_modlog_channel_id()))
+    } else {
+        Err(CommandError::FailedPrecondition("No logging channel configured.".into()).into())
+    }
+}
+
+/// Fails if the author's account is younger than `min_age`, as derived from their user ID. The
+/// error tells the author how much longer they need to wait. Guilds configure the threshold via
+/// `ValidationConfig.minimum_account_age`, in seconds.
+pub fn require_account_age<R>(ctx: &Context<'_, R>, min_age: Duration) -> Result<()> {
+    check_account_age(ctx.message.author.id, min_age, SystemTime::now())
+}
+
+fn check_account_age(user_id: UserId, min_age: Duration, now: SystemTime) -> Result<()> {
+    // Clock skew may put the creation time slightly in the future; treat that as a new account.
+    let age = now
+        .duration_since(snowflake::user_created_at(user_id))
+        .unwrap_or_default();
+    if age >= min_age {
+        return Ok(());
+    }
+    Err(CommandError::FailedPrecondition(format!(
+        "Your account is too new to use this command. Try again in {}.",
+        format_wait(min_age - age)).into()).into())
+}
+
+/// Formats a wait time using its largest whole unit, rounding up so that it never undershoots.
+fn format_wait(wait: Duration) -> String {
+    const UNITS: &[(u64, &str)] = &[(86400, "day"), (3600, "hour"), (60, "minute")];
+    let secs = wait.as_secs().max(1);
+    let (size, name) = UNITS.iter()
+        .find(|(size, _)| secs >= *size)
+        .copied()
+        .unwrap_or((60, "minute"));
+    let count = (secs + size - 1) / size;
+    format!("{} {}{}", count, name, if count == 1 { "" } else { "s" })
+}
+
+/// Gets the arguments passed to the command, failing with the command's usage if fewer than
+/// `min` arguments were provided. See [`split_args`] for how arguments are delimited.
+pub fn require_args<R>(
+    ctx: &Context<'_, R>,
+    min: usize,
+    usage: &'static str
+) -> Result<Vec<String>> {
+    // The first token is the prefixed command itself.
+    let args: Vec<String> = split_args(&ctx.message.content).into_iter().skip(1).collect();
+    if args.len() < min {
+        Err(CommandError::FailedPrecondition(usage.into()).into())
+    } else {
+        Ok(args)
+    }
+}
+
+/// Splits input into whitespace delimited arguments. Double quoted substrings, i.e.
+/// `"two words"`, are kept together as a single argument without the quotes. An unterminated quote
+/// runs to the end of the input.
+pub fn split_args(input: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_arg = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            },
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            },
+            c => {
+                current.push(c);
+                in_arg = true;
+            },
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cache::InMemoryCache, db::{fake::FakeRedis, CachedGuildConfig}, test_support};
+    use crate::proto::guild_configs::MusicConfig;
+    use twilight_model::{channel::Message, id::MessageId};
+
+    fn message(guild_id: Option<GuildId>, content: &str) -> Message {
+        Message {
+            content: content.to_owned(),
+            guild_id,
+            ..test_support::message(ChannelId(2), MessageId(4), UserId(3))
+        }
+    }
+
+    fn context(message: &Message) -> Context<'_, ()> {
+        Context {
+            message,
+            http: twilight_http::Client::new("token"),
+            cache: InMemoryCache::new(),
+            redis: (),
+        }
+    }
+
+    #[test]
+    fn test_require_in_dm() {
+        let dm = message(None, "~link");
+        assert!(require_in_dm(&context(&dm)).is_ok());
+
+        let guild = message(Some(GuildId(1)), "~link");
+        let err = require_in_dm(&context(&guild)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::FailedPrecondition(msg))
+                if msg == "This command can only be used in DMs."
+        ));
+    }
+
+    fn context_with_config<T>(message: &Message, config: Option<T>) -> Context<'_, FakeRedis>
+        where T: protobuf::Message + CachedGuildConfig {
+        let redis = FakeRedis::default();
+        if let Some(config) = config {
+            redis.store_config(GuildId(1), &config);
+        }
+        Context {
+            message,
+            http: twilight_http::Client::new("token"),
+            cache: InMemoryCache::new(),
+            redis,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_context_config_in_guild() {
+        let mut stored = MusicConfig::new();
+        stored.set_volume(50);
+        let msg = message(Some(GuildId(1)), "~volume");
+
+        let ctx = context_with_config(&msg, Some(stored));
+        let config: MusicConfig = ctx.config().await.unwrap().unwrap();
+        assert_eq!(config.get_volume(), 50);
+
+        // Guilds without the config set get the default.
+        let ctx = context_with_config(&msg, None::<MusicConfig>);
+        let config: MusicConfig = ctx.config().await.unwrap().unwrap();
+        assert!(!config.has_volume());
+    }
+
+    #[tokio::test]
+    async fn test_context_config_in_dm() {
+        let msg = message(None, "~volume");
+        let ctx = context_with_config(&msg, Some(MusicConfig::new()));
+        assert!(ctx.config::<MusicConfig>().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_require_logging_channel() {
+        let mut config = LoggingConfig::new();
+        config.set_modlog_channel_id(5);
+        let msg = message(Some(GuildId(1)), "~log");
+        let ctx = context_with_config(&msg, Some(config));
+        assert_eq!(require_logging_channel(&ctx).await.unwrap(), ChannelId(5));
+    }
+
+    #[tokio::test]
+    async fn test_require_logging_channel_unset() {
+        let msg = message(Some(GuildId(1)), "~log");
+        let ctx = context_with_config(&msg, Some(LoggingConfig::new()));
+        let err = require_logging_channel(&ctx).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::FailedPrecondition(msg)) if msg == "No logging channel configured."
+        ));
+
+        let dm = message(None, "~log");
+        let ctx = context_with_config(&dm, Some(LoggingConfig::new()));
+        let err = require_logging_channel(&ctx).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::FailedPrecondition(msg))
+                if msg == "Command must be run in a server."
+        ));
+    }
+
+    #[test]
+    fn test_split_args_unquoted() {
+        assert_eq!(split_args("~ban  123   spam"), vec!["~ban", "123", "spam"]);
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[test]
+    fn test_split_args_quoted() {
+        assert_eq!(
+            split_args(r#"~play "two words" three"#),
+            vec!["~play", "two words", "three"]);
+        assert_eq!(
+            split_args(r#"~say "" "unterminated quote"#),
+            vec!["~say", "", "unterminated quote"]);
+    }
+
+    #[test]
+    fn test_require_args() {
+        const USAGE: &str = "Usage: ~ban <user> <reason>";
+        let msg = message(Some(GuildId(1)), r#"~ban 123 "being rude""#);
+        assert_eq!(
+            require_args(&context(&msg), 2, USAGE).unwrap(),
+            vec!["123", "being rude"]);
+
+        let msg = message(Some(GuildId(1)), "~ban 123");
+        let err = require_args(&context(&msg), 2, USAGE).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::FailedPrecondition(msg)) if msg == USAGE
+        ));
+    }
+
+    #[test]
+    fn test_require_account_age() {
+        // UserId(3) was created at the very start of the Discord epoch.
+        let msg = message(Some(GuildId(1)), "~verify");
+        assert!(require_account_age(&context(&msg), Duration::from_secs(86400)).is_ok());
+    }
+
+    #[test]
+    fn test_check_account_age_new_account() {
+        let now = SystemTime::now();
+        let millis = now.duration_since(std::time::UNIX_EPOCH).unwrap().as_millis() as u64;
+        let user_id = UserId((millis - snowflake::DISCORD_EPOCH_MS) << 22);
+
+        assert!(check_account_age(user_id, Duration::from_secs(0), now).is_ok());
+        let err = check_account_age(user_id, Duration::from_secs(3 * 86400), now).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CommandError>(),
+            Some(CommandError::FailedPrecondition(msg))
+                if msg == "Your account is too new to use this command. Try again in 3 days."
+        ));
+    }
+
+    #[test]
+    fn test_format_wait() {
+        assert_eq!(format_wait(Duration::from_secs(0)), "1 minute");
+        assert_eq!(format_wait(Duration::from_secs(90)), "2 minutes");
+        assert_eq!(format_wait(Duration::from_secs(3600)), "1 hour");
+        assert_eq!(format_wait(Duration::from_secs(86400 + 1)), "2 days");
+    }
 }
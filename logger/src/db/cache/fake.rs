@@ -0,0 +1,241 @@
+//! An in-memory fake of Redis, shared by the tests of everything that talks to it.
+
+use super::{decode_config, encode_config, CacheKey, CachePrefix, CachedGuildConfig,
+            CompressionLevel};
+use crate::prelude::*;
+use futures::future::BoxFuture;
+use redis::{aio::ConnectionLike, RedisFuture, RedisResult, ToRedisArgs, Value};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    sync::Mutex,
+};
+
+type Args = Vec<Vec<u8>>;
+type ReadHook = Arc<dyn Fn() -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// The key guild configs are stored under.
+pub fn config_key(guild_id: GuildId) -> Vec<u8> {
+    CacheKey(CachePrefix::GuildConfigs, guild_id.0).to_redis_args().remove(0)
+}
+
+/// A configurable, in-memory stand-in for Redis. It implements the commands this crate uses:
+/// HGET, HSET, PUBLISH, the config compare-and-set script, and the rate limiting commands, both
+/// on their own and within MULTI/EXEC transactions. Clones share the same data.
+///
+/// Failing keys, racing writers, and work interleaved with reads in flight can be injected to
+/// test how callers handle them.
+#[derive(Clone, Default)]
+pub struct FakeRedis {
+    state: Arc<Mutex<State>>,
+    after_reads: Option<ReadHook>,
+}
+
+#[derive(Default)]
+struct State {
+    hashes: HashMap<(Vec<u8>, Vec<u8>), Vec<u8>>,
+    counters: HashMap<Vec<u8>, i64>,
+    pttl_ms: i64,
+    failing_keys: HashSet<Vec<u8>>,
+    racing_writes: VecDeque<Vec<u8>>,
+    transaction: Option<Vec<Value>>,
+    commands: Vec<Args>,
+}
+
+impl FakeRedis {
+
+    /// Stores a guild's config, as [`set_config`](super::set_config) would.
+    pub fn store_config<T>(&self, guild_id: GuildId, config: &T)
+        where T: protobuf::Message + CachedGuildConfig {
+        let encoded = encode_config(config, CompressionLevel::DEFAULT).unwrap();
+        let field = T::SUBKEY.to_redis_args().remove(0);
+        self.state().hashes.insert((config_key(guild_id), field), encoded);
+    }
+
+    /// Gets a guild's stored config, or None if it has not been stored.
+    pub fn stored_config<T>(&self, guild_id: GuildId) -> Option<T>
+        where T: protobuf::Message + CachedGuildConfig {
+        let field = T::SUBKEY.to_redis_args().remove(0);
+        let state = self.state();
+        let stored = state.hashes.get(&(config_key(guild_id), field))?;
+        Some(decode_config(Some(stored)).unwrap())
+    }
+
+    /// Makes every command on a key fail, as if the connection was reset.
+    pub fn fail_key(&self, key: Vec<u8>) {
+        self.state().failing_keys.insert(key);
+    }
+
+    /// Sets the TTL in milliseconds that PTTL replies with.
+    pub fn set_pttl(&self, pttl_ms: i64) {
+        self.state().pttl_ms = pttl_ms;
+    }
+
+    /// Has another client overwrite the hash field right after each of the next HGETs, storing
+    /// each of `values` in turn.
+    pub fn race_reads(&self, values: impl IntoIterator<Item=Vec<u8>>) {
+        self.state().racing_writes.extend(values);
+    }
+
+    /// Runs `hook` after each HGET is served, but before its reply is delivered, as if `hook`
+    /// ran while the read was in flight.
+    pub fn after_reads<F, Fut>(mut self, hook: F) -> Self
+        where F: Fn() -> Fut + Send + Sync + 'static,
+              Fut: Future<Output=()> + Send + 'static {
+        self.after_reads = Some(Arc::new(move || -> BoxFuture<'static, ()> { Box::pin(hook()) }));
+        self
+    }
+
+    /// Every command received so far, split into its arguments.
+    pub fn commands(&self) -> Vec<Args> {
+        self.state().commands.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().expect("Fake Redis state lock has been poisoned")
+    }
+
+}
+
+impl State {
+
+    fn execute(&mut self, args: Args) -> RedisResult<Value> {
+        self.commands.push(args.clone());
+        let command = args[0].to_ascii_uppercase();
+        // The compare-and-set script passes its key after the script and key count.
+        let key = if command == b"EVAL" { args.get(3) } else { args.get(1) };
+        if key.map_or(false, |key| self.failing_keys.contains(key)) {
+            return Err((redis::ErrorKind::IoError, "Connection reset").into());
+        }
+
+        let reply = match &command[..] {
+            b"MULTI" => {
+                self.transaction = Some(Vec::new());
+                return Ok(Value::Okay);
+            },
+            b"EXEC" => {
+                let replies = self.transaction.take().expect("EXEC without MULTI");
+                return Ok(Value::Bulk(replies));
+            },
+            b"HGET" => {
+                let field = (args[1].clone(), args[2].clone());
+                let current = self.hashes.get(&field).cloned();
+                if let Some(value) = self.racing_writes.pop_front() {
+                    self.hashes.insert(field, value);
+                }
+                current.map_or(Value::Nil, Value::Data)
+            },
+            b"HSET" => {
+                self.hashes.insert((args[1].clone(), args[2].clone()), args[3].clone());
+                Value::Int(1)
+            },
+            b"PUBLISH" => Value::Int(0),
+            b"EVAL" => {
+                let field = (args[3].clone(), args[4].clone());
+                if self.hashes.get(&field).map_or(&[][..], |v| &v[..]) != &args[5][..] {
+                    Value::Int(0)
+                } else {
+                    self.hashes.insert(field, args[6].clone());
+                    Value::Int(1)
+                }
+            },
+            // Only SET NX is supported.
+            b"SET" => {
+                if self.counters.contains_key(&args[1]) {
+                    Value::Nil
+                } else {
+                    self.counters.insert(args[1].clone(), parse_int(&args[2]));
+                    Value::Okay
+                }
+            },
+            b"INCRBY" => {
+                let count = self.counters.entry(args[1].clone()).or_insert(0);
+                *count += parse_int(&args[2]);
+                Value::Int(*count)
+            },
+            b"PTTL" => Value::Int(self.pttl_ms),
+            _ => panic!("Unexpected command: {}", String::from_utf8_lossy(&command)),
+        };
+
+        match &mut self.transaction {
+            Some(replies) => {
+                replies.push(reply);
+                Ok(Value::Status("QUEUED".to_owned()))
+            },
+            None => Ok(reply),
+        }
+    }
+
+}
+
+impl ConnectionLike for FakeRedis {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a redis::Cmd) -> RedisFuture<'a, Value> {
+        let mut commands = parse_packed_commands(&cmd.get_packed_command());
+        assert_eq!(commands.len(), 1);
+        let args = commands.remove(0);
+        let hook = match &args[0].to_ascii_uppercase()[..] {
+            b"HGET" => self.after_reads.clone(),
+            _ => None,
+        };
+        let reply = self.state().execute(args);
+        Box::pin(async move {
+            if let Some(hook) = hook {
+                hook().await;
+            }
+            reply
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        pipeline: &'a redis::Pipeline,
+        offset: usize,
+        count: usize
+    ) -> RedisFuture<'a, Vec<Value>> {
+        let replies: RedisResult<Vec<Value>> = {
+            let mut state = self.state();
+            parse_packed_commands(&pipeline.get_packed_pipeline())
+                .into_iter()
+                .map(|args| state.execute(args))
+                .collect()
+        };
+        Box::pin(async move { Ok(replies?.into_iter().skip(offset).take(count).collect()) })
+    }
+
+    fn get_db(&self) -> i64 {
+        0
+    }
+}
+
+fn parse_int(arg: &[u8]) -> i64 {
+    std::str::from_utf8(arg).unwrap().parse().unwrap()
+}
+
+/// Splits packed Redis commands back into their arguments.
+fn parse_packed_commands(packed: &[u8]) -> Vec<Args> {
+    fn read_line<'a>(packed: &mut &'a [u8]) -> &'a [u8] {
+        let end = packed.windows(2).position(|w| w == b"\r\n").expect("Unterminated line");
+        let line = &packed[..end];
+        *packed = &packed[end + 2..];
+        line
+    }
+    fn read_len(packed: &mut &[u8]) -> usize {
+        std::str::from_utf8(&read_line(packed)[1..]).unwrap().parse().unwrap()
+    }
+
+    let mut packed = packed;
+    let mut commands = Vec::new();
+    while !packed.is_empty() {
+        let count = read_len(&mut packed);
+        let command = (0..count)
+            .map(|_| {
+                let len = read_len(&mut packed);
+                let arg = packed[..len].to_vec();
+                packed = &packed[len + 2..];
+                arg
+            })
+            .collect();
+        commands.push(command);
+    }
+    commands
+}
@@ -0,0 +1,325 @@
+use super::{
+    decode_invalidation, fetch_guild_configs, get_config, set_config, update_config,
+    CachedGuildConfig, CompressionLevel, CONFIG_INVALIDATION_CHANNEL,
+};
+use crate::prelude::*;
+use dashmap::DashMap;
+use redis::aio::ConnectionLike;
+use std::{any::Any, time::Instant};
+
+type MemoKey = (GuildId, u8);
+type MemoEntry = (Arc<dyn Any + Send + Sync>, Instant);
+
+/// An optional in-process, read-through memo in front of the guild configs stored in Redis.
+///
+/// Parsed configs are kept for a short TTL. Writes made through the memo invalidate the
+/// corresponding entry. Every config write is also announced over Redis pub/sub, so writes made
+/// elsewhere (i.e. by another process) are observed promptly if [`listen_for_invalidations`] is
+/// running, and otherwise once the entry expires.
+///
+/// Every invalidation also bumps a per-config generation. Reads from Redis are only memoized if
+/// the generation is unchanged once they complete, so a read that raced with a write can't
+/// memoize the config from before the write.
+///
+/// [`listen_for_invalidations`]: Self::listen_for_invalidations
+#[derive(Clone)]
+pub struct GuildConfigMemo {
+    ttl: Duration,
+    compression_level: CompressionLevel,
+    entries: Arc<DashMap<MemoKey, MemoEntry>>,
+    generations: Arc<DashMap<MemoKey, u64>>,
+}
+
+impl GuildConfigMemo {
+
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_compression_level(ttl, CompressionLevel::DEFAULT)
+    }
+
+    /// Creates a memo that compresses the configs written through it at the given level.
+    pub fn with_compression_level(ttl: Duration, compression_level: CompressionLevel) -> Self {
+        Self {
+            ttl,
+            compression_level,
+            entries: Arc::new(DashMap::new()),
+            generations: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Gets a guild's config, only reading from Redis if it's not memoized or has expired.
+    pub async fn get<T, C>(&self, connection: &mut C, guild_id: GuildId) -> Result<Arc<T>>
+    where
+        T: protobuf::Message + CachedGuildConfig + Send + Sync + 'static,
+        C: ConnectionLike + Send,
+    {
+        if let Some(config) = self.lookup::<T>(guild_id, Instant::now()) {
+            return Ok(config);
+        }
+        let generation = self.generation(&(guild_id, T::SUBKEY));
+        let config = Arc::new(get_config(connection, guild_id).await?);
+        self.insert(guild_id, generation, config.clone());
+        Ok(config)
+    }
+
+    /// Memoizes the configs of many guilds up front, i.e. on startup, issuing at most
+    /// `concurrency` requests to Redis at once. Guilds whose configs fail to load are logged
+    /// and skipped, to be read through on demand instead. Returns the number of configs
+    /// memoized.
+    pub async fn warm<T, C, I>(&self, connection: &C, guild_ids: I, concurrency: usize) -> usize
+    where
+        T: protobuf::Message + CachedGuildConfig + Send + Sync + 'static,
+        C: ConnectionLike + Clone + Send,
+        I: IntoIterator<Item=GuildId>,
+    {
+        let generations: Vec<(GuildId, u64)> = guild_ids
+            .into_iter()
+            .map(|guild_id| (guild_id, self.generation(&(guild_id, T::SUBKEY))))
+            .collect();
+        let results: Vec<(GuildId, Result<T>)> = fetch_guild_configs(
+            connection, generations.iter().map(|kv| kv.0), concurrency).await;
+        let mut memoized = 0;
+        for ((guild_id, result), (_, generation)) in results.into_iter().zip(generations) {
+            match result {
+                Ok(config) => {
+                    if self.insert(guild_id, generation, Arc::new(config)) {
+                        memoized += 1;
+                    }
+                },
+                Err(err) => warn!("Failed to load the config for guild {}: {:?}", guild_id, err),
+            }
+        }
+        memoized
+    }
+
+    /// Writes a guild's config to Redis, invalidating the memoized copy.
+    pub async fn set<T, C>(&self, connection: &mut C, guild_id: GuildId, value: &T) -> Result<()>
+    where
+        T: protobuf::Message + CachedGuildConfig + Send + Sync + 'static,
+        C: ConnectionLike + Send,
+    {
+        let result = set_config(connection, guild_id, value, self.compression_level).await;
+        self.invalidate::<T>(guild_id);
+        result
+    }
+
+    /// Atomically updates a guild's config via [`update_config`], invalidating the memoized copy.
+    pub async fn update<T, C, F>(&self, connection: &mut C, guild_id: GuildId, update: F)
+        -> Result<T>
+    where
+        T: protobuf::Message + CachedGuildConfig + Send + Sync + 'static,
+        C: ConnectionLike + Send,
+        F: FnMut(&mut T) + Send,
+    {
+        let result = update_config(connection, guild_id, self.compression_level, update).await;
+        self.invalidate::<T>(guild_id);
+        result
+    }
+
+    /// Drops the memoized copy of a guild's config, if any.
+    pub fn invalidate<T: CachedGuildConfig>(&self, guild_id: GuildId) {
+        self.invalidate_key((guild_id, T::SUBKEY));
+    }
+
+    /// Subscribes to config writes announced by every process, including this one, and drops
+    /// the corresponding memoized configs. Runs until the subscription is lost, so it should be
+    /// spawned as its own task and restarted on error.
+    ///
+    /// Invalidations also keep reads in flight from memoizing the config from before the write.
+    /// At worst they cost an extra read, so duplicate or redundant invalidations are harmless.
+    /// Redis pub/sub does not store messages, however: writes announced while the subscription
+    /// is down are never delivered, so the TTL still bounds how stale a read may be.
+    pub async fn listen_for_invalidations(&self, client: &redis::Client) -> Result<()> {
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(CONFIG_INVALIDATION_CHANNEL).await?;
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            if !self.apply_invalidation(message.get_payload_bytes()) {
+                warn!("Received a malformed config invalidation: {:?}",
+                      message.get_payload_bytes());
+            }
+        }
+        anyhow::bail!("Lost the config invalidation subscription.");
+    }
+
+    /// Drops the memoized config named by an invalidation message. Returns false if the message
+    /// is malformed.
+    fn apply_invalidation(&self, payload: &[u8]) -> bool {
+        match decode_invalidation(payload) {
+            Some(key) => {
+                self.invalidate_key(key);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Bumps the config's generation before dropping its entry, so that reads already in flight
+    /// don't memoize the config from before the invalidation.
+    fn invalidate_key(&self, key: MemoKey) {
+        *self.generations.entry(key).or_insert(0) += 1;
+        self.entries.remove(&key);
+    }
+
+    fn generation(&self, key: &MemoKey) -> u64 {
+        self.generations.get(key).map(|generation| *generation).unwrap_or(0)
+    }
+
+    /// Memoizes a config read from Redis, unless it has been invalidated since `generation` was
+    /// taken before the read. Returns whether the config was memoized.
+    fn insert<T>(&self, guild_id: GuildId, generation: u64, config: Arc<T>) -> bool
+    where
+        T: CachedGuildConfig + Send + Sync + 'static,
+    {
+        let key = (guild_id, T::SUBKEY);
+        // Holding the generation's guard blocks invalidations until the entry is inserted, at
+        // which point they will drop it.
+        let current = self.generations.get(&key);
+        if current.as_deref().copied().unwrap_or(0) != generation {
+            return false;
+        }
+        let entry: Arc<dyn Any + Send + Sync> = config;
+        self.entries.insert(key, (entry, Instant::now()));
+        true
+    }
+
+    fn lookup<T>(&self, guild_id: GuildId, now: Instant) -> Option<Arc<T>>
+    where
+        T: CachedGuildConfig + Send + Sync + 'static,
+    {
+        let key = (guild_id, T::SUBKEY);
+        let ttl = self.ttl;
+        let expired = |inserted: &Instant| now.saturating_duration_since(*inserted) >= ttl;
+        if let Some(entry) = self.entries.get(&key) {
+            let (config, inserted) = entry.value();
+            if !expired(inserted) {
+                return Arc::clone(config).downcast::<T>().ok();
+            }
+        }
+        self.entries.remove_if(&key, |_, (_, inserted)| expired(inserted));
+        None
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fake::FakeRedis;
+    use crate::proto::guild_configs::MusicConfig;
+
+    #[tokio::test]
+    async fn test_memo_hit_and_miss() {
+        let memo = GuildConfigMemo::new(Duration::from_secs(60));
+        let mut connection = FakeRedis::default();
+
+        let config: Arc<MusicConfig> = memo.get(&mut connection, GuildId(1)).await.unwrap();
+        assert_eq!(config.get_volume(), 100);
+        assert_eq!(connection.commands().len(), 1);
+
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        assert_eq!(connection.commands().len(), 1);
+
+        // Other guilds are memoized separately.
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(2)).await.unwrap();
+        assert_eq!(connection.commands().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_memo_expires() {
+        let memo = GuildConfigMemo::new(Duration::from_secs(0));
+        let mut connection = FakeRedis::default();
+
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        assert_eq!(connection.commands().len(), 2);
+        assert!(memo.entries.len() <= 1);
+    }
+
+    #[tokio::test]
+    async fn test_memo_invalidated_by_broadcast() {
+        use crate::proto::guild_configs::LoggingConfig;
+        let memo = GuildConfigMemo::new(Duration::from_secs(60));
+        let mut connection = FakeRedis::default();
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        memo.get::<LoggingConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+
+        // The payload another process publishes when it writes the guild's music config.
+        let payload = super::super::encode_invalidation(GuildId(1), MusicConfig::SUBKEY);
+        assert!(memo.apply_invalidation(&payload));
+        assert!(!memo.apply_invalidation(b"garbage"));
+
+        assert!(memo.lookup::<MusicConfig>(GuildId(1), Instant::now()).is_none());
+        assert!(memo.lookup::<LoggingConfig>(GuildId(1), Instant::now()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_memo_skips_reads_racing_broadcasts() {
+        let memo = GuildConfigMemo::new(Duration::from_secs(60));
+        // The write is made by another process, and only announced to the memo.
+        let racing_memo = memo.clone();
+        let mut connection = FakeRedis::default().after_reads(move || {
+            let payload = super::super::encode_invalidation(GuildId(1), MusicConfig::SUBKEY);
+            assert!(racing_memo.apply_invalidation(&payload));
+            async {}
+        });
+
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        assert!(memo.lookup::<MusicConfig>(GuildId(1), Instant::now()).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memo_warm() {
+        let memo = GuildConfigMemo::new(Duration::from_secs(60));
+        let connection = super::super::tests::flaky_redis();
+        let guild_ids = (1..=3).map(GuildId);
+        let memoized = memo.warm::<MusicConfig, _, _>(&connection, guild_ids, 2).await;
+        assert_eq!(memoized, 2);
+
+        let now = Instant::now();
+        let config = memo.lookup::<MusicConfig>(GuildId(3), now).unwrap();
+        assert_eq!(config.get_volume(), 3);
+        assert!(memo.lookup::<MusicConfig>(GuildId(1), now).is_some());
+        assert!(memo.lookup::<MusicConfig>(GuildId(2), now).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_memo_skips_reads_racing_writes() {
+        let memo = GuildConfigMemo::new(Duration::from_secs(60));
+        let redis = FakeRedis::default();
+        let (racing_memo, racing_redis) = (memo.clone(), redis.clone());
+        let mut connection = redis.clone().after_reads(move || {
+            let (memo, mut redis) = (racing_memo.clone(), racing_redis.clone());
+            async move {
+                let mut config = MusicConfig::new();
+                config.set_volume(50);
+                memo.set(&mut redis, GuildId(1), &config).await.unwrap();
+            }
+        });
+
+        // The read completes with the config from before the write, which must not be memoized.
+        let config: Arc<MusicConfig> = memo.get(&mut connection, GuildId(1)).await.unwrap();
+        assert_eq!(config.get_volume(), 100);
+        assert!(memo.lookup::<MusicConfig>(GuildId(1), Instant::now()).is_none());
+
+        // Reads that don't race a write are memoized as usual.
+        let config: Arc<MusicConfig> = memo.get(&mut redis.clone(), GuildId(1)).await.unwrap();
+        assert_eq!(config.get_volume(), 50);
+        assert!(memo.lookup::<MusicConfig>(GuildId(1), Instant::now()).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_memo_invalidated_by_writes() {
+        let memo = GuildConfigMemo::new(Duration::from_secs(60));
+        let mut connection = FakeRedis::default();
+
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        memo.set(&mut connection, GuildId(1), &MusicConfig::new()).await.unwrap();
+        assert!(memo.entries.is_empty());
+
+        memo.get::<MusicConfig, _>(&mut connection, GuildId(1)).await.unwrap();
+        memo.update(&mut connection, GuildId(1), |config: &mut MusicConfig| {
+            config.set_volume(50);
+        }).await.unwrap();
+        assert!(memo.entries.is_empty());
+    }
+}
@@ -10,18 +10,60 @@ use redis::{self, RedisWrite, ToRedisArgs, FromRedisValue, aio::ConnectionLike};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use std::io::prelude::*;
+use std::time::Instant;
 use twilight_model::id::*;
 
+mod memo;
+mod rate_limit;
+#[cfg(test)]
+pub mod fake;
+
+pub use self::memo::GuildConfigMemo;
+pub use self::rate_limit::{RateLimit, RateLimitStatus};
+
 /// The single byte compression mode header for values stored in Redis.
 #[repr(u8)]
 #[derive(FromPrimitive)]
 enum CompressionMode {
     /// Uncompressed. The value is entirely uncompressed and can be used as is.
     Uncompressed = 0,
-    /// Compressed with zlib. Default compression level: 6.
+    /// Compressed with zlib. Default compression level: 6, see [`CompressionLevel`].
     Zlib = 1,
 }
 
+/// The zlib compression level (0-9) used when writing values to Redis. Higher levels trade CPU
+/// time for smaller payloads. Payloads written at any level can be read back regardless of the
+/// level used by the reader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompressionLevel(u32);
+
+impl CompressionLevel {
+    pub const DEFAULT: Self = Self(6);
+
+    /// Creates a compression level. Values above 9 are clamped.
+    pub const fn new(level: u32) -> Self {
+        Self(if level > 9 { 9 } else { level })
+    }
+}
+
+impl Default for CompressionLevel {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Truncates content to at most `max_chars` characters. Returns whether the content was
+/// truncated.
+fn truncate_content(content: &mut String, max_chars: usize) -> bool {
+    match content.char_indices().nth(max_chars) {
+        Some((idx, _)) => {
+            content.truncate(idx);
+            true
+        },
+        None => false,
+    }
+}
+
 /// The single byte key prefix for all keys stored in Redis.
 #[repr(u8)]
 #[derive(Copy, Clone)]
@@ -33,6 +75,10 @@ pub(super) enum CachePrefix {
     OnlineStatus = 2_u8,
     /// Messages cached.
     Messages = 3_u8,
+    /// Arbitrary compressed binary payloads, see [`CachedBytes`].
+    Bytes = 4_u8,
+    /// Counters for rate limited actions, see [`RateLimit`].
+    RateLimits = 5_u8,
 }
 
 /// A prefixed key schema for 64-bit integer keys. Implements ToRedisArgs, so its generically
@@ -116,13 +162,47 @@ pub struct CachedMessage {
     proto: Protobuf<CachedMessageProto>,
 }
 
+/// Whether messages should be cached for a guild. Cached messages are only used for logging
+/// deleted and edited messages, so there's no need to store them if neither is enabled.
+pub fn should_cache_messages(config: &LoggingConfig) -> bool {
+    config.get_deleted_messages().get_enabled() || config.get_edited_messages().get_enabled()
+}
+
+/// Checks [`should_cache_messages`] against a guild's logging config, read through `memo`. If
+/// the config cannot be read, messages are cached anyway: a message that isn't cached can never
+/// be logged, while an unneeded one just expires.
+pub async fn should_cache_guild_messages<C: ConnectionLike + Send>(
+    memo: &GuildConfigMemo,
+    connection: &mut C,
+    guild_id: GuildId
+) -> bool {
+    match memo.get::<LoggingConfig, _>(connection, guild_id).await {
+        Ok(config) => should_cache_messages(&config),
+        Err(err) => {
+            warn!("Failed to read the logging config for guild {}, caching messages anyway: {:?}",
+                  guild_id, err);
+            true
+        },
+    }
+}
+
 impl CachedMessage {
 
-    pub fn new(message: twilight_model::channel::Message) -> Self {
+    /// Creates a cached message. If `max_content_length` is set, content longer than that many
+    /// characters is truncated and the message is flagged as truncated. None disables
+    /// truncation.
+    pub fn new(
+        message: twilight_model::channel::Message,
+        max_content_length: Option<usize>
+    ) -> Self {
         let mut msg = CachedMessageProto::new();
         msg.set_id(message.id.0);
         msg.set_channel_id(message.channel_id.0);
-        msg.set_content(message.content);
+        let mut content = message.content;
+        if let Some(max_length) = max_content_length {
+            msg.set_content_truncated(truncate_content(&mut content, max_length));
+        }
+        msg.set_content(content);
         if let Some(guild_id) = message.guild_id {
             msg.set_guild_id(guild_id.0)
         }
@@ -132,19 +212,57 @@ impl CachedMessage {
         user.set_id(author.id.0);
         user.set_username(author.name.clone());
         user.set_discriminator(message.author.discriminator() as u32);
+        if let Some(ref avatar) = author.avatar {
+            user.set_avatar(avatar.clone());
+        }
 
         Self {
             proto: Protobuf(msg)
         }
     }
 
+    /// Gets the CDN URL of the author's avatar as it was when the message was cached. Messages
+    /// cached before avatars were stored get the author's default avatar.
+    pub fn author_avatar_url(&self) -> String {
+        let author = self.proto.0.get_author();
+        let avatar = if author.has_avatar() { Some(author.get_avatar()) } else { None };
+        crate::cache::avatar_url(
+            UserId(author.get_id()),
+            author.get_discriminator() as u16,
+            avatar)
+    }
+
     pub fn flush(self) -> redis::Pipeline {
+        let mut pipeline = redis::pipe();
+        pipeline.atomic();
+        self.write_to(&mut pipeline);
+        pipeline
+    }
+
+    fn write_to(self, pipeline: &mut redis::Pipeline) {
         let channel_id = self.proto.0.get_channel_id();
         let id = self.proto.0.get_id();
         let key = CacheKey(CachePrefix::Messages, (channel_id, id));
-        let mut pipeline = redis::pipe();
-        pipeline.atomic().set(key, self.proto).expire(key, 3600);
-        pipeline
+        pipeline.set(key, self.proto).expire(key, 3600);
+    }
+
+    /// Extends the lifetime of a cached message, so that frequently referenced messages remain
+    /// cached. Returns false if the message is not cached.
+    pub async fn touch<C: ConnectionLike + Send>(
+        connection: &mut C,
+        channel_id: ChannelId,
+        id: MessageId,
+        ttl: Duration
+    ) -> Result<bool> {
+        let touched: bool = Self::touch_cmd(channel_id, id, ttl)
+            .query_async(connection)
+            .await?;
+        Ok(touched)
+    }
+
+    fn touch_cmd(channel_id: ChannelId, id: MessageId, ttl: Duration) -> redis::Cmd {
+        let key = CacheKey(CachePrefix::Messages, (channel_id.0, id.0));
+        redis::Cmd::expire(key, ttl.as_secs() as usize)
     }
 
     pub fn delete(channel_id: ChannelId, id: MessageId) -> redis::Cmd {
@@ -164,6 +282,82 @@ impl CachedMessage {
 
 }
 
+/// Accumulates cached messages so that they can be written to Redis in a single atomic pipeline,
+/// rather than a round trip per message. A batch should be flushed once it reaches `max_size`
+/// messages or its oldest message has waited `max_delay`, whichever comes first.
+pub struct MessageFlushBatch {
+    pipeline: redis::Pipeline,
+    len: usize,
+    oldest: Option<Instant>,
+    max_size: usize,
+    max_delay: Duration,
+}
+
+impl MessageFlushBatch {
+
+    pub fn new(max_size: usize, max_delay: Duration) -> Self {
+        Self {
+            pipeline: Self::empty_pipeline(),
+            len: 0,
+            oldest: None,
+            max_size,
+            max_delay,
+        }
+    }
+
+    /// Adds a message to the batch. Returns true if the batch is now full and should be flushed.
+    pub fn push(&mut self, message: CachedMessage) -> bool {
+        self.oldest.get_or_insert_with(Instant::now);
+        message.write_to(&mut self.pipeline);
+        self.len += 1;
+        self.len >= self.max_size
+    }
+
+    /// Gets the number of messages in the batch.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Checks if the batch is full, or its oldest message has waited longer than the maximum
+    /// delay as of `now`. Empty batches never need to be flushed.
+    pub fn should_flush(&self, now: Instant) -> bool {
+        match self.oldest {
+            Some(oldest) => {
+                self.len >= self.max_size || now.saturating_duration_since(oldest) >= self.max_delay
+            },
+            None => false,
+        }
+    }
+
+    /// Takes the accumulated pipeline, leaving the batch empty. Returns None if the batch is
+    /// empty.
+    pub fn take(&mut self) -> Option<redis::Pipeline> {
+        if self.is_empty() {
+            return None;
+        }
+        self.len = 0;
+        self.oldest = None;
+        Some(std::mem::replace(&mut self.pipeline, Self::empty_pipeline()))
+    }
+
+    fn empty_pipeline() -> redis::Pipeline {
+        let mut pipeline = redis::pipe();
+        pipeline.atomic();
+        pipeline
+    }
+
+}
+
+impl FromRedisValue for CachedMessage {
+    fn from_redis_value(value: &redis::Value) -> redis::RedisResult<Self> {
+        Ok(Self { proto: Protobuf::from_redis_value(value)? })
+    }
+}
+
 pub struct Protobuf<T: protobuf::Message>(T);
 
 impl<T: protobuf::Message> Protobuf<T> {
@@ -220,8 +414,9 @@ impl<T: protobuf::Message> FromRedisValue for Protobuf<T> {
 
 }
 
-fn compress_payload(payload: &[u8]) -> Result<Vec<u8>> {
-    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::new(6));
+fn compress_payload(payload: &[u8], level: CompressionLevel) -> Result<Vec<u8>> {
+    let compression = flate2::Compression::new(level.0);
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), compression);
     encoder.write_all(&payload)?;
     let mut output = encoder.finish()?;
     let compression_mode = if output.len() < payload.len() {
@@ -273,34 +468,257 @@ impl<T: protobuf::Message + CachedGuildConfig + Send> Cacheable for T {
         I: Into<GuildId> + Send,
         C: ConnectionLike + Send,
     {
-        let key = CacheKey(CachePrefix::GuildConfigs, key.into().0);
-        let response: Option<Vec<u8>> = redis::Cmd::hget(key, Self::SUBKEY)
+        return Ok(Some(get_config(connection, key.into()).await?));
+    }
+
+    async fn set<I, C>(connection: &mut C, key: I, value: &Self) -> Result<()>
+    where
+        I: Into<GuildId> + Send,
+        C: ConnectionLike + Send,
+    {
+        set_config(connection, key.into(), value, CompressionLevel::DEFAULT).await
+    }
+}
+
+/// Fetches a guild config. If nothing has been stored, returns the default value for the type.
+pub async fn get_config<T, C>(connection: &mut C, guild_id: GuildId) -> Result<T>
+where
+    T: protobuf::Message + CachedGuildConfig,
+    C: ConnectionLike + Send,
+{
+    let key = CacheKey(CachePrefix::GuildConfigs, guild_id.0);
+    let response: Option<Vec<u8>> = redis::Cmd::hget(key, T::SUBKEY)
+        .query_async(connection)
+        .await?;
+    decode_config(response.as_deref())
+}
+
+/// Writes a guild config like [`Cacheable::set`] does, compressed at the given level.
+pub async fn set_config<T, C>(
+    connection: &mut C,
+    guild_id: GuildId,
+    value: &T,
+    level: CompressionLevel
+) -> Result<()>
+where
+    T: protobuf::Message + CachedGuildConfig,
+    C: ConnectionLike + Send,
+{
+    write_config_pipeline(guild_id, T::SUBKEY, encode_config(value, level)?)
+        .query_async::<_, ()>(connection)
+        .await?;
+    Ok(())
+}
+
+/// The Redis pub/sub channel every guild config write is announced on, so that other processes
+/// can drop their memoized copies. See [`GuildConfigMemo::listen_for_invalidations`].
+pub const CONFIG_INVALIDATION_CHANNEL: &str = "hourai:config-invalidations";
+
+/// Encodes a config invalidation message: the guild ID in big-endian, then the config subkey.
+fn encode_invalidation(guild_id: GuildId, subkey: u8) -> [u8; 9] {
+    let mut payload = [subkey; 9];
+    BigEndian::write_u64(&mut payload[0..8], guild_id.0);
+    payload
+}
+
+fn decode_invalidation(payload: &[u8]) -> Option<(GuildId, u8)> {
+    if payload.len() != 9 {
+        return None;
+    }
+    Some((GuildId(BigEndian::read_u64(&payload[0..8])), payload[8]))
+}
+
+/// Writes an encoded config and announces the write on [`CONFIG_INVALIDATION_CHANNEL`] in a
+/// single transaction.
+fn write_config_pipeline(guild_id: GuildId, subkey: u8, encoded: Vec<u8>) -> redis::Pipeline {
+    let key = CacheKey(CachePrefix::GuildConfigs, guild_id.0);
+    let mut pipeline = redis::pipe();
+    pipeline
+        .atomic()
+        .hset(key, subkey, encoded).ignore()
+        .publish(CONFIG_INVALIDATION_CHANNEL, &encode_invalidation(guild_id, subkey)[..]).ignore();
+    pipeline
+}
+
+/// An arbitrary binary payload, i.e. a rendered image or a JSON blob, cached in Redis. Payloads
+/// are compressed like configs are, and expire after a TTL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedBytes(pub Vec<u8>);
+
+impl CachedBytes {
+    /// The TTL used by [`Cacheable::set`].
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(3600);
+
+    /// Stores the payload under a key compressed at the given level, expiring it after `ttl`.
+    pub async fn set_with_ttl<C: ConnectionLike + Send>(
+        connection: &mut C,
+        key: u64,
+        value: &Self,
+        ttl: Duration,
+        level: CompressionLevel
+    ) -> Result<()> {
+        value.set_cmd(key, ttl, level)?.query_async(connection).await?;
+        Ok(())
+    }
+
+    fn set_cmd(&self, key: u64, ttl: Duration, level: CompressionLevel) -> Result<redis::Cmd> {
+        let key = CacheKey(CachePrefix::Bytes, key);
+        Ok(redis::Cmd::set_ex(key, compress_payload(&self.0, level)?, ttl.as_secs() as usize))
+    }
+
+    fn decode(payload: &[u8]) -> Result<Self> {
+        Ok(Self(decompress_payload(payload)?))
+    }
+}
+
+#[async_trait]
+impl Cacheable for CachedBytes {
+    type Key = u64;
+
+    async fn get<I, C>(connection: &mut C, key: I) -> Result<Option<Self>>
+    where
+        I: Into<u64> + Send,
+        C: ConnectionLike + Send,
+    {
+        let key = CacheKey(CachePrefix::Bytes, key.into());
+        let response: Option<Vec<u8>> = redis::Cmd::get(key)
             .query_async(connection)
             .await?;
-        let proto = if let Some(payload) = response {
-            let decomp = decompress_payload(&payload[..])?;
-            Self::parse_from_bytes(&decomp[..])?
-        } else {
-            // If nothing has been found, return the default value for the type.
-            Self::new()
-        };
-        return Ok(Some(proto));
+        response.map(|payload| Self::decode(&payload[..])).transpose()
     }
 
     async fn set<I, C>(connection: &mut C, key: I, value: &Self) -> Result<()>
     where
-        I: Into<GuildId> + Send,
+        I: Into<u64> + Send,
         C: ConnectionLike + Send,
     {
-        let mut proto_enc: Vec<u8> = Vec::new();
-        value.write_to_vec(&mut proto_enc)?;
-        let compressed = compress_payload(&proto_enc[..])?;
-        let key = CacheKey(CachePrefix::GuildConfigs, key.into().0);
-        redis::Cmd::hset(key, Self::SUBKEY, compressed)
+        Self::set_with_ttl(
+            connection, key.into(), value, Self::DEFAULT_TTL, CompressionLevel::DEFAULT).await
+    }
+}
+
+/// Decodes a stored guild config. If nothing has been stored, returns the default value for the
+/// type.
+fn decode_config<T: protobuf::Message>(payload: Option<&[u8]>) -> Result<T> {
+    match payload {
+        Some(payload) => {
+            let decomp = decompress_payload(payload)?;
+            Ok(T::parse_from_bytes(&decomp[..])?)
+        },
+        None => Ok(T::new()),
+    }
+}
+
+fn encode_config(value: &impl protobuf::Message, level: CompressionLevel) -> Result<Vec<u8>> {
+    let mut proto_enc: Vec<u8> = Vec::new();
+    value.write_to_vec(&mut proto_enc)?;
+    compress_payload(&proto_enc[..], level)
+}
+
+/// The maximum number of times [`update_config`] will attempt to apply an update before giving
+/// up due to contention.
+pub const CONFIG_UPDATE_ATTEMPTS: usize = 5;
+
+/// Writes a guild config only if the stored value still matches the one it was derived from,
+/// then announces the write like [`write_config_pipeline`] does. Returns 1 if the config was
+/// written, 0 otherwise.
+///
+/// KEYS[1] is the guild's config hash. ARGV holds the config subkey, the expected value (empty
+/// if no config was stored), the new value, the invalidation channel and message. Stored
+/// configs always start with a compression header byte, so they are never empty.
+const COMPARE_AND_SET_CONFIG: &str = r"
+local current = redis.call('HGET', KEYS[1], ARGV[1])
+if (current or '') ~= ARGV[2] then
+    return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+redis.call('PUBLISH', ARGV[4], ARGV[5])
+return 1
+";
+
+fn compare_and_set_config_cmd(
+    guild_id: GuildId,
+    subkey: u8,
+    expected: Option<&[u8]>,
+    encoded: Vec<u8>
+) -> redis::Cmd {
+    let mut cmd = redis::cmd("EVAL");
+    cmd.arg(COMPARE_AND_SET_CONFIG)
+        .arg(1)
+        .arg(CacheKey(CachePrefix::GuildConfigs, guild_id.0))
+        .arg(subkey)
+        .arg(expected.unwrap_or_default())
+        .arg(encoded)
+        .arg(CONFIG_INVALIDATION_CHANNEL)
+        .arg(&encode_invalidation(guild_id, subkey)[..]);
+    cmd
+}
+
+/// Atomically reads, modifies, and writes back a guild config.
+///
+/// The write is a compare-and-set done in a Lua script, so it only goes through if the stored
+/// config is unchanged since it was read. If another client writes to the same config in the
+/// meantime, the update is retried from a fresh read, up to [`CONFIG_UPDATE_ATTEMPTS`] times.
+/// `update` may therefore be called more than once. The config is written compressed at the
+/// given level.
+///
+/// No state is kept on the connection between commands, so this is safe to use with a
+/// multiplexed connection like [`RedisPool`](super::RedisPool).
+pub async fn update_config<T, C, F>(
+    connection: &mut C,
+    guild_id: GuildId,
+    level: CompressionLevel,
+    mut update: F
+) -> Result<T>
+where
+    T: protobuf::Message + CachedGuildConfig + Send,
+    C: ConnectionLike + Send,
+    F: FnMut(&mut T) + Send,
+{
+    let key = CacheKey(CachePrefix::GuildConfigs, guild_id.0);
+    for _ in 0..CONFIG_UPDATE_ATTEMPTS {
+        let current: Option<Vec<u8>> = redis::Cmd::hget(key, T::SUBKEY)
             .query_async(connection)
             .await?;
-        return Ok(());
+        let mut config: T = decode_config(current.as_deref())?;
+        update(&mut config);
+        let encoded = encode_config(&config, level)?;
+        let written: bool =
+            compare_and_set_config_cmd(guild_id, T::SUBKEY, current.as_deref(), encoded)
+                .query_async(connection)
+                .await?;
+        if written {
+            return Ok(config);
+        }
     }
+    anyhow::bail!("Failed to update config for guild {}: too much contention", guild_id);
+}
+
+/// Fetches a guild config for every guild in `guild_ids`, issuing at most `concurrency` requests
+/// to Redis at once. See [`GuildConfigMemo::warm`] to memoize the fetched configs on startup.
+///
+/// A failure to fetch or parse any individual config does not abort the batch. Each guild's
+/// result is returned alongside its ID in completion order, not input order.
+pub async fn fetch_guild_configs<T, C>(
+    connection: &C,
+    guild_ids: impl IntoIterator<Item=GuildId>,
+    concurrency: usize
+) -> Vec<(GuildId, Result<T>)>
+where
+    T: protobuf::Message + CachedGuildConfig,
+    C: ConnectionLike + Clone + Send,
+{
+    let requests = guild_ids.into_iter().map(|guild_id| {
+        let mut connection = connection.clone();
+        async move {
+            let result = get_config(&mut connection, guild_id).await;
+            (guild_id, result)
+        }
+    });
+    futures::stream::iter(requests)
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await
 }
 
 pub trait CachedGuildConfig {
@@ -322,3 +740,317 @@ guild_config!(ValidationConfig, 3_u8);
 guild_config!(MusicConfig, 4_u8);
 guild_config!(AnnouncementConfig, 5_u8);
 guild_config!(RoleConfig, 6_u8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::fake::{config_key, FakeRedis};
+
+    fn music_config(volume: u32) -> Vec<u8> {
+        let mut config = MusicConfig::new();
+        config.set_volume(volume);
+        encode_config(&config, CompressionLevel::DEFAULT).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_update_config_retries_on_contention() {
+        let mut connection = FakeRedis::default();
+        connection.race_reads(vec![music_config(20), music_config(21)]);
+        let mut calls = 0;
+        let level = CompressionLevel::DEFAULT;
+        let config: MusicConfig = update_config(&mut connection, GuildId(1), level, |config| {
+            calls += 1;
+            config.set_volume(config.get_volume() + 10);
+        }).await.unwrap();
+
+        // The update is applied on top of the last racing write, rather than overwriting it.
+        assert_eq!(config.get_volume(), 31);
+        assert_eq!(calls, 3);
+        let stored: Option<MusicConfig> = connection.stored_config(GuildId(1));
+        assert_eq!(stored.map(|config| config.get_volume()), Some(31));
+
+        // Each attempt reads and then compare-and-sets the guild's configs.
+        let key = config_key(GuildId(1));
+        let commands = connection.commands();
+        assert_eq!(commands.len(), 6);
+        for attempt in commands.chunks(2) {
+            assert_eq!(attempt[0][..3], [b"HGET".to_vec(), key.clone(), b"4".to_vec()]);
+            assert_eq!(attempt[1][0], b"EVAL");
+            assert_eq!(attempt[1][2..5], [b"1".to_vec(), key.clone(), b"4".to_vec()]);
+        }
+    }
+
+    /// A fake Redis where every guild has a music config stored, with the guild's ID as its
+    /// volume, except reading the configs of guild 2 fails.
+    pub(super) fn flaky_redis() -> FakeRedis {
+        let redis = FakeRedis::default();
+        for guild_id in (1..=4).map(GuildId) {
+            let mut config = MusicConfig::new();
+            config.set_volume(guild_id.0 as u32);
+            redis.store_config(guild_id, &config);
+        }
+        redis.fail_key(config_key(GuildId(2)));
+        redis
+    }
+
+    #[tokio::test]
+    async fn test_fetch_guild_configs_continues_past_failures() {
+        let connection = flaky_redis();
+        let guild_ids = (1..=4).map(GuildId);
+        let mut results: Vec<(GuildId, Result<MusicConfig>)> =
+            fetch_guild_configs(&connection, guild_ids, 2).await;
+        results.sort_by_key(|(guild_id, _)| *guild_id);
+
+        assert_eq!(results.len(), 4);
+        for (guild_id, result) in results {
+            if guild_id == GuildId(2) {
+                assert!(result.is_err());
+            } else {
+                assert_eq!(result.unwrap().get_volume(), guild_id.0 as u32);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_config_gives_up() {
+        let mut connection = FakeRedis::default();
+        connection.race_reads((0..CONFIG_UPDATE_ATTEMPTS as u32).map(|i| music_config(20 + i)));
+        let level = CompressionLevel::DEFAULT;
+        let result = update_config(&mut connection, GuildId(1), level, |config: &mut MusicConfig| {
+            config.set_volume(50);
+        }).await;
+
+        assert!(result.is_err());
+        assert_eq!(connection.commands().len(), 2 * CONFIG_UPDATE_ATTEMPTS);
+        let stored: Option<MusicConfig> = connection.stored_config(GuildId(1));
+        assert_ne!(stored.map(|config| config.get_volume()), Some(50));
+    }
+
+    #[test]
+    fn test_config_writes_publish_invalidations() {
+        let encoded = encode_config(&MusicConfig::new(), CompressionLevel::DEFAULT).unwrap();
+        let key = CacheKey(CachePrefix::GuildConfigs, 1_u64);
+        let mut expected = redis::pipe();
+        expected
+            .atomic()
+            .cmd("HSET").arg(key).arg(4_u8).arg(encoded.clone()).ignore()
+            .cmd("PUBLISH").arg(CONFIG_INVALIDATION_CHANNEL).arg(&[0, 0, 0, 0, 0, 0, 0, 1, 4][..])
+            .ignore();
+        assert_eq!(
+            write_config_pipeline(GuildId(1), MusicConfig::SUBKEY, encoded).get_packed_pipeline(),
+            expected.get_packed_pipeline());
+    }
+
+    #[test]
+    fn test_invalidation_round_trip() {
+        let payload = encode_invalidation(GuildId(u64::MAX - 1), 3);
+        assert_eq!(decode_invalidation(&payload), Some((GuildId(u64::MAX - 1), 3)));
+        assert_eq!(decode_invalidation(&payload[..8]), None);
+        assert_eq!(decode_invalidation(b""), None);
+    }
+
+    #[test]
+    fn test_should_cache_messages() {
+        let mut config = LoggingConfig::new();
+        assert!(!should_cache_messages(&config));
+
+        config.mut_deleted_messages().set_enabled(true);
+        assert!(should_cache_messages(&config));
+
+        config.mut_deleted_messages().set_enabled(false);
+        config.mut_edited_messages().set_enabled(true);
+        assert!(should_cache_messages(&config));
+
+        config.mut_edited_messages().set_enabled(false);
+        assert!(!should_cache_messages(&config));
+    }
+
+    #[tokio::test]
+    async fn test_should_cache_guild_messages() {
+        let should_cache = |config: Option<LoggingConfig>| async move {
+            let mut connection = FakeRedis::default();
+            match config {
+                Some(config) => connection.store_config(GuildId(1), &config),
+                None => connection.fail_key(config_key(GuildId(1))),
+            }
+            let memo = GuildConfigMemo::new(Duration::from_secs(60));
+            let first = should_cache_guild_messages(&memo, &mut connection, GuildId(1)).await;
+            let second = should_cache_guild_messages(&memo, &mut connection, GuildId(1)).await;
+            assert_eq!(first, second);
+            (first, connection.commands().len())
+        };
+
+        // Logging configs are only read from Redis once.
+        assert_eq!(should_cache(Some(LoggingConfig::new())).await, (false, 1));
+        let mut config = LoggingConfig::new();
+        config.mut_edited_messages().set_enabled(true);
+        assert_eq!(should_cache(Some(config)).await, (true, 1));
+
+        // Messages are still cached if the config can't be read.
+        assert_eq!(should_cache(None).await, (true, 2));
+    }
+
+    #[test]
+    fn test_compression_levels_round_trip() {
+        let payload = "hourai ".repeat(100).into_bytes();
+        for level in &[0, 6, 9] {
+            let compressed = compress_payload(&payload, CompressionLevel::new(*level)).unwrap();
+            assert_eq!(decompress_payload(&compressed).unwrap(), payload);
+        }
+
+        let fast = compress_payload(&payload, CompressionLevel::new(1)).unwrap();
+        let best = compress_payload(&payload, CompressionLevel::new(9)).unwrap();
+        assert_eq!(best[0], CompressionMode::Zlib as u8);
+        assert!(best.len() <= fast.len());
+    }
+
+    #[test]
+    fn test_compression_level_clamped() {
+        assert_eq!(CompressionLevel::default(), CompressionLevel::new(6));
+        assert_eq!(CompressionLevel::new(12), CompressionLevel::new(9));
+    }
+
+    #[test]
+    fn test_truncate_content() {
+        let mut content = "hello".to_owned();
+        assert!(!truncate_content(&mut content, 5));
+        assert_eq!(content, "hello");
+        assert!(!truncate_content(&mut content, usize::MAX));
+
+        assert!(truncate_content(&mut content, 2));
+        assert_eq!(content, "he");
+    }
+
+    #[test]
+    fn test_truncate_content_multibyte() {
+        // Each character is multiple bytes, so a byte-based cut would land mid-character.
+        let mut content = "東方東方".to_owned();
+        assert!(truncate_content(&mut content, 3));
+        assert_eq!(content, "東方東");
+
+        let mut content = "a🎵b".to_owned();
+        assert!(truncate_content(&mut content, 2));
+        assert_eq!(content, "a🎵");
+    }
+
+    #[test]
+    fn test_cached_message_truncates_content() {
+        use twilight_model::channel::Message;
+        let message = |content: &str| Message {
+            content: content.to_owned(),
+            ..crate::test_support::message(ChannelId(1), MessageId(2), UserId(3))
+        };
+
+        let cached = CachedMessage::new(message("東方東方"), Some(3));
+        assert_eq!(cached.proto.0.get_content(), "東方東");
+        assert!(cached.proto.0.get_content_truncated());
+
+        let cached = CachedMessage::new(message("東方"), Some(3));
+        assert_eq!(cached.proto.0.get_content(), "東方");
+        assert!(!cached.proto.0.get_content_truncated());
+
+        let cached = CachedMessage::new(message(&"a".repeat(5000)), None);
+        assert_eq!(cached.proto.0.get_content().len(), 5000);
+        assert!(!cached.proto.0.get_content_truncated());
+    }
+
+    fn round_trip(proto: CachedMessageProto) -> CachedMessage {
+        let message = CachedMessage { proto: Protobuf(proto) };
+        let mut args = message.proto.to_redis_args();
+        assert_eq!(args.len(), 1);
+        CachedMessage::from_redis_value(&redis::Value::Data(args.remove(0))).unwrap()
+    }
+
+    #[test]
+    fn test_cached_message_avatar_round_trip() {
+        let mut proto = CachedMessageProto::new();
+        let author = proto.mut_author();
+        author.set_id(1);
+        author.set_discriminator(1234);
+        author.set_avatar("a_hash".to_owned());
+
+        let message = round_trip(proto);
+        assert_eq!(message.proto.0.get_author().get_avatar(), "a_hash");
+        assert_eq!(message.author_avatar_url(), "https://cdn.discordapp.com/avatars/1/a_hash.gif");
+    }
+
+    #[test]
+    fn test_cached_message_without_avatar_round_trip() {
+        // Mirrors messages cached before avatars were stored.
+        let mut proto = CachedMessageProto::new();
+        let author = proto.mut_author();
+        author.set_id(1);
+        author.set_discriminator(1234);
+
+        let message = round_trip(proto);
+        assert!(!message.proto.0.get_author().has_avatar());
+        assert_eq!(message.author_avatar_url(), "https://cdn.discordapp.com/embed/avatars/4.png");
+    }
+
+    #[test]
+    fn test_cached_bytes_round_trip() {
+        let blob = CachedBytes(b"{\"hourai\": true}".repeat(50));
+        let payload = compress_payload(&blob.0, CompressionLevel::DEFAULT).unwrap();
+        assert_eq!(payload[0], CompressionMode::Zlib as u8);
+        assert!(payload.len() < blob.0.len());
+        assert_eq!(CachedBytes::decode(&payload).unwrap(), blob);
+
+        let cmd = blob.set_cmd(7, Duration::from_secs(60), CompressionLevel::DEFAULT).unwrap();
+        let expected = redis::cmd("SETEX")
+            .arg(CacheKey(CachePrefix::Bytes, 7_u64))
+            .arg(60)
+            .arg(payload)
+            .get_packed_command();
+        assert_eq!(cmd.get_packed_command(), expected);
+    }
+
+    fn cached_message(channel_id: u64, id: u64) -> CachedMessage {
+        let mut proto = CachedMessageProto::new();
+        proto.set_channel_id(channel_id);
+        proto.set_id(id);
+        CachedMessage { proto: Protobuf(proto) }
+    }
+
+    #[test]
+    fn test_message_flush_batch() {
+        let mut batch = MessageFlushBatch::new(3, Duration::from_secs(60));
+        assert!(batch.take().is_none());
+
+        assert!(!batch.push(cached_message(1, 2)));
+        assert!(!batch.push(cached_message(1, 3)));
+        assert!(batch.push(cached_message(4, 5)));
+        assert_eq!(batch.len(), 3);
+
+        // One atomic pipeline with a SET and EXPIRE for each message.
+        let mut expected = redis::pipe();
+        expected.atomic();
+        for (channel_id, id) in &[(1_u64, 2_u64), (1, 3), (4, 5)] {
+            let key = CacheKey(CachePrefix::Messages, (*channel_id, *id));
+            expected.set(key, cached_message(*channel_id, *id).proto).expire(key, 3600);
+        }
+        let pipeline = batch.take().unwrap();
+        assert_eq!(pipeline.get_packed_pipeline(), expected.get_packed_pipeline());
+
+        assert!(batch.is_empty());
+        assert!(batch.take().is_none());
+    }
+
+    #[test]
+    fn test_message_flush_batch_delay() {
+        let mut batch = MessageFlushBatch::new(100, Duration::from_secs(5));
+        let now = Instant::now();
+        assert!(!batch.should_flush(now + Duration::from_secs(10)));
+
+        batch.push(cached_message(1, 2));
+        assert!(!batch.should_flush(Instant::now()));
+        assert!(batch.should_flush(Instant::now() + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_touch_resets_expiry() {
+        let cmd = CachedMessage::touch_cmd(ChannelId(1), MessageId(2), Duration::from_secs(600));
+        let key = CacheKey(CachePrefix::Messages, (1_u64, 2_u64));
+        let expected = redis::cmd("EXPIRE").arg(key).arg(600).get_packed_command();
+        assert_eq!(cmd.get_packed_command(), expected);
+    }
+}
@@ -0,0 +1,122 @@
+use super::{CacheKey, CachePrefix};
+use crate::prelude::*;
+use redis::aio::ConnectionLike;
+
+/// The outcome of counting an action against a [`RateLimit`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimitStatus {
+    /// Whether the action is allowed.
+    pub allowed: bool,
+    /// How many more actions are allowed in the current window.
+    pub remaining: u64,
+    /// How long until the current window ends and the count is reset.
+    pub reset_after: Duration,
+}
+
+/// A fixed window rate limiter stored in Redis, so that limits are shared across every shard
+/// and process. Unlike in-process cooldowns, limits survive restarts for the rest of the window.
+///
+/// Each key is a pair of 64-bit integers, i.e. a user ID and a per-command identifier. The
+/// first action in a window starts it; every action, allowed or not, counts towards the limit.
+pub struct RateLimit;
+
+impl RateLimit {
+
+    /// Counts an action against `key`, returning whether it is allowed. At most `limit` actions
+    /// are allowed per `window`.
+    pub async fn check<C: ConnectionLike + Send>(
+        connection: &mut C,
+        key: (u64, u64),
+        limit: u64,
+        window: Duration
+    ) -> Result<bool> {
+        Ok(Self::acquire(connection, key, limit, window).await?.allowed)
+    }
+
+    /// Like [`check`], but also reports how many actions remain and when the window resets.
+    ///
+    /// [`check`]: Self::check
+    pub async fn acquire<C: ConnectionLike + Send>(
+        connection: &mut C,
+        key: (u64, u64),
+        limit: u64,
+        window: Duration
+    ) -> Result<RateLimitStatus> {
+        let (count, ttl_ms): (u64, i64) = Self::acquire_cmd(key, window)
+            .query_async(connection)
+            .await?;
+        // A negative TTL means the key expired between commands or has no expiry; either way the
+        // window is effectively over.
+        let reset_after = Duration::from_millis(ttl_ms.max(0) as u64);
+        Ok(RateLimitStatus {
+            allowed: count <= limit,
+            remaining: limit.saturating_sub(count),
+            reset_after,
+        })
+    }
+
+    fn acquire_cmd(key: (u64, u64), window: Duration) -> redis::Pipeline {
+        let key = CacheKey(CachePrefix::RateLimits, key);
+        let mut pipeline = redis::pipe();
+        // SET NX only starts a window if one isn't already running, and INCR keeps the expiry.
+        pipeline
+            .atomic()
+            .cmd("SET").arg(key).arg(0).arg("PX").arg(window.as_millis() as u64).arg("NX")
+            .ignore()
+            .incr(key, 1)
+            .pttl(key);
+        pipeline
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::fake::FakeRedis;
+
+    #[tokio::test]
+    async fn test_rate_limit_hits_limit() {
+        let mut connection = FakeRedis::default();
+        connection.set_pttl(1500);
+        let window = Duration::from_secs(2);
+
+        let status = RateLimit::acquire(&mut connection, (1, 2), 2, window).await.unwrap();
+        assert_eq!(status, RateLimitStatus {
+            allowed: true,
+            remaining: 1,
+            reset_after: Duration::from_millis(1500),
+        });
+        assert!(RateLimit::check(&mut connection, (1, 2), 2, window).await.unwrap());
+        assert!(!RateLimit::check(&mut connection, (1, 2), 2, window).await.unwrap());
+
+        let status = RateLimit::acquire(&mut connection, (1, 2), 2, window).await.unwrap();
+        assert!(!status.allowed);
+        assert_eq!(status.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_expired_ttl() {
+        let mut connection = FakeRedis::default();
+        connection.set_pttl(-2);
+        let status = RateLimit::acquire(&mut connection, (1, 2), 1, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(status.allowed);
+        assert_eq!(status.reset_after, Duration::from_secs(0));
+    }
+
+    #[test]
+    fn test_rate_limit_commands() {
+        let key = CacheKey(CachePrefix::RateLimits, (1_u64, 2_u64));
+        let mut expected = redis::pipe();
+        expected
+            .atomic()
+            .cmd("SET").arg(key).arg(0).arg("PX").arg(2000).arg("NX").ignore()
+            .cmd("INCRBY").arg(key).arg(1)
+            .cmd("PTTL").arg(key);
+        assert_eq!(
+            RateLimit::acquire_cmd((1, 2), Duration::from_secs(2)).get_packed_pipeline(),
+            expected.get_packed_pipeline());
+    }
+}
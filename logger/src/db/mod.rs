@@ -1,7 +1,9 @@
 mod models;
 mod cache;
+mod retry;
 
 pub use self::models::*;
 pub use self::cache::*;
+pub use self::retry::*;
 
 pub type RedisPool = redis::aio::ConnectionManager;
@@ -0,0 +1,83 @@
+use crate::prelude::*;
+use std::future::Future;
+
+/// Runs a Redis operation, reconnecting and retrying it once if it fails due to a connection
+/// level error. Logical errors, like failing to parse a stored protobuf, are returned immediately
+/// without retrying.
+///
+/// The operation is given its own clone of the connection for every attempt. If a reconnect
+/// succeeds, `connection` is replaced with the new connection for future use.
+pub async fn with_retry<C, T, R, RFut, F, Fut>(
+    connection: &mut C,
+    reconnect: R,
+    mut op: F
+) -> Result<T>
+where
+    C: Clone,
+    R: FnOnce() -> RFut,
+    RFut: Future<Output=Result<C>>,
+    F: FnMut(C) -> Fut,
+    Fut: Future<Output=Result<T>>,
+{
+    match op(connection.clone()).await {
+        Err(err) if is_connection_error(&err) => {
+            warn!("Redis connection error, reconnecting: {:?}", err);
+            *connection = reconnect().await?;
+            op(connection.clone()).await
+        },
+        result => result,
+    }
+}
+
+fn is_connection_error(err: &anyhow::Error) -> bool {
+    let redis_err = match err.downcast_ref::<Error>() {
+        Some(Error::Redis(redis_err)) => Some(redis_err),
+        _ => err.downcast_ref::<redis::RedisError>(),
+    };
+    match redis_err {
+        Some(err) => err.is_io_error() ||
+                     err.is_connection_dropped() ||
+                     err.is_connection_refusal() ||
+                     err.is_timeout(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection_reset() -> redis::RedisError {
+        std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset").into()
+    }
+
+    #[tokio::test]
+    async fn test_retries_connection_errors_once() {
+        let mut connection = 0_u32;
+        let result = with_retry(
+            &mut connection,
+            || async { Ok(1) },
+            |c| async move {
+                if c == 0 {
+                    Err(connection_reset().into())
+                } else {
+                    Ok(c)
+                }
+            }).await;
+
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(connection, 1);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_logical_errors() {
+        let mut connection = 0_u32;
+        let result: Result<u32> = with_retry(
+            &mut connection,
+            || async { Err(anyhow::anyhow!("should not reconnect")) },
+            |_| async { Err(anyhow::anyhow!("malformed protobuf")) }).await;
+
+        assert_eq!(result.unwrap_err().to_string(), "malformed protobuf");
+        assert_eq!(connection, 0);
+    }
+}
@@ -80,6 +80,12 @@ impl Initializer {
         sql
     }
 
+    /// Creates a new Redis client, for dedicated connections like pub/sub subscriptions.
+    pub fn redis_client(&self) -> redis::Client {
+        let init = self.0.lock().unwrap();
+        redis::Client::open(init.config.redis.as_ref()).expect("Failed to create Redis client")
+    }
+
     pub async fn redis(&self) -> RedisPool {
         let mut init = self.0.lock().unwrap();
 
@@ -87,7 +93,7 @@ impl Initializer {
             return redis;
         }
 
-        let client = redis::Client::open(self.config().redis.as_ref())
+        let client = redis::Client::open(init.config.redis.as_ref())
                                    .expect("Failed to create Redis client");
         let redis = redis::aio::ConnectionManager::new(client)
                           .await
@@ -5,6 +5,10 @@ pub mod db;
 pub mod error;
 pub mod init;
 pub mod prelude;
+pub mod snowflake;
+
+#[cfg(test)]
+mod test_support;
 
 // Include the auto-generated protos as a module
 pub mod proto {
@@ -0,0 +1,74 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use twilight_model::id::*;
+
+/// The first second of 2015, in milliseconds since the Unix epoch. All Discord snowflakes encode
+/// their creation time relative to this.
+pub const DISCORD_EPOCH_MS: u64 = 1420070400000;
+
+/// Gets the time a Discord snowflake ID was created.
+pub fn created_at(id: u64) -> SystemTime {
+    created_at_with_epoch(id, DISCORD_EPOCH_MS)
+}
+
+/// Gets the time a snowflake ID was created, for snowflakes whose upper 42 bits count
+/// milliseconds since `epoch_ms`, itself in milliseconds since the Unix epoch.
+pub fn created_at_with_epoch(id: u64, epoch_ms: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis((id >> 22) + epoch_ms)
+}
+
+pub fn user_created_at(id: UserId) -> SystemTime {
+    created_at(id.0)
+}
+
+pub fn guild_created_at(id: GuildId) -> SystemTime {
+    created_at(id.0)
+}
+
+pub fn channel_created_at(id: ChannelId) -> SystemTime {
+    created_at(id.0)
+}
+
+pub fn message_created_at(id: MessageId) -> SystemTime {
+    created_at(id.0)
+}
+
+pub fn role_created_at(id: RoleId) -> SystemTime {
+    created_at(id.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+    }
+
+    #[test]
+    fn test_created_at() {
+        assert_eq!(millis(created_at(0)), DISCORD_EPOCH_MS);
+        // 2016-04-30T11:18:25.796Z
+        assert_eq!(millis(created_at(175928847299117063)), 1462015105796);
+        // The lower 22 bits carry no timing information.
+        assert_eq!(created_at(175928847299117063), created_at(175928847299117063 & !0x3FFFFF));
+    }
+
+    #[test]
+    fn test_created_at_with_epoch() {
+        assert_eq!(millis(created_at_with_epoch(0, 0)), 0);
+        assert_eq!(millis(created_at_with_epoch(1000 << 22, 0)), 1000);
+        assert_eq!(millis(created_at_with_epoch((1000 << 22) | 0x3FFFFF, 500)), 1500);
+        assert_eq!(created_at_with_epoch(175928847299117063, DISCORD_EPOCH_MS),
+                   created_at(175928847299117063));
+    }
+
+    #[test]
+    fn test_typed_created_at() {
+        let id = 175928847299117063;
+        assert_eq!(user_created_at(UserId(id)), created_at(id));
+        assert_eq!(guild_created_at(GuildId(id)), created_at(id));
+        assert_eq!(channel_created_at(ChannelId(id)), created_at(id));
+        assert_eq!(message_created_at(MessageId(id)), created_at(id));
+        assert_eq!(role_created_at(RoleId(id)), created_at(id));
+    }
+}
@@ -0,0 +1,56 @@
+//! Fixtures shared by the tests of multiple modules.
+
+use twilight_model::{
+    channel::{
+        message::{MessageFlags, MessageType},
+        Message,
+    },
+    id::{ChannelId, GuildId, MessageId, UserId},
+    user::User,
+};
+
+/// Builds a plain text message sent in guild 1. Tests that need other field values can use
+/// struct update syntax, i.e. `Message { content, ..message(channel_id, id, author_id) }`.
+pub fn message(channel_id: ChannelId, id: MessageId, author_id: UserId) -> Message {
+    Message {
+        activity: None,
+        application: None,
+        attachments: Vec::new(),
+        author: User {
+            avatar: None,
+            bot: false,
+            discriminator: "0001".to_owned(),
+            email: None,
+            flags: None,
+            id: author_id,
+            locale: None,
+            mfa_enabled: None,
+            name: "test".to_owned(),
+            premium_type: None,
+            public_flags: None,
+            system: None,
+            verified: None,
+        },
+        channel_id,
+        content: "ping".to_owned(),
+        edited_timestamp: None,
+        embeds: Vec::new(),
+        flags: Some(MessageFlags::empty()),
+        guild_id: Some(GuildId(1)),
+        id,
+        kind: MessageType::Regular,
+        member: None,
+        mention_channels: Vec::new(),
+        mention_everyone: false,
+        mention_roles: Vec::new(),
+        mentions: Vec::new(),
+        pinned: false,
+        reactions: Vec::new(),
+        reference: None,
+        stickers: Vec::new(),
+        referenced_message: None,
+        timestamp: String::new(),
+        tts: false,
+        webhook_id: None,
+    }
+}